@@ -0,0 +1,67 @@
+// Benchmarks of the steps of the commitment protocol, measured separately so that the cost of the
+// hash (`commit` and `validate`) can be told apart from the cost of sampling the published value
+// and nonce (`Commitment::new`). This tells how much switching to a faster hash could save. The
+// comparison example's --estimate-capacity reads the measurements of `commitment/new`,
+// `commitment/commit`, and `commitment/validate` to calibrate its estimate.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dprio::*;
+
+// The corpus size used in the comparison example with the default parameters.
+const N: u64 = 162_564_322;
+
+// The state shared by the benchmarks: a commitment, its closed commitment, and its published
+// value and nonce.
+fn setup() -> (Commitment, ClosedCommitment, (u64, Nonce)) {
+    let commitment = Commitment::new(N).unwrap();
+    let closed_commitment = commitment.commit();
+    let published_value = commitment.publish();
+    (commitment, closed_commitment, published_value)
+}
+
+// Sampling the published value and the nonce (no hashing).
+fn bench_new(c: &mut Criterion) {
+    c.bench_function("commitment/new", |b| {
+        b.iter(|| Commitment::new(black_box(N)).unwrap())
+    });
+}
+
+// Hashing the personalization, nonce, and published value.
+fn bench_commit(c: &mut Criterion) {
+    let (commitment, _, _) = setup();
+    c.bench_function("commitment/commit", |b| {
+        b.iter(|| black_box(&commitment).commit())
+    });
+}
+
+// Checking the published value's range, hashing it again, and comparing the hashes.
+fn bench_validate(c: &mut Criterion) {
+    let (_, closed_commitment, (p, nonce)) = setup();
+    c.bench_function("commitment/validate", |b| {
+        b.iter(|| {
+            closed_commitment
+                .validate(black_box(p), black_box(&nonce))
+                .unwrap()
+        })
+    });
+}
+
+// All of the above, as one participant does it in a round.
+fn bench_round_trip(c: &mut Criterion) {
+    c.bench_function("commitment/round_trip", |b| {
+        b.iter(|| {
+            let commitment = Commitment::new(black_box(N)).unwrap();
+            let closed_commitment = commitment.commit();
+            let (p, nonce) = commitment.publish();
+            closed_commitment.validate(p, &nonce).unwrap()
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_new,
+    bench_commit,
+    bench_validate,
+    bench_round_trip
+);
+criterion_main!(benches);
@@ -1,70 +1,179 @@
 extern crate clap;
 extern crate prio;
 
-use clap::{Arg, ArgAction, Command};
+use clap::{value_parser, Arg, ArgAction, Command};
 use prio::client::*;
 use prio::encrypt::*;
 use prio::field::*;
 use prio::server::*;
-use rand::distributions::Binomial;
-use rand::Rng;
+use rand::distributions::{Binomial, Distribution, Uniform};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use dprio::*;
 
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
-use std::time::Instant;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// The probability that a simulated client's actual value is 1 (rather than 0).
+const CLIENT_VALUE_PROBABILITY: f64 = 0.5_f64;
 
 struct ClientState {
     client: Client<Field32>,
     data: Vec<u32>,
     noise: Option<Vec<u32>>,
-    actual_value: usize,
+    key_fingerprint: KeyFingerprint,
+}
+
+// A short hash of the pair of server public keys a client encrypted its shares to, which the
+// client attaches to its submission. Shares encrypted to the wrong keys fail verification with no
+// indication of why, so the servers compare the fingerprint against that of their own keys first.
+// prio doesn't expose the bytes of a public key, so this hashes their Debug representation, which
+// includes them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct KeyFingerprint([u8; 8]);
+
+impl KeyFingerprint {
+    fn new(public_key1: &PublicKey, public_key2: &PublicKey) -> KeyFingerprint {
+        let mut hasher = Sha256::new();
+        for public_key in [public_key1, public_key2].iter() {
+            let encoded_key = format!("{:?}", public_key);
+            hasher.update((encoded_key.len() as u64).to_be_bytes());
+            hasher.update(encoded_key.as_bytes());
+        }
+        let mut fingerprint = [0; 8];
+        fingerprint.copy_from_slice(&hasher.finalize()[..8]);
+        KeyFingerprint(fingerprint)
+    }
+}
+
+// Checks that every submission was made with the servers' public keys, before any of them is
+// verified.
+fn check_key_fingerprints(
+    expected: KeyFingerprint,
+    key_fingerprints: &[KeyFingerprint],
+) -> Result<(), DprioError> {
+    if key_fingerprints
+        .iter()
+        .all(|key_fingerprint| *key_fingerprint == expected)
+    {
+        Ok(())
+    } else {
+        Err(DprioError::PublicKeyMismatch)
+    }
+}
+
+// Tracks the ground truth of a simulated study (the noise-free sum of the client values), to report
+// alongside the noised result. This is for evaluation only: in a real deployment nobody knows the
+// clients' values, and the protocol itself never sees the harness.
+#[derive(Clone, Debug, Default)]
+struct EvaluationHarness {
+    true_sum: usize,
+    n_clients: usize,
+}
+
+impl EvaluationHarness {
+    fn new() -> EvaluationHarness {
+        EvaluationHarness::default()
+    }
+
+    // Records the actual value of a client that takes part in the study.
+    fn record_client(&mut self, actual_value: usize) {
+        self.true_sum += actual_value;
+        self.n_clients += 1;
+    }
+
+    // The sum of the recorded values, before any noise is added.
+    fn true_sum(&self) -> usize {
+        self.true_sum
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn n_clients(&self) -> usize {
+        self.n_clients
+    }
+}
+
+// A source of noise for the simulated clients. This is boxed so that experimenters can plug in any
+// `rand::distributions::Distribution<f64>` (see `distribution_noise`).
+// WARNING: only the Laplace noise of `laplace_noise` carries a differential privacy guarantee.
+type NoiseDistribution = Box<dyn Fn(&mut StdRng) -> f64>;
+
+fn laplace_noise(epsilon: f64) -> NoiseDistribution {
+    Box::new(move |rng| {
+        laplace::noise_with_rng(rng, 1.0_f64, epsilon).expect("parameters should be fine") as f64
+    })
+}
+
+// Draws the clients' noise from an arbitrary distribution, e.g. for ablation studies (see
+// `ablation_errors`). The samples are rounded to the nearest integer. This gives NO privacy
+// guarantee.
+fn distribution_noise<D: Distribution<f64> + 'static>(dist: D) -> NoiseDistribution {
+    Box::new(move |rng| laplace::noise_from_distribution(&dist, rng))
+}
+
+// Supplies the given noise values in order, cycling through them, in place of sampled noise. This
+// is for golden-output tests of the pipeline from noise to bits to shares to the recovered sum, so
+// the rng is unused.
+#[cfg(test)]
+fn fixed_noise(values: Vec<i64>) -> NoiseDistribution {
+    assert!(!values.is_empty());
+    let next_index = std::cell::Cell::new(0);
+    Box::new(move |_| {
+        let index = next_index.get();
+        next_index.set((index + 1) % values.len());
+        values[index] as f64
+    })
 }
 
 impl ClientState {
     fn new(
         dimension: usize,
-        shift_value: isize,
-        epsilon: f64,
-        generate_noise: bool,
+        shift_value: u64,
+        actual_value: usize,
+        noise_distribution: Option<&NoiseDistribution>,
         public_key1: &PublicKey,
         public_key2: &PublicKey,
+        rng: &mut StdRng,
     ) -> ClientState {
-        assert!(dimension > 0);
-        assert!(shift_value >= 0);
-        let mut data = Vec::with_capacity(dimension);
-        // The study is a count, so each client will send either 0 or 1. For this simulation, the
-        // probability of sending 1 is 0.5. Since we have to account for negative noise, we also add
-        // 2^(dimension - 1) (shift_value) to the value being sent.
-        let mut rng = rand::thread_rng();
-        let actual_value = rng.sample(Binomial::new(1, 0.5)) as usize;
-        let value = shift_value as usize + actual_value;
-        for i in 0..dimension {
-            let ith_bit = (value >> i) & 1;
-            data.push(ith_bit as u32);
-        }
-        assert!(data.len() == dimension);
-        let noise = if generate_noise {
-            let mut noise = Vec::with_capacity(dimension);
-            let noise_value = laplace::noise(1.0_f64, epsilon).expect("parameters should be fine")
-                as isize
-                + shift_value;
-            assert!(noise_value >= 0);
-            for i in 0..dimension {
-                let ith_bit = (noise_value >> i) & 1;
-                noise.push(ith_bit as u32);
-            }
-            assert!(noise.len() == dimension);
-            Some(noise)
-        } else {
-            None
-        };
+        let noise_value =
+            noise_distribution.map(|noise_distribution| noise_distribution(rng).round() as i64);
+        ClientState::with_values(
+            dimension,
+            shift_value,
+            actual_value,
+            noise_value,
+            public_key1,
+            public_key2,
+        )
+    }
+
+    fn with_values(
+        dimension: usize,
+        shift_value: u64,
+        actual_value: usize,
+        noise_value: Option<i64>,
+        public_key1: &PublicKey,
+        public_key2: &PublicKey,
+    ) -> ClientState {
+        // Since we have to account for negative noise, we also add 2^(dimension - 1)
+        // (shift_value) to the value being sent.
+        let data = laplace::encode_bits(actual_value as isize, shift_value as isize, dimension)
+            .expect("the value should fit in the dimension");
+        let noise = noise_value.map(|noise_value| {
+            laplace::encode_bits(noise_value as isize, shift_value as isize, dimension)
+                .expect("the noise should fit in the dimension")
+        });
 
         ClientState {
             client: Client::new(dimension, public_key1.clone(), public_key2.clone()).unwrap(),
             data,
             noise,
-            actual_value,
+            key_fingerprint: KeyFingerprint::new(public_key1, public_key2),
         }
     }
 
@@ -92,20 +201,20 @@ impl ClientState {
 
 struct ServerState {
     server: Server<Field32>,
-    public_key: PublicKey,
+    event_sink: Box<dyn events::EventSink>,
 }
 
 impl ServerState {
     fn new(dimension: usize, is_first_server: bool, private_key: PrivateKey) -> ServerState {
-        let public_key = PublicKey::from(&private_key);
         ServerState {
             server: Server::new(dimension, is_first_server, private_key).unwrap(),
-            public_key,
+            event_sink: Box::new(events::NoopSink),
         }
     }
 
-    fn get_public_key(&self) -> &PublicKey {
-        &self.public_key
+    fn with_event_sink(mut self, event_sink: Box<dyn events::EventSink>) -> ServerState {
+        self.event_sink = event_sink;
+        self
     }
 
     fn generate_verifications(
@@ -134,9 +243,15 @@ impl ServerState {
             .zip(server1_verifications.iter())
             .zip(server2_verifications.iter())
         {
-            self.server
+            let is_valid = self
+                .server
                 .aggregate_by_sum(share, server1_verification, server2_verification)
                 .unwrap();
+            self.event_sink.record(if is_valid {
+                events::Event::ShareAccepted
+            } else {
+                events::Event::ShareRejected
+            });
         }
     }
 
@@ -150,14 +265,264 @@ impl ServerState {
     }
 }
 
+// Identifies one of several independent studies aggregated by the same servers.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct StudyId(u64);
+
+// The id of the study in `run_servers`.
+const SIMULATION_STUDY: StudyId = StudyId(0);
+
+// A server aggregating several concurrent studies, each with its own dimension, key, and
+// aggregation state, so that a single server doesn't have to be run per study. Shares are routed
+// to a study's state by its id.
+struct MultiStudyServer {
+    is_first_server: bool,
+    studies: HashMap<StudyId, ServerState>,
+}
+
+impl MultiStudyServer {
+    fn new(is_first_server: bool) -> MultiStudyServer {
+        MultiStudyServer {
+            is_first_server,
+            studies: HashMap::new(),
+        }
+    }
+
+    // Adds a study, whose events are reported to event_sink (e.g. to keep per-study metrics).
+    fn add_study(
+        &mut self,
+        study_id: StudyId,
+        dimension: usize,
+        private_key: PrivateKey,
+        event_sink: Box<dyn events::EventSink>,
+    ) -> Result<(), DprioError> {
+        if self.studies.contains_key(&study_id) {
+            return Err(DprioError::DuplicateStudy);
+        }
+        self.studies.insert(
+            study_id,
+            ServerState::new(dimension, self.is_first_server, private_key)
+                .with_event_sink(event_sink),
+        );
+        Ok(())
+    }
+
+    fn study(&mut self, study_id: StudyId) -> Result<&mut ServerState, DprioError> {
+        self.studies
+            .get_mut(&study_id)
+            .ok_or(DprioError::UnknownStudy)
+    }
+}
+
 #[derive(Debug)]
+enum DprioError {
+    // The linked prio version doesn't encode or aggregate shares the way this code expects.
+    IncompatiblePrioVersion,
+    // The servers arrived at different totals after exchanging their total shares.
+    TotalMismatch,
+    // A share was routed to a study the server doesn't aggregate.
+    UnknownStudy,
+    // A study was added to a server that already aggregates a study with the same id.
+    DuplicateStudy,
+    // The sum of a study's contributions could exceed what the field can represent.
+    StudyTooLarge,
+    // A client encrypted its shares to different public keys than the servers'.
+    PublicKeyMismatch,
+}
+
+// The largest sum a field can represent before wrapping around its modulus (the modulus minus
+// one).
+fn max_representable_sum<F>() -> u128
+where
+    F: FieldElement + From<u32>,
+    u32: From<F>,
+{
+    u32::from(F::from(0) - F::from(1)) as u128
+}
+
+// Checks that the sum of n_contributions contributions of `dimension` bits each can't wrap around
+// the modulus of F, as the aggregate couldn't be recovered otherwise.
+fn check_sum_fits<F>(n_contributions: usize, dimension: usize) -> Result<(), DprioError>
+where
+    F: FieldElement + From<u32>,
+    u32: From<F>,
+{
+    let max_contribution = 1u128
+        .checked_shl(dimension as u32)
+        .ok_or(DprioError::StudyTooLarge)?
+        - 1;
+    match max_contribution.checked_mul(n_contributions as u128) {
+        Some(max_sum) if max_sum <= max_representable_sum::<F>() => Ok(()),
+        _ => Err(DprioError::StudyTooLarge),
+    }
+}
+
+// The modulus of Field32.
+fn field_modulus() -> i128 {
+    u32::from(Field32::from(0) - Field32::from(1)) as i128 + 1
+}
+
+// A sum of field elements along with the integer it would be without the modular reduction. The
+// field arithmetic wraps around the modulus, so code that treats a field element as an integer
+// (e.g. compares it with another, or converts it with `to_integer`) is only correct if the sum
+// never wrapped. In debug builds, this checks that the field element and the integer stay
+// congruent, and that the sum didn't wrap when it's converted to an integer.
+#[derive(Clone, Copy, Debug)]
+struct CheckedSum {
+    value: Field32,
+    unwrapped: i128,
+}
+
+impl CheckedSum {
+    fn from_integer(unwrapped: i128) -> CheckedSum {
+        CheckedSum {
+            value: Field32::from(unwrapped.rem_euclid(field_modulus()) as u32),
+            unwrapped,
+        }
+    }
+
+    // For a field element known to be the sum of non-negative integers whose total is less than
+    // the modulus, so that it never wrapped.
+    fn from_field(value: Field32) -> CheckedSum {
+        CheckedSum {
+            value,
+            unwrapped: u32::from(value) as i128,
+        }
+    }
+
+    fn sub(self, other: CheckedSum) -> CheckedSum {
+        let difference = CheckedSum {
+            value: self.value - other.value,
+            unwrapped: self.unwrapped - other.unwrapped,
+        };
+        difference.debug_assert_congruent();
+        difference
+    }
+
+    fn debug_assert_congruent(&self) {
+        debug_assert_eq!(
+            self.unwrapped.rem_euclid(field_modulus()),
+            u32::from(self.value) as i128,
+            "the field element isn't congruent to the integer sum"
+        );
+    }
+
+    // The field element, which wraps around the modulus.
+    fn to_field(self) -> Field32 {
+        self.value
+    }
+
+    // The sum as an integer, for sums that can't legitimately be negative or exceed the field.
+    fn to_integer(self) -> usize {
+        debug_assert_eq!(
+            self.unwrapped,
+            u32::from(self.value) as i128,
+            "the field arithmetic wrapped around the modulus"
+        );
+        u32::from(self.value) as usize
+    }
+}
+
+// Each server adds the total share it received from the other server to its own, and the servers
+// check that they arrived at the same total (which they can do by publishing it). A mismatch means
+// a total share was corrupted (or mishandled) on its way to one of the servers.
+fn combine_total_shares(
+    server1: &mut ServerState,
+    server2: &mut ServerState,
+    share_from_server1: &Field32,
+    share_from_server2: &Field32,
+) -> Result<Field32, DprioError> {
+    let server1_total = *server1.add_and_get_total_sum(share_from_server2);
+    let server2_total = *server2.add_and_get_total_sum(share_from_server1);
+    if server1_total != server2_total {
+        return Err(DprioError::TotalMismatch);
+    }
+    Ok(server1_total)
+}
+
+// The share format can change between prio versions, which would silently break the sums this
+// code computes. To catch this early, this encodes a known value with the linked version of prio
+// and checks that the servers recover it.
+fn check_prio_compatibility(
+    priv_key1: &PrivateKey,
+    priv_key2: &PrivateKey,
+) -> Result<(), DprioError> {
+    // 5 is 101 in binary, so this checks both the bit order and that zero bits are handled.
+    let test_value = 5;
+    let test_data = [1, 0, 1];
+    let mut client = Client::new(
+        test_data.len(),
+        PublicKey::from(priv_key1),
+        PublicKey::from(priv_key2),
+    )
+    .map_err(|_| DprioError::IncompatiblePrioVersion)?;
+    let data = test_data
+        .iter()
+        .map(|x| Field32::from(*x))
+        .collect::<Vec<Field32>>();
+    let (share1, share2) = client
+        .encode_simple(&data)
+        .map_err(|_| DprioError::IncompatiblePrioVersion)?;
+    let mut server1 = ServerState::new(test_data.len(), true, priv_key1.clone());
+    let mut server2 = ServerState::new(test_data.len(), false, priv_key2.clone());
+    let eval_at = Field32::from(12313);
+    let server1_verification = server1
+        .server
+        .generate_verification_message(eval_at, &share1)
+        .ok_or(DprioError::IncompatiblePrioVersion)?;
+    let server2_verification = server2
+        .server
+        .generate_verification_message(eval_at, &share2)
+        .ok_or(DprioError::IncompatiblePrioVersion)?;
+    for (server, share) in [(&mut server1, &share1), (&mut server2, &share2)] {
+        let valid = server
+            .server
+            .aggregate_by_sum(share, &server1_verification, &server2_verification)
+            .map_err(|_| DprioError::IncompatiblePrioVersion)?;
+        if !valid {
+            return Err(DprioError::IncompatiblePrioVersion);
+        }
+    }
+    if *server1.add_and_get_total_sum(server2.total_sum()) != Field32::from(test_value) {
+        return Err(DprioError::IncompatiblePrioVersion);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 struct Results {
     dprio: bool,
     dimension: usize,
     calculated_sum: usize,
     actual_sum: usize,
-    client_elapsed: u128,
-    server_elapsed: u128,
+    // The number of clients that responded, which is the (public) denominator of the mean.
+    #[serde(default)]
+    n_responders: usize,
+    // How many times larger dimension is than plain prio's (see `dimension_inflation`), which
+    // dominates the extra client cost of dprio.
+    #[serde(default)]
+    dimension_inflation: f64,
+    // All times are in microseconds, as some client operations take well under a millisecond.
+    // Total client time (setup_elapsed_us + encode_elapsed_us).
+    client_elapsed_us: u128,
+    // Time spent creating the clients (sampling noise and setting up the prio clients).
+    setup_elapsed_us: u128,
+    // Time spent encoding the clients' data and noise into shares.
+    encode_elapsed_us: u128,
+    // Total server time (commitment_elapsed_us + verification_elapsed_us + aggregation_elapsed_us).
+    server_elapsed_us: u128,
+    // Time spent selecting the noise with the commitment protocol (0 without dprio).
+    commitment_elapsed_us: u128,
+    // Time spent generating the verification messages.
+    verification_elapsed_us: u128,
+    // Time spent aggregating the verified shares and computing the total sum.
+    aggregation_elapsed_us: u128,
+    // Peak resident set size during the simulation, in kB (0 if not measured).
+    peak_memory_kb: u64,
+    // The number of shares the servers rejected because they didn't verify (as reported to their
+    // event sinks). The simulated clients are honest, so this should always be 0.
+    #[serde(default)]
+    rejected_shares: usize,
 }
 
 impl fmt::Display for Results {
@@ -165,24 +530,31 @@ impl fmt::Display for Results {
         let flavor = if self.dprio { "dprio" } else { "prio" };
         write!(
             f,
-            "{},{},{},{},{},{},",
+            "{},{},{},{},{},{},{},{},{},{},{},{},",
             flavor,
             self.dimension,
             self.calculated_sum,
             self.actual_sum,
-            self.client_elapsed,
-            self.server_elapsed
+            self.client_elapsed_us,
+            self.setup_elapsed_us,
+            self.encode_elapsed_us,
+            self.server_elapsed_us,
+            self.commitment_elapsed_us,
+            self.verification_elapsed_us,
+            self.aggregation_elapsed_us,
+            self.peak_memory_kb
         )
     }
 }
 
+// Returns the average client time and server time (in microseconds) and the average error.
 fn average_results(results: &[Results]) -> (f64, f64, f64) {
     let mut client_elapsed_sum = 0;
     let mut server_elapsed_sum = 0;
     let mut error_sum = 0;
     for result in results.iter() {
-        client_elapsed_sum += result.client_elapsed;
-        server_elapsed_sum += result.server_elapsed;
+        client_elapsed_sum += result.client_elapsed_us;
+        server_elapsed_sum += result.server_elapsed_us;
         error_sum += result.actual_sum.abs_diff(result.calculated_sum);
     }
     (
@@ -192,6 +564,151 @@ fn average_results(results: &[Results]) -> (f64, f64, f64) {
     )
 }
 
+// The standard normal quantile for a two-sided 95% confidence interval.
+const Z_95: f64 = 1.959964_f64;
+
+// Approximates the quantile of Student's t distribution with df degrees of freedom matching Z_95,
+// with the Cornish-Fisher expansion around the normal quantile. This is within 1% of the exact
+// value for df >= 3, but underestimates it (making intervals too narrow) for fewer degrees of
+// freedom, e.g. 9.7 rather than 12.7 for df = 1.
+fn t_quantile_95(df: f64) -> f64 {
+    let z = Z_95;
+    z + (z.powi(3) + z) / (4.0_f64 * df)
+        + (5.0_f64 * z.powi(5) + 16.0_f64 * z.powi(3) + 3.0_f64 * z) / (96.0_f64 * df.powi(2))
+        + (3.0_f64 * z.powi(7) + 19.0_f64 * z.powi(5) + 17.0_f64 * z.powi(3) - 15.0_f64 * z)
+            / (384.0_f64 * df.powi(3))
+}
+
+// Returns the mean and the (unbiased) sample variance of the server times of results.
+fn server_elapsed_mean_and_variance(results: &[Results]) -> (f64, f64) {
+    let n = results.len() as f64;
+    let mean = results
+        .iter()
+        .map(|result| result.server_elapsed_us as f64)
+        .sum::<f64>()
+        / n;
+    let variance = results
+        .iter()
+        .map(|result| (result.server_elapsed_us as f64 - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0_f64);
+    (mean, variance)
+}
+
+// Returns a 95% confidence interval on the server overhead of dprio over prio, in percent (as in
+// `server_table`), from Welch's t-test on the per-trial server times. The interval is on the
+// difference of the mean times, relative to the mean prio time, whose own uncertainty is ignored.
+// An interval that contains 0 means the trials can't tell the overhead from noise. With fewer
+// than 2 trials of either, the variance can't be estimated, so the interval is unbounded.
+fn overhead_confidence_interval(prio: &[Results], dprio: &[Results]) -> (f64, f64) {
+    if prio.len() < 2 || dprio.len() < 2 {
+        return (f64::NEG_INFINITY, f64::INFINITY);
+    }
+    let (prio_mean, prio_variance) = server_elapsed_mean_and_variance(prio);
+    let (dprio_mean, dprio_variance) = server_elapsed_mean_and_variance(dprio);
+    let prio_squared_error = prio_variance / prio.len() as f64;
+    let dprio_squared_error = dprio_variance / dprio.len() as f64;
+    let standard_error = (prio_squared_error + dprio_squared_error).sqrt();
+    let overhead = 100.0_f64 * (dprio_mean - prio_mean) / prio_mean;
+    if standard_error == 0.0_f64 {
+        return (overhead, overhead);
+    }
+    // The Welch-Satterthwaite degrees of freedom.
+    let df = standard_error.powi(4)
+        / (prio_squared_error.powi(2) / (prio.len() - 1) as f64
+            + dprio_squared_error.powi(2) / (dprio.len() - 1) as f64);
+    let half_width = 100.0_f64 * t_quantile_95(df) * standard_error / prio_mean;
+    (overhead - half_width, overhead + half_width)
+}
+
+// Splits the error of a run, measured against the quantity the study is estimating (the expected
+// count over the client population, `clients * CLIENT_VALUE_PROBABILITY`), into the part
+// introduced by the noise (`calculated_sum - actual_sum`, see `signed_error`) and the part due to
+// sampling a finite number of clients (`actual_sum - clients * CLIENT_VALUE_PROBABILITY`).
+// Returns the magnitudes of the two components as (noise, sampling).
+// The noise component is read off the result, so this takes the simulation's Params rather than
+// a description of the noise. The sampling component, however, is only meaningful for clients
+// whose values are drawn as in `sample_client_values`, i.e. are 1 with probability
+// CLIENT_VALUE_PROBABILITY.
+// Note that the noise component doesn't depend on the number of clients whereas the sampling
+// component grows with its square root, so small populations tend to be noise-dominated and
+// large populations tend to be sampling-dominated.
+fn error_decomposition(result: &Results, params: &Params) -> (f64, f64) {
+    let noise = signed_error(result) as f64;
+    let sampling = result.actual_sum as f64 - params.clients as f64 * CLIENT_VALUE_PROBABILITY;
+    (noise.abs(), sampling.abs())
+}
+
+// Resets the peak resident set size of this process, so that a subsequent call to
+// peak_memory_kb reports the peak from this point on. This only does anything on Linux with the
+// "memory-stats" feature enabled.
+#[cfg(all(feature = "memory-stats", target_os = "linux"))]
+fn reset_peak_memory() {
+    // Writing 5 to clear_refs resets the peak RSS (VmHWM) to the current RSS. If this isn't
+    // permitted, the peak covers the whole lifetime of the process instead.
+    let _ = std::fs::write("/proc/self/clear_refs", "5");
+}
+
+#[cfg(not(all(feature = "memory-stats", target_os = "linux")))]
+fn reset_peak_memory() {}
+
+// Returns the peak resident set size of this process in kB, or 0 if it isn't available.
+#[cfg(all(feature = "memory-stats", target_os = "linux"))]
+fn peak_memory_kb() -> u64 {
+    let status = match std::fs::read_to_string("/proc/self/status") {
+        Ok(status) => status,
+        Err(_) => return 0,
+    };
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(not(all(feature = "memory-stats", target_os = "linux")))]
+fn peak_memory_kb() -> u64 {
+    0
+}
+
+// A histogram of the errors (calculated_sum - actual_sum) of a set of results, to show the shape
+// of the error distribution (which should look like the sum of the selected noise values, i.e.
+// roughly Laplace-shaped) rather than just its average. Bin i counts the errors in
+// [i * bin_width, (i + 1) * bin_width).
+struct ErrorHistogram {
+    bin_width: i64,
+    counts: BTreeMap<i64, usize>,
+}
+
+impl ErrorHistogram {
+    fn new(results: &[Results], bin_width: i64) -> ErrorHistogram {
+        assert!(bin_width > 0);
+        let mut counts = BTreeMap::new();
+        for result in results {
+            *counts
+                .entry(signed_error(result).div_euclid(bin_width))
+                .or_insert(0) += 1;
+        }
+        ErrorHistogram { bin_width, counts }
+    }
+}
+
+impl fmt::Display for ErrorHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (bin, count) in &self.counts {
+            writeln!(
+                f,
+                "[{}, {}): {}",
+                bin * self.bin_width,
+                (bin + 1) * self.bin_width,
+                count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 struct Params {
     epsilon: f64,
     clients: usize,
@@ -216,14 +733,119 @@ impl fmt::Display for Params {
     }
 }
 
+const TABLE_TITLES: [&str; 3] = [
+    "Table 3: Average simulation of server processing time with varying epsilon",
+    "Table 4: Average simulation of server processing time with varying client population size",
+    "Table 5: Average simulation of server processing time with varying client noises elected",
+];
+
 fn main() {
     let matches = Command::new("comparison")
         .version("0.1")
         .author("Dana Keeler <dkeeler@mozilla.com>")
         .about("Compare simulated prio and dprio")
         .arg(Arg::new("full").short('f').action(ArgAction::SetTrue))
+        .arg(
+            Arg::new("reuse-client-shares")
+                .short('r')
+                .long("reuse-client-shares")
+                .help("Encode client shares once per batch and reuse them in each trial")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("error-histograms")
+                .short('e')
+                .long("error-histograms")
+                .help("Print a histogram of the dprio errors across trials for each set of params")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("means")
+                .short('m')
+                .long("means")
+                .help("Print the noised mean of the client values of a dprio trial, with its 95% confidence interval, for each set of params")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("seed")
+                .short('s')
+                .long("seed")
+                .value_name("N")
+                .help("Seed the simulations to reproduce a previous run (random if not given)")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("only-trial")
+                .long("only-trial")
+                .value_name("I")
+                .help("Run only trial I (from 0) of each set of params, e.g. with the --seed of a run to reproduce one of its trials")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("noise-only")
+                .long("noise-only")
+                .help("Only measure noise selection, with clients that submit noise but no data")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("ablation")
+                .long("ablation")
+                .help("Compare the dprio error with Laplace noise to that with uniform noise of the same variance (which isn't private)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("estimate-capacity")
+                .long("estimate-capacity")
+                .value_name("N")
+                .help("Estimate the CPU time a server needs for a study of N clients, calibrated with a dprio run")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Save the results of the simulations as JSON (see the render subcommand)"),
+        )
+        .subcommand(
+            Command::new("render")
+                .about("Print the tables for results previously saved with --output")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .long("input")
+                        .value_name("FILE")
+                        .required(true),
+                ),
+        )
         .get_matches();
+    if let Some(render_matches) = matches.subcommand_matches("render") {
+        let input = render_matches
+            .get_one::<String>("input")
+            .expect("input is required");
+        let saved_results = std::fs::read_to_string(input).expect("couldn't read input");
+        let results_batches: Vec<Vec<BatchResults>> =
+            serde_json::from_str(&saved_results).expect("couldn't parse input");
+        for (title, results_batch) in TABLE_TITLES.iter().zip(results_batches.iter()) {
+            println!("{}", title);
+            print!("{}", latex_from_results(results_batch));
+            println!();
+        }
+        return;
+    }
     let do_full_run = matches.get_flag("full");
+    let reuse_client_shares = matches.get_flag("reuse-client-shares");
+    let report_error_histograms = matches.get_flag("error-histograms");
+    let report_means = matches.get_flag("means");
+    let seed = match matches.get_one::<u64>("seed") {
+        Some(seed) => *seed,
+        None => rand::thread_rng().gen::<u64>(),
+    };
+    println!("seed {} (pass --seed {} to reproduce this run)", seed, seed);
+    let only_trial = matches.get_one::<usize>("only-trial").copied();
+    if reuse_client_shares {
+        println!("reusing client shares across trials (client times are from a single encoding)");
+    }
     if do_full_run {
         println!("running full simulation");
     } else {
@@ -235,6 +857,28 @@ fn main() {
     } else {
         (1000, 10, 5)
     };
+    if let Some(trial_index) = only_trial {
+        if trial_index >= n_trials {
+            eprintln!(
+                "--only-trial must be less than the number of trials ({})",
+                n_trials
+            );
+            std::process::exit(1);
+        }
+        println!("running only trial {} of each set of params", trial_index);
+    }
+    if matches.get_flag("noise-only") {
+        do_noise_only_simulations(do_full_run, n_noises, seed);
+        return;
+    }
+    if matches.get_flag("ablation") {
+        do_ablation_simulations(n_clients, n_noises, n_trials, seed);
+        return;
+    }
+    if let Some(target_clients) = matches.get_one::<usize>("estimate-capacity") {
+        do_capacity_estimate(*target_clients, n_clients, n_noises, seed);
+        return;
+    }
 
     let epsilon_params = vec![
         Params::new(0.025_f64, n_clients, n_noises, n_trials),
@@ -244,8 +888,16 @@ fn main() {
         Params::new(0.4_f64, n_clients, n_noises, n_trials),
         Params::new(0.8_f64, n_clients, n_noises, n_trials),
     ];
-    println!("Table 3: Average simulation of server processing time with varying epsilon");
-    do_batch_of_simulations(epsilon_params);
+    let mut results_batches = Vec::with_capacity(TABLE_TITLES.len());
+    println!("{}", TABLE_TITLES[0]);
+    results_batches.push(do_batch_of_simulations(
+        epsilon_params,
+        reuse_client_shares,
+        report_error_histograms,
+        report_means,
+        seed,
+        only_trial,
+    ));
 
     let clients_params = if do_full_run {
         vec![
@@ -261,10 +913,15 @@ fn main() {
             Params::new(0.1_f64, 10_000, 14, n_trials),
         ]
     };
-    println!(
-        "Table 4: Average simulation of server processing time with varying client population size"
-    );
-    do_batch_of_simulations(clients_params);
+    println!("{}", TABLE_TITLES[1]);
+    results_batches.push(do_batch_of_simulations(
+        clients_params,
+        reuse_client_shares,
+        report_error_histograms,
+        report_means,
+        seed,
+        only_trial,
+    ));
 
     let noises_params = vec![
         Params::new(0.1_f64, n_clients, 1, n_trials),
@@ -273,59 +930,428 @@ fn main() {
         Params::new(0.1_f64, n_clients, 8, n_trials),
         Params::new(0.1_f64, n_clients, 16, n_trials),
     ];
-    println!(
-        "Table 5: Average simulation of server processing time with varying client noises elected"
-    );
-    do_batch_of_simulations(noises_params);
+    println!("{}", TABLE_TITLES[2]);
+    results_batches.push(do_batch_of_simulations(
+        noises_params,
+        reuse_client_shares,
+        report_error_histograms,
+        report_means,
+        seed,
+        only_trial,
+    ));
+
+    if let Some(output) = matches.get_one::<String>("output") {
+        let saved_results =
+            serde_json::to_string(&results_batches).expect("couldn't serialize results");
+        std::fs::write(output, saved_results).expect("couldn't write output");
+    }
 }
 
-fn do_batch_of_simulations(params_batch: Vec<Params>) {
-    let mut results_batch = Vec::with_capacity(params_batch.len());
-    for params in params_batch {
-        results_batch.push(do_simulation_with_params(params));
+// Builds a LaTeX `tabular` environment. Headers and cells are emitted verbatim, so they may
+// contain LaTeX markup (and any literal `%` must already be escaped as `\%`).
+struct LatexTable {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl LatexTable {
+    fn new(headers: &[&str]) -> LatexTable {
+        LatexTable {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows: Vec::new(),
+        }
     }
-    println!(">>>>> begin copy/paste latex <<<<<");
-    println!("$\\epsilon$ & Population Size & Client Noises Selected & Prio Server Processing Time (ms) & \\dpprio Server Processing Time (ms) & Overhead (\\%) & Error \\\\ \\hline");
-    for results in &results_batch {
+
+    fn add_row(&mut self, row: Vec<String>) {
+        assert!(row.len() == self.headers.len());
+        self.rows.push(row);
+    }
+}
+
+impl fmt::Display for LatexTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let columns = vec!["c"; self.headers.len()].join("|");
+        writeln!(f, "\\begin{{tabular}}{{|{}|}} \\hline", columns)?;
+        writeln!(f, "{} \\\\ \\hline", self.headers.join(" & "))?;
+        for row in &self.rows {
+            writeln!(f, "{} \\\\ \\hline", row.join(" & "))?;
+        }
+        writeln!(f, "\\end{{tabular}}")
+    }
+}
+
+fn server_table(results_batch: &[BatchResults]) -> LatexTable {
+    let mut table = LatexTable::new(&[
+        "$\\epsilon$",
+        "Population Size",
+        "Client Noises Selected",
+        "Prio Server Processing Time ($\\mu$s)",
+        "\\dpprio Server Processing Time ($\\mu$s)",
+        "Overhead (\\%)",
+        "Error",
+    ]);
+    for results in results_batch {
         let (_prio_client_elapsed, prio_server_elapsed, _prio_error) =
             average_results(&results.prio_results);
         let (_dprio_client_elapsed, dprio_server_elapsed, dprio_error) =
             average_results(&results.dprio_results);
         let server_overhead =
             100.0_f64 * (dprio_server_elapsed - prio_server_elapsed) / prio_server_elapsed;
-        println!(
-            "{} & {} & {} & {:.1} & {:.1} & {:.2}\\% & {:.1} \\\\ \\hline",
-            results.params.epsilon,
-            results.params.clients,
-            results.params.noises,
-            prio_server_elapsed,
-            dprio_server_elapsed,
-            server_overhead,
-            dprio_error
-        );
-    }
-    println!(">>>>> end copy/paste latex <<<<<");
-    let mut client_overheads = Vec::with_capacity(results_batch.len());
-    for results in &results_batch {
+        table.add_row(vec![
+            format!("{}", results.params.epsilon),
+            format!("{}", results.params.clients),
+            format!("{}", results.params.noises),
+            format!("{:.1}", prio_server_elapsed),
+            format!("{:.1}", dprio_server_elapsed),
+            format!("{:.2}\\%", server_overhead),
+            format!("{:.1}", dprio_error),
+        ]);
+    }
+    table
+}
+
+fn client_table(results_batch: &[BatchResults]) -> LatexTable {
+    let mut table = LatexTable::new(&[
+        "$\\epsilon$",
+        "Population Size",
+        "Client Noises Selected",
+        "Client Overhead (\\%)",
+    ]);
+    for results in results_batch {
         let (prio_client_elapsed, _prio_server_elapsed, _prio_error) =
             average_results(&results.prio_results);
         let (dprio_client_elapsed, _dprio_server_elapsed, _dprio_error) =
             average_results(&results.dprio_results);
         let client_overhead =
             100.0_f64 * (dprio_client_elapsed - prio_client_elapsed) / prio_client_elapsed;
-        client_overheads.push(format!("{:.2}", client_overhead));
+        table.add_row(vec![
+            format!("{}", results.params.epsilon),
+            format!("{}", results.params.clients),
+            format!("{}", results.params.noises),
+            format!("{:.2}\\%", client_overhead),
+        ]);
+    }
+    table
+}
+
+// Renders the tables (and the summary of the error) for a batch of simulations. This only depends
+// on the results, so it can also be used to render results saved with --output.
+fn latex_from_results(results_batch: &[BatchResults]) -> String {
+    let mut output = String::new();
+    output.push_str(">>>>> begin copy/paste latex <<<<<\n");
+    output.push_str(&server_table(results_batch).to_string());
+    output.push_str(">>>>> end copy/paste latex <<<<<\n");
+    output.push_str(">>>>> begin copy/paste latex <<<<<\n");
+    output.push_str(&client_table(results_batch).to_string());
+    output.push_str(">>>>> end copy/paste latex <<<<<\n");
+    let mut error_decompositions = Vec::with_capacity(results_batch.len());
+    let mut overhead_intervals = Vec::with_capacity(results_batch.len());
+    for results in results_batch {
+        let (low, high) =
+            overhead_confidence_interval(&results.prio_results, &results.dprio_results);
+        overhead_intervals.push(if low.is_finite() && high.is_finite() {
+            format!("[{:.2}, {:.2}]", low, high)
+        } else {
+            "[-inf, inf]".to_string()
+        });
+        let mut noise_sum = 0.0_f64;
+        let mut sampling_sum = 0.0_f64;
+        for result in &results.dprio_results {
+            let (noise, sampling) = error_decomposition(result, &results.params);
+            noise_sum += noise;
+            sampling_sum += sampling;
+        }
+        let n_results = results.dprio_results.len() as f64;
+        error_decompositions.push(format!(
+            "{:.1}/{:.1}",
+            noise_sum / n_results,
+            sampling_sum / n_results
+        ));
+    }
+    output.push_str(&format!(
+        "(dprio error from noise/sampling: {})\n",
+        error_decompositions.join(" ")
+    ));
+    output.push_str(&format!(
+        "(dprio server overhead 95% confidence intervals, in %: {})\n",
+        overhead_intervals.join(" ")
+    ));
+    output
+}
+
+fn do_batch_of_simulations(
+    params_batch: Vec<Params>,
+    reuse_client_shares: bool,
+    report_error_histograms: bool,
+    report_means: bool,
+    seed: u64,
+    only_trial: Option<usize>,
+) -> Vec<BatchResults> {
+    let mut results_batch = Vec::with_capacity(params_batch.len());
+    for params in params_batch {
+        results_batch.push(do_simulation_with_params(
+            params,
+            reuse_client_shares,
+            seed,
+            only_trial,
+        ));
+    }
+    print!("{}", latex_from_results(&results_batch));
+    if report_error_histograms {
+        for results in &results_batch {
+            // The noise of a single client has a scale of 1 / epsilon.
+            let bin_width = (1.0_f64 / results.params.epsilon).ceil() as i64;
+            println!(
+                "dprio error histogram (epsilon {}, {} clients, {} noises):",
+                results.params.epsilon, results.params.clients, results.params.noises
+            );
+            print!("{}", ErrorHistogram::new(&results.dprio_results, bin_width));
+        }
+    }
+    if report_means {
+        for results in &results_batch {
+            // Trials are independent, so a single one shows what a study would publish.
+            if let Some(result) = results.dprio_results.first() {
+                match dp_mean(result, &results.params, 0.95_f64) {
+                    Ok(mean) => println!(
+                        "dprio mean (epsilon {}, {} clients, {} noises): {:.4} (95% CI {:.4} to {:.4}), actual {:.4}",
+                        results.params.epsilon,
+                        results.params.clients,
+                        results.params.noises,
+                        mean.value,
+                        mean.confidence_interval.0,
+                        mean.confidence_interval.1,
+                        result.actual_sum as f64 / result.n_responders as f64
+                    ),
+                    Err(err) => println!(
+                        "no dprio mean (epsilon {}, {} clients, {} noises): {:?}",
+                        results.params.epsilon, results.params.clients, results.params.noises, err
+                    ),
+                }
+            }
+        }
+    }
+    for results in &results_batch {
+        if results
+            .dprio_results
+            .iter()
+            .any(|result| exceeds_noise_bound(result, &results.params))
+        {
+            let minimized_params = minimize(results.params, seed, |params, seed| {
+                do_simulation_with_params(*params, false, seed, None)
+                    .dprio_results
+                    .iter()
+                    .any(|result| exceeds_noise_bound(result, params))
+            });
+            match minimized_params {
+                Some(minimized_params) => println!(
+                    "implausible result with params {:?}, reproduced with {:?} (seed {})",
+                    results.params, minimized_params, seed
+                ),
+                None => println!(
+                    "implausible result with params {:?}, which didn't reproduce (seed {})",
+                    results.params, seed
+                ),
+            }
+        }
+    }
+    for results in &results_batch {
+        let rejected_shares: usize = results
+            .prio_results
+            .iter()
+            .chain(results.dprio_results.iter())
+            .map(|result| result.rejected_shares)
+            .sum();
+        if rejected_shares > 0 {
+            println!(
+                "{} shares were rejected with params {:?} (seed {})",
+                rejected_shares, results.params, seed
+            );
+        }
     }
-    println!("(client overheads (%): {})", client_overheads.join(" "));
     println!("");
+    results_batch
+}
+
+// Returns true if the error introduced by the noise is larger than can plausibly be explained by
+// the noise. Each noise value (with an l1 sensitivity of 1) exceeds 6 ln(10) / epsilon in
+// magnitude with probability 10^-6 (this is the same analysis as in `laplace::min_bits`), so the
+// sum of the selected noise values exceeds `noises` times that with probability at most
+// `noises` * 10^-6.
+fn exceeds_noise_bound(result: &Results, params: &Params) -> bool {
+    let noise_bound = params.noises as f64 * 6.0_f64 * 10.0_f64.ln() / params.epsilon;
+    signed_error(result).abs() as f64 > noise_bound
+}
+
+// Returns the noised mean of the client values of a dprio result, with its confidence interval.
+// The servers divide by the number of responders, which they learn anyway from the number of
+// shares they receive (the contribution count minus the selected noises), so the mean is as
+// private as the sum (see `DpResult::mean`). The calculated sum is mapped back to a signed value
+// the same way as in `signed_error`, as negative noise can make it wrap around the modulus.
+fn dp_mean(
+    result: &Results,
+    params: &Params,
+    confidence: f64,
+) -> Result<laplace::DpMean, ParameterError> {
+    let modulus = field_modulus() as i64;
+    let sum = result.calculated_sum as i64;
+    let sum = if sum > modulus / 2 {
+        sum - modulus
+    } else {
+        sum
+    };
+    laplace::DpResult::new(sum, 1.0_f64, params.epsilon, params.noises, confidence)?
+        .mean(result.n_responders)
+}
+
+// Where criterion saves the measurements of `cargo bench --bench commitment` (see
+// benches/commitment.rs), relative to the root of the package.
+const CRITERION_DIR: &str = "target/criterion";
+
+// Returns the mean time of an iteration of the criterion benchmark bench_id, in nanoseconds, from
+// the measurements saved in criterion_dir, or None if the benchmark hasn't been run. Criterion
+// saves the latest measurements of a benchmark in the "new" directory under its id, with any
+// slashes replaced by underscores.
+fn bench_mean_ns(criterion_dir: &Path, bench_id: &str) -> Option<f64> {
+    let estimates_path = criterion_dir
+        .join(bench_id.replace('/', "_"))
+        .join("new")
+        .join("estimates.json");
+    let estimates: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(estimates_path).ok()?).ok()?;
+    estimates["mean"]["point_estimate"].as_f64()
+}
+
+// Returns the costs of creating and closing one commitment and of validating one, in nanoseconds,
+// as measured by the benchmarks of the commitment protocol, or None if they haven't been run.
+fn commitment_costs_from_benches(criterion_dir: &Path) -> Option<(f64, f64)> {
+    let commitment_ns = bench_mean_ns(criterion_dir, "commitment/new")?
+        + bench_mean_ns(criterion_dir, "commitment/commit")?;
+    let validation_ns = bench_mean_ns(criterion_dir, "commitment/validate")?;
+    Some((commitment_ns, validation_ns))
+}
+
+// Measures the same steps as the benchmarks of the commitment protocol, each averaged over
+// n_iterations, for when the benchmarks haven't been run (or were run with another build than
+// the simulation being calibrated). Returns the costs in the order of
+// `commitment_costs_from_benches`.
+fn measure_commitment_costs(n_iterations: usize) -> (f64, f64) {
+    // The corpus size of the benchmarks. The costs hardly depend on it.
+    let n = 162_564_322;
+    let commitment_start_time = Instant::now();
+    for _ in 0..n_iterations {
+        std::hint::black_box(Commitment::new(n).unwrap().commit());
+    }
+    let commitment_elapsed = commitment_start_time.elapsed();
+    let commitment = Commitment::new(n).unwrap();
+    let closed_commitment = commitment.commit();
+    let (p, nonce) = commitment.publish();
+    let validation_start_time = Instant::now();
+    for _ in 0..n_iterations {
+        std::hint::black_box(closed_commitment.validate(p, &nonce).unwrap());
+    }
+    let validation_elapsed = validation_start_time.elapsed();
+    (
+        commitment_elapsed.as_nanos() as f64 / n_iterations as f64,
+        validation_elapsed.as_nanos() as f64 / n_iterations as f64,
+    )
+}
+
+// Derives the per-operation costs of `capacity::estimate_server_cpu_millis` from the costs of the
+// commitment protocol (as returned by `commitment_costs_from_benches` or
+// `measure_commitment_costs`) and the timing breakdown of a run with n_noises selected noises. The
+// run's times cover both servers.
+fn calibration_from_results(
+    result: &Results,
+    n_noises: usize,
+    commitment_costs: (f64, f64),
+) -> capacity::Calibration {
+    let n_servers = 2.0_f64;
+    let n_elements = ((result.n_responders + n_noises) * result.dimension) as f64;
+    let (commitment_ns, validation_ns) = commitment_costs;
+    capacity::Calibration {
+        commitment_ns,
+        validation_ns,
+        verification_per_element_ns: result.verification_elapsed_us as f64 * 1000.0_f64
+            / (n_servers * n_elements),
+        aggregation_per_element_ns: result.aggregation_elapsed_us as f64 * 1000.0_f64
+            / (n_servers * n_elements),
+    }
+}
+
+// Returns calculated_sum - actual_sum. The calculated sum is an element of the field, so if the
+// noise made the noised sum negative, it wrapped around to a value near the modulus. Such values
+// are mapped back to negative errors.
+fn signed_error(result: &Results) -> i64 {
+    let modulus = field_modulus() as i64;
+    let error = (result.calculated_sum as i64 - result.actual_sum as i64).rem_euclid(modulus);
+    if error > modulus / 2 {
+        error - modulus
+    } else {
+        error
+    }
+}
+
+// Shrinks the number of clients, noises, and trials of `failing_params` while `fails` still
+// returns true, so that a failure can be reproduced (and reported) with the smallest possible
+// simulation. Each parameter is repeatedly reduced to the smallest value that still fails (by
+// bisection, so if the failure isn't monotonic in a parameter, a smaller failing value may be
+// missed), until none of them can be reduced further. The seed is passed to each evaluation of
+// `fails`, so that a seeded predicate evaluates consistently. Returns None if failing_params don't
+// fail (which can happen if the failure is rare and `fails` isn't seeded).
+fn minimize<F>(failing_params: Params, seed: u64, mut fails: F) -> Option<Params>
+where
+    F: FnMut(&Params, u64) -> bool,
+{
+    if !fails(&failing_params, seed) {
+        return None;
+    }
+    let mut params = failing_params;
+    loop {
+        let before = (params.clients, params.noises, params.trials);
+        params.trials = smallest_failing(params.trials, 1, |trials| {
+            fails(&Params { trials, ..params }, seed)
+        });
+        params.noises = smallest_failing(params.noises, 0, |noises| {
+            fails(&Params { noises, ..params }, seed)
+        });
+        // There can't be fewer clients than selected noises.
+        params.clients = smallest_failing(params.clients, params.noises.max(1), |clients| {
+            fails(&Params { clients, ..params }, seed)
+        });
+        if (params.clients, params.noises, params.trials) == before {
+            return Some(params);
+        }
+    }
+}
+
+// Given that `fails(failing_value)` is true, returns the smallest value in
+// [lowest, failing_value] found by bisection for which `fails` is true.
+fn smallest_failing<F>(failing_value: usize, lowest: usize, mut fails: F) -> usize
+where
+    F: FnMut(usize) -> bool,
+{
+    let mut low = lowest;
+    let mut high = failing_value;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if fails(mid) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    high
 }
 
+#[derive(Deserialize, Serialize)]
 struct BatchResults {
     params: Params,
     prio_results: Vec<Results>,
     dprio_results: Vec<Results>,
 }
 
-fn do_simulation_with_params(params: Params) -> BatchResults {
+fn server_private_keys() -> (PrivateKey, PrivateKey) {
     let priv_key1 = PrivateKey::from_base64(
         "BIl6j+J6dYttxALdjISDv6ZI4/VWVEhUzaS05LgrsfswmbLOgN\
          t9HUC2E0w+9RqZx3XMkdEHBHfNuCSMpOwofVSq3TfyKwn0NrftKisKKVSaTOt5seJ67P5QL4hxgPWvxw==",
@@ -336,107 +1362,417 @@ fn do_simulation_with_params(params: Params) -> BatchResults {
          LMQIQoRwDVaW64g/WTdcxT4rDULoycUNFB60LER6hPEHg/ObBnRPV1rwS3nj9Bj0tbjVPPyL9p8QW8B+w==",
     )
     .unwrap();
-    let mut prio_results = Vec::with_capacity(params.trials);
-    let mut dprio_results = Vec::with_capacity(params.trials);
-    for _ in 0..params.trials {
-        let prio_result = do_simulation(
-            false,
-            params.epsilon,
-            params.clients,
-            params.noises,
-            priv_key1.clone(),
-            priv_key2.clone(),
+    (priv_key1, priv_key2)
+}
+
+// Measures the cost of noise selection in isolation, for growing corpora of noise from clients that
+// submit no data (see `encode_noise_providers`). Each corpus is measured once, as the cost of
+// selection barely varies between runs.
+fn do_noise_only_simulations(do_full_run: bool, n_noises: usize, seed: u64) {
+    let corpus_sizes: &[usize] = if do_full_run {
+        &[1000, 10_000, 100_000, 1_000_000]
+    } else {
+        &[100, 1000, 10_000]
+    };
+    let epsilon = 0.1_f64;
+    let (priv_key1, priv_key2) = server_private_keys();
+    let mut rng = StdRng::seed_from_u64(seed);
+    println!(
+        "noise selection only (epsilon {}, {} noises selected)",
+        epsilon, n_noises
+    );
+    for corpus_size in corpus_sizes {
+        let encoded_providers = encode_noise_providers(
+            epsilon,
+            &laplace_noise(epsilon),
+            *corpus_size,
+            &PublicKey::from(&priv_key1),
+            &PublicKey::from(&priv_key2),
+            &mut rng,
         );
-        prio_results.push(prio_result);
-        let dprio_result = do_simulation(
-            true,
-            params.epsilon,
-            params.clients,
-            params.noises,
+        let result = run_servers(
+            encoded_providers,
+            n_noises,
             priv_key1.clone(),
             priv_key2.clone(),
+            &mut rng,
+        );
+        println!(
+            "corpus of {}: selection {}us, server total {}us",
+            corpus_size, result.commitment_elapsed_us, result.server_elapsed_us
         );
-        dprio_results.push(dprio_result);
     }
-    BatchResults {
-        params,
+}
+
+// Compares the dprio error with the Laplace noise of each epsilon to the error with uniform noise
+// of the same variance, to separate the effect of the noise's shape from that of its magnitude.
+fn do_ablation_simulations(n_clients: usize, n_noises: usize, n_trials: usize, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    println!(
+        "noise ablation ({} clients, {} noises, {} trials): mean absolute dprio error",
+        n_clients, n_noises, n_trials
+    );
+    for epsilon in [0.1_f64, 0.4_f64, 0.8_f64] {
+        let (laplace_error, uniform_error) =
+            ablation_errors(epsilon, n_clients, n_noises, n_trials, &mut rng);
+        println!(
+            "epsilon {}: laplace {:.1}, uniform {:.1}",
+            epsilon, laplace_error, uniform_error
+        );
+    }
+}
+
+// Calibrates `capacity::estimate_server_cpu_millis` with the benchmarks of the commitment protocol
+// (which should be run first, with `cargo bench --bench commitment`) and a dprio run of n_clients
+// clients and n_noises noises, and prints the estimated CPU time of a server for target_clients
+// clients (with the same number of noises).
+fn do_capacity_estimate(target_clients: usize, n_clients: usize, n_noises: usize, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (priv_key1, priv_key2) = server_private_keys();
+    let client_values = sample_client_values(n_clients, 0.0_f64, &mut rng);
+    let result = do_simulation(
+        true,
+        0.1_f64,
+        &client_values,
+        n_noises,
+        priv_key1,
+        priv_key2,
+        &mut rng,
+    );
+    let commitment_costs =
+        commitment_costs_from_benches(Path::new(CRITERION_DIR)).unwrap_or_else(|| {
+            println!("the commitment benchmarks haven't been run, so measuring the commitments");
+            measure_commitment_costs(1000)
+        });
+    let calibration = calibration_from_results(&result, n_noises, commitment_costs);
+    println!(
+        "calibrated with {} clients and {} noises: {:?}",
+        n_clients, n_noises, calibration
+    );
+    println!(
+        "estimated CPU time per server for {} clients: {:.1} ms",
+        target_clients,
+        capacity::estimate_server_cpu_millis(
+            target_clients,
+            n_noises,
+            result.dimension,
+            &calibration
+        )
+    );
+}
+
+// Returns the mean absolute error of n_trials dprio runs with the Laplace noise for epsilon, and
+// of as many runs with noise drawn uniformly from [-sqrt(6) / epsilon, sqrt(6) / epsilon]. Both
+// have a variance of about 2 / epsilon^2 (the Laplace noise's granularity aside), but only the
+// Laplace noise is private.
+fn ablation_errors(
+    epsilon: f64,
+    n_clients: usize,
+    n_noises: usize,
+    n_trials: usize,
+    rng: &mut StdRng,
+) -> (f64, f64) {
+    let (priv_key1, priv_key2) = server_private_keys();
+    let public_key1 = PublicKey::from(&priv_key1);
+    let public_key2 = PublicKey::from(&priv_key2);
+    let half_width = 6.0_f64.sqrt() / epsilon;
+    let noise_distributions = [
+        laplace_noise(epsilon),
+        distribution_noise(Uniform::new_inclusive(-half_width, half_width)),
+    ];
+    let mut errors = [0.0_f64; 2];
+    for (noise_distribution, error) in noise_distributions.iter().zip(errors.iter_mut()) {
+        for _ in 0..n_trials {
+            let client_values = sample_client_values(n_clients, 0.0_f64, rng);
+            let encoded_clients = encode_clients_with_noise(
+                epsilon,
+                Some(noise_distribution),
+                &client_values,
+                &public_key1,
+                &public_key2,
+                rng,
+            );
+            let result = run_servers(
+                encoded_clients,
+                n_noises,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                rng,
+            );
+            *error += signed_error(&result).abs() as f64 / n_trials as f64;
+        }
+    }
+    (errors[0], errors[1])
+}
+
+// If reuse_client_shares is true, each population of clients is only encoded once and the same
+// shares are given to the servers in every trial. This is a benchmarking convenience to measure
+// the server processing time without the variation introduced by generating new client data, and
+// isn't a valid execution of the protocol: in a real deployment, every aggregation must use fresh
+// shares (and fresh noise).
+// Within a trial, the prio and dprio runs aggregate the values of the same client population.
+// Each trial draws its client values, laplace noise, and the commitments selecting the noise from
+// an RNG seeded with `trial_seed(master_seed, trial_index)`, so that any trial can be reproduced on
+// its own. If reuse_client_shares is true, the shared population (and its noise) is always drawn
+// with the seed of the first trial, whichever trials are run, and only the commitments are drawn
+// with each trial's seed. If only_trial is given, only that trial is run (and the results have a
+// single trial).
+fn do_simulation_with_params(
+    params: Params,
+    reuse_client_shares: bool,
+    master_seed: u64,
+    only_trial: Option<usize>,
+) -> BatchResults {
+    let trial_indices = match only_trial {
+        Some(trial_index) => {
+            assert!(trial_index < params.trials, "trial index out of range");
+            trial_index..trial_index + 1
+        }
+        None => 0..params.trials,
+    };
+    let params = Params {
+        trials: trial_indices.len(),
+        ..params
+    };
+    let (priv_key1, priv_key2) = server_private_keys();
+    check_prio_compatibility(&priv_key1, &priv_key2)
+        .expect("the linked prio version should be compatible");
+    let mut prio_results = Vec::with_capacity(params.trials);
+    let mut dprio_results = Vec::with_capacity(params.trials);
+    if reuse_client_shares {
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        // Every trial reuses the clients of the first one, even if the first one isn't run.
+        let rng = &mut StdRng::seed_from_u64(trial_seed(master_seed, 0));
+        let client_values = sample_client_values(params.clients, 0.0_f64, rng);
+        let prio_clients = encode_clients(
+            false,
+            params.epsilon,
+            &client_values,
+            &public_key1,
+            &public_key2,
+            rng,
+        );
+        let dprio_clients = encode_clients(
+            true,
+            params.epsilon,
+            &client_values,
+            &public_key1,
+            &public_key2,
+            rng,
+        );
+        for trial_index in trial_indices {
+            let rng = &mut StdRng::seed_from_u64(trial_seed(master_seed, trial_index));
+            reset_peak_memory();
+            prio_results.push(run_servers(
+                prio_clients.clone(),
+                params.noises,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                rng,
+            ));
+            reset_peak_memory();
+            dprio_results.push(run_servers(
+                dprio_clients.clone(),
+                params.noises,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                rng,
+            ));
+        }
+    } else {
+        for trial_index in trial_indices {
+            let rng = &mut StdRng::seed_from_u64(trial_seed(master_seed, trial_index));
+            // The prio and dprio runs of a trial use the same client population, so that their
+            // errors differ only by the noise.
+            let client_values = sample_client_values(params.clients, 0.0_f64, rng);
+            let prio_result = do_simulation(
+                false,
+                params.epsilon,
+                &client_values,
+                params.noises,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                rng,
+            );
+            prio_results.push(prio_result);
+            let dprio_result = do_simulation(
+                true,
+                params.epsilon,
+                &client_values,
+                params.noises,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                rng,
+            );
+            dprio_results.push(dprio_result);
+        }
+    }
+    BatchResults {
+        params,
         prio_results,
         dprio_results,
     }
 }
 
+// Derives the seed of a trial from the master seed of the run and the trial's index, so that a
+// trial's seed doesn't depend on any other trial (see --only-trial).
+fn trial_seed(master_seed: u64, trial_index: usize) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(master_seed.to_be_bytes());
+    hasher.update((trial_index as u64).to_be_bytes());
+    let mut seed = [0; 8];
+    seed.copy_from_slice(&hasher.finalize()[..8]);
+    u64::from_be_bytes(seed)
+}
+
 fn select_noise(
     shares_for_server1: &mut Vec<Vec<u8>>,
     shares_for_server2: &mut Vec<Vec<u8>>,
-    noise_for_server1: &mut Vec<Vec<u8>>,
-    noise_for_server2: &mut Vec<Vec<u8>>,
+    noise_pool: &mut NoisePool,
     n_noises: usize,
+    rng: &mut StdRng,
 ) {
     for _ in 0..n_noises {
-        let commitment_from_server1 = Commitment::new(noise_for_server1.len() as u64);
-        let commitment_from_server2 = Commitment::new(noise_for_server2.len() as u64);
-        let closed_commitment_from_server1 = commitment_from_server1.commit();
-        let closed_commitment_from_server2 = commitment_from_server2.commit();
-        let published_commitment_from_server1 = commitment_from_server1.publish();
-        let published_commitment_from_server2 = commitment_from_server2.publish();
-        let opened_commitment_from_server1 = closed_commitment_from_server1
-            .validate(published_commitment_from_server1)
-            .unwrap();
-        let opened_commitment_from_server2 = closed_commitment_from_server2
-            .validate(published_commitment_from_server2)
-            .unwrap();
-        let noise_index = OpenedCommitment::gather(&[
-            opened_commitment_from_server1,
-            opened_commitment_from_server2,
-        ])
-        .unwrap();
-        shares_for_server1.push(noise_for_server1.swap_remove(noise_index as usize));
-        shares_for_server2.push(noise_for_server2.swap_remove(noise_index as usize));
+        // Each server validates both published values itself, and the servers confirm they selected
+        // the same index before removing anything from their noise corpora.
+        let (share_for_server1, share_for_server2) = noise_pool
+            .draw_with_rng(rng)
+            .expect("there should be enough noise");
+        shares_for_server1.push(share_for_server1);
+        shares_for_server2.push(share_for_server2);
     }
 }
 
+// The shares (and, for dprio, noise shares) a population of clients sends to the servers, along
+// with what the servers need to know to decode the aggregate.
+#[derive(Clone)]
+struct EncodedClients {
+    do_dprio: bool,
+    dimension: usize,
+    // The number of clients that didn't abstain.
+    n_responders: usize,
+    actual_sum: usize,
+    // One per responding client (or noise provider), covering both its data and noise shares.
+    key_fingerprints: Vec<KeyFingerprint>,
+    shares_for_server1: Vec<Vec<u8>>,
+    shares_for_server2: Vec<Vec<u8>>,
+    noise_for_server1: Vec<Vec<u8>>,
+    noise_for_server2: Vec<Vec<u8>>,
+    setup_elapsed: Duration,
+    encode_elapsed: Duration,
+}
+
 // This code was adapted from
 // https://github.com/abetterinternet/libprio-rs/blob/e58a06de3af0bdcb12e4273751c33b5ceee94d95/examples/sum.rs
 fn do_simulation(
     do_dprio: bool,
     epsilon: f64,
-    n_clients: usize,
+    client_values: &[usize],
     n_noises: usize,
     priv_key1: PrivateKey,
     priv_key2: PrivateKey,
+    rng: &mut StdRng,
 ) -> Results {
-    // +1 to minimum bits to be able to handle negative noise values
-    let dimension = if do_dprio {
-        laplace::min_bits(1.0_f64, epsilon).expect("min_bits should succeed") + 1
+    reset_peak_memory();
+    let encoded_clients = encode_clients(
+        do_dprio,
+        epsilon,
+        client_values,
+        &PublicKey::from(&priv_key1),
+        &PublicKey::from(&priv_key2),
+        rng,
+    );
+    run_servers(encoded_clients, n_noises, priv_key1, priv_key2, rng)
+}
+
+// Samples the actual values of a population of n_clients clients. Each client abstains with
+// probability abstention_probability, in which case it sends neither data nor noise (as a
+// non-responder to a survey would), so only the values of the responders are returned.
+fn sample_client_values(
+    n_clients: usize,
+    abstention_probability: f64,
+    rng: &mut StdRng,
+) -> Vec<usize> {
+    let mut client_values = Vec::with_capacity(n_clients);
+    for _ in 0..n_clients {
+        if rng.gen_bool(abstention_probability) {
+            continue;
+        }
+        // The study is a count, so each client will send either 0 or 1. For this simulation, the
+        // probability of sending 1 is CLIENT_VALUE_PROBABILITY.
+        client_values.push(rng.sample(Binomial::new(1, CLIENT_VALUE_PROBABILITY)) as usize);
+    }
+    client_values
+}
+
+// Encodes the given client values (one per responding client), with noise if do_dprio is true.
+fn encode_clients(
+    do_dprio: bool,
+    epsilon: f64,
+    client_values: &[usize],
+    public_key1: &PublicKey,
+    public_key2: &PublicKey,
+    rng: &mut StdRng,
+) -> EncodedClients {
+    let noise_distribution = if do_dprio {
+        Some(laplace_noise(epsilon))
     } else {
-        1
+        None
     };
-    let mut server1 = ServerState::new(dimension, true, priv_key1);
-    let mut server2 = ServerState::new(dimension, false, priv_key2);
+    encode_clients_with_noise(
+        epsilon,
+        noise_distribution.as_ref(),
+        client_values,
+        public_key1,
+        public_key2,
+        rng,
+    )
+}
 
-    let shift_value = if do_dprio {
-        assert!(dimension > 1 && dimension <= u32::MAX as usize);
-        2isize.pow((dimension - 1) as u32)
+// Like `encode_clients`, but with the clients' noise drawn from noise_distribution (if any) rather
+// than from the Laplace noise for epsilon. The dimension is still the one for epsilon.
+fn encode_clients_with_noise(
+    epsilon: f64,
+    noise_distribution: Option<&NoiseDistribution>,
+    client_values: &[usize],
+    public_key1: &PublicKey,
+    public_key2: &PublicKey,
+    rng: &mut StdRng,
+) -> EncodedClients {
+    let do_dprio = noise_distribution.is_some();
+    let dimension = if do_dprio {
+        dimension_inflation(epsilon) * PRIO_DIMENSION
     } else {
-        0
+        PRIO_DIMENSION
     };
-    assert!(shift_value >= 0);
+
+    let shift_value = shift_value(do_dprio, dimension);
+    let n_clients = client_values.len();
     let mut clients = Vec::with_capacity(n_clients);
-    let mut actual_value = 0;
-    let client_start_time = Instant::now();
-    for _ in 0..n_clients {
+    let mut harness = EvaluationHarness::new();
+    let setup_start_time = Instant::now();
+    for client_value in client_values {
         let client = ClientState::new(
             dimension,
             shift_value,
-            epsilon,
-            do_dprio,
-            server1.get_public_key(),
-            server2.get_public_key(),
+            *client_value,
+            noise_distribution,
+            public_key1,
+            public_key2,
+            rng,
         );
-        actual_value += client.actual_value;
+        harness.record_client(*client_value);
         clients.push(client);
     }
+    let setup_elapsed = setup_start_time.elapsed();
+    let n_responders = clients.len();
+    let key_fingerprints = clients
+        .iter()
+        .map(|client| client.key_fingerprint)
+        .collect();
 
+    let encode_start_time = Instant::now();
     let mut shares_for_server1 = Vec::with_capacity(n_clients);
     let mut shares_for_server2 = Vec::with_capacity(n_clients);
     let mut noise_for_server1 = Vec::with_capacity(n_clients);
@@ -453,23 +1789,180 @@ fn do_simulation(
             noise_for_server2.push(noise2);
         }
     }
-    let client_elapsed = client_start_time.elapsed();
+    let encode_elapsed = encode_start_time.elapsed();
+
+    EncodedClients {
+        do_dprio,
+        dimension,
+        n_responders,
+        actual_sum: harness.true_sum(),
+        key_fingerprints,
+        shares_for_server1,
+        shares_for_server2,
+        noise_for_server1,
+        noise_for_server2,
+        setup_elapsed,
+        encode_elapsed,
+    }
+}
+
+// Each client of the count study sends a single bit without dprio.
+const PRIO_DIMENSION: usize = 1;
+
+// Returns the factor by which dprio inflates the dimension of the study over plain prio's
+// PRIO_DIMENSION: each value must be sent with min_bits bits of noise, +1 to be able to handle
+// negative noise values.
+fn dimension_inflation(epsilon: f64) -> usize {
+    laplace::min_bits(1.0_f64, epsilon).expect("min_bits should succeed") + 1
+}
+
+// Encodes a degenerate population of n_providers clients that submit only noise (drawn from
+// noise_distribution) and no data, for measuring noise selection in isolation. The servers select
+// from the full corpus as usual, but there is no data to aggregate, so the actual sum is 0 and the
+// calculated sum is that of the selected noise.
+fn encode_noise_providers(
+    epsilon: f64,
+    noise_distribution: &NoiseDistribution,
+    n_providers: usize,
+    public_key1: &PublicKey,
+    public_key2: &PublicKey,
+    rng: &mut StdRng,
+) -> EncodedClients {
+    let dimension = dimension_inflation(epsilon) * PRIO_DIMENSION;
+    let shift_value = shift_value(true, dimension);
+    let setup_start_time = Instant::now();
+    let mut providers: Vec<ClientState> = (0..n_providers)
+        .map(|_| {
+            ClientState::new(
+                dimension,
+                shift_value,
+                0,
+                Some(noise_distribution),
+                public_key1,
+                public_key2,
+                rng,
+            )
+        })
+        .collect();
+    let setup_elapsed = setup_start_time.elapsed();
+
+    let encode_start_time = Instant::now();
+    let mut noise_for_server1 = Vec::with_capacity(n_providers);
+    let mut noise_for_server2 = Vec::with_capacity(n_providers);
+    for provider in &mut providers {
+        let (noise1, noise2) = provider.get_noise().unwrap();
+        noise_for_server1.push(noise1);
+        noise_for_server2.push(noise2);
+    }
+    let encode_elapsed = encode_start_time.elapsed();
+
+    EncodedClients {
+        do_dprio: true,
+        dimension,
+        n_responders: 0,
+        actual_sum: 0,
+        key_fingerprints: providers
+            .iter()
+            .map(|provider| provider.key_fingerprint)
+            .collect(),
+        shares_for_server1: Vec::new(),
+        shares_for_server2: Vec::new(),
+        noise_for_server1,
+        noise_for_server2,
+        setup_elapsed,
+        encode_elapsed,
+    }
+}
+
+// The value clients add to every value they send (and servers subtract for every contribution).
+// Without noise, values are never negative, so no shift is needed.
+fn shift_value(do_dprio: bool, dimension: usize) -> u64 {
+    if do_dprio {
+        laplace::shift_value_for_dimension(dimension).expect("dimension should be supported")
+    } else {
+        0
+    }
+}
+
+// The noise is selected with commitments drawn from rng.
+fn run_servers(
+    encoded_clients: EncodedClients,
+    n_noises: usize,
+    priv_key1: PrivateKey,
+    priv_key2: PrivateKey,
+    rng: &mut StdRng,
+) -> Results {
+    let EncodedClients {
+        do_dprio,
+        dimension,
+        n_responders,
+        actual_sum,
+        key_fingerprints,
+        mut shares_for_server1,
+        mut shares_for_server2,
+        noise_for_server1,
+        noise_for_server2,
+        setup_elapsed,
+        encode_elapsed,
+    } = encoded_clients;
+    let server_key_fingerprint =
+        KeyFingerprint::new(&PublicKey::from(&priv_key1), &PublicKey::from(&priv_key2));
+    // A simulation runs a single study, which the servers route shares to like any other. Each
+    // server counts the shares it rejects.
+    let server1_sink = events::CountingSink::new();
+    let server2_sink = events::CountingSink::new();
+    let mut multi_study_server1 = MultiStudyServer::new(true);
+    let mut multi_study_server2 = MultiStudyServer::new(false);
+    multi_study_server1
+        .add_study(
+            SIMULATION_STUDY,
+            dimension,
+            priv_key1,
+            Box::new(server1_sink.clone()),
+        )
+        .unwrap();
+    multi_study_server2
+        .add_study(
+            SIMULATION_STUDY,
+            dimension,
+            priv_key2,
+            Box::new(server2_sink.clone()),
+        )
+        .unwrap();
+    let server1 = multi_study_server1.study(SIMULATION_STUDY).unwrap();
+    let server2 = multi_study_server2.study(SIMULATION_STUDY).unwrap();
 
     let server_start_time = Instant::now();
+    check_key_fingerprints(server_key_fingerprint, &key_fingerprints)
+        .expect("the clients should use the servers' public keys");
+    let commitment_start_time = Instant::now();
     if do_dprio {
         select_noise(
             &mut shares_for_server1,
             &mut shares_for_server2,
-            &mut noise_for_server1,
-            &mut noise_for_server2,
+            &mut NoisePool::new(noise_for_server1, noise_for_server2).unwrap(),
             n_noises,
+            rng,
         );
     }
 
+    // Every contribution (data or selected noise) was shifted, so the total shift depends on how
+    // many contributions the servers actually received.
+    let contribution_count = shares_for_server1.len();
+    check_sum_fits::<Field32>(contribution_count, dimension)
+        .expect("the study should fit in the field");
+    assert_eq!(contribution_count, shares_for_server2.len());
+    let selected_noise_count = if do_dprio { n_noises } else { 0 };
+    assert_eq!(contribution_count, n_responders + selected_noise_count);
+    let commitment_elapsed = commitment_start_time.elapsed();
+
+    let verification_start_time = Instant::now();
     let eval_at = Field32::from(12313);
     let server1_verifications = server1.generate_verifications(&shares_for_server1, eval_at);
     let server2_verifications = server2.generate_verifications(&shares_for_server2, eval_at);
+    let verification_elapsed = verification_start_time.elapsed();
 
+    let aggregation_start_time = Instant::now();
     server1.aggregate(
         shares_for_server1,
         &server1_verifications,
@@ -481,23 +1974,1363 @@ fn do_simulation(
         &server2_verifications,
     );
 
-    let raw_sum = *server1.add_and_get_total_sum(server2.total_sum());
-    let total_shift_count = if do_dprio {
-        n_clients + n_noises
+    let share_from_server1 = *server1.total_sum();
+    let share_from_server2 = *server2.total_sum();
+    let raw_sum = combine_total_shares(server1, server2, &share_from_server1, &share_from_server2)
+        .expect("the servers should arrive at the same total");
+    let shift_value = shift_value(do_dprio, dimension);
+    // Every contribution was shifted to be non-negative, and check_sum_fits ensured that their sum
+    // fits in the field, so the raw sum didn't wrap.
+    let raw_sum = CheckedSum::from_field(raw_sum);
+    let total_shift_value =
+        CheckedSum::from_integer(shift_value as i128 * contribution_count as i128);
+    // Note that total_shift_value <= raw_sum doesn't hold in general: if the selected noise is
+    // negative enough, the noised raw sum is less than the total shift.
+    let total_sum = raw_sum.sub(total_shift_value);
+    let signed_sum = laplace::decode_sum(
+        u32::from(raw_sum.to_field()) as u64,
+        field_modulus() as u64,
+        shift_value,
+        contribution_count,
+    )
+    .expect("the study should fit in the field");
+    debug_assert_eq!(signed_sum as i128, total_sum.unwrapped);
+    // Without noise, the total is the (non-negative) sum of the client values. Negative noise can
+    // make the noised total negative, which is reported as it would be in the field, i.e.
+    // wrapped around the modulus (see `signed_error`).
+    let calculated_sum = if do_dprio {
+        signed_sum.rem_euclid(field_modulus() as i64) as usize
     } else {
-        n_clients
+        total_sum.to_integer()
     };
-    let total_shift_value = Field32::from((shift_value as usize * total_shift_count) as u32);
-    // assert!(total_shift_value <= raw_sum); TODO: why doesn't this work
-    let total_sum = raw_sum - total_shift_value;
+    let aggregation_elapsed = aggregation_start_time.elapsed();
     let server_elapsed = server_start_time.elapsed();
+    // Both servers decide on each share from the same pair of verification messages.
+    let rejected_shares = server1_sink.count(events::Event::ShareRejected);
+    assert_eq!(
+        rejected_shares,
+        server2_sink.count(events::Event::ShareRejected)
+    );
 
     Results {
         dprio: do_dprio,
         dimension,
-        calculated_sum: <u32 as From<Field32>>::from(total_sum) as usize,
-        actual_sum: actual_value,
-        client_elapsed: client_elapsed.as_millis(),
-        server_elapsed: server_elapsed.as_millis(),
+        calculated_sum,
+        actual_sum,
+        n_responders,
+        dimension_inflation: dimension as f64 / PRIO_DIMENSION as f64,
+        client_elapsed_us: (setup_elapsed + encode_elapsed).as_micros(),
+        setup_elapsed_us: setup_elapsed.as_micros(),
+        encode_elapsed_us: encode_elapsed.as_micros(),
+        server_elapsed_us: server_elapsed.as_micros(),
+        commitment_elapsed_us: commitment_elapsed.as_micros(),
+        verification_elapsed_us: verification_elapsed.as_micros(),
+        aggregation_elapsed_us: aggregation_elapsed.as_micros(),
+        peak_memory_kb: peak_memory_kb(),
+        rejected_shares,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the lowest `dimension` bits of value, starting with the least significant.
+    fn to_bits(value: usize, dimension: usize) -> Vec<u32> {
+        let bits: Vec<u32> = (0..dimension).map(|i| ((value >> i) & 1) as u32).collect();
+        assert!(bits.len() == dimension);
+        bits
+    }
+
+    // The tests don't need to be reproducible, so they're seeded randomly.
+    fn test_rng() -> StdRng {
+        StdRng::seed_from_u64(rand::thread_rng().gen::<u64>())
+    }
+
+    fn synthetic_results(calculated_sum: usize, actual_sum: usize) -> Results {
+        Results {
+            dprio: true,
+            dimension: 30,
+            calculated_sum,
+            actual_sum,
+            n_responders: 100,
+            dimension_inflation: 30.0_f64,
+            client_elapsed_us: 0,
+            setup_elapsed_us: 0,
+            encode_elapsed_us: 0,
+            server_elapsed_us: 0,
+            commitment_elapsed_us: 0,
+            verification_elapsed_us: 0,
+            aggregation_elapsed_us: 0,
+            peak_memory_kb: 0,
+            rejected_shares: 0,
+        }
+    }
+
+    fn synthetic_timed_results(
+        dprio: bool,
+        error: usize,
+        client_elapsed_us: u128,
+        server_elapsed_us: u128,
+    ) -> Results {
+        Results {
+            dprio,
+            dimension: if dprio { 17 } else { 1 },
+            calculated_sum: 500 + error,
+            actual_sum: 500,
+            n_responders: 1000,
+            dimension_inflation: if dprio { 17.0_f64 } else { 1.0_f64 },
+            client_elapsed_us,
+            setup_elapsed_us: client_elapsed_us / 2,
+            encode_elapsed_us: client_elapsed_us - client_elapsed_us / 2,
+            server_elapsed_us,
+            commitment_elapsed_us: server_elapsed_us / 4,
+            verification_elapsed_us: server_elapsed_us / 4,
+            aggregation_elapsed_us: server_elapsed_us - 2 * (server_elapsed_us / 4),
+            peak_memory_kb: 0,
+            rejected_shares: 0,
+        }
+    }
+
+    #[test]
+    fn test_dimension_inflation() {
+        let mut previous = 0;
+        for epsilon in [0.8_f64, 0.4_f64, 0.2_f64, 0.1_f64, 0.05_f64, 0.025_f64].iter() {
+            let inflation = dimension_inflation(*epsilon);
+            assert_eq!(inflation, laplace::min_bits(1.0_f64, *epsilon).unwrap() + 1);
+            assert!(inflation > previous);
+            previous = inflation;
+        }
+
+        let mut rng = test_rng();
+        let epsilon = 0.1_f64;
+        let (priv_key1, priv_key2) = server_private_keys();
+        let client_values = sample_client_values(10, 0.0_f64, &mut rng);
+        let result = do_simulation(
+            true,
+            epsilon,
+            &client_values,
+            1,
+            priv_key1.clone(),
+            priv_key2.clone(),
+            &mut rng,
+        );
+        assert_eq!(result.dimension, dimension_inflation(epsilon));
+        assert_eq!(
+            result.dimension_inflation,
+            dimension_inflation(epsilon) as f64
+        );
+        let result = do_simulation(
+            false,
+            epsilon,
+            &client_values,
+            1,
+            priv_key1,
+            priv_key2,
+            &mut rng,
+        );
+        assert_eq!(result.dimension_inflation, 1.0_f64);
+    }
+
+    #[test]
+    fn test_sub_millisecond_timing() {
+        // A single client takes well under a millisecond, which used to be recorded as 0 ms.
+        let (priv_key1, priv_key2) = server_private_keys();
+        let result = do_simulation(
+            false,
+            0.8_f64,
+            &[1],
+            0,
+            priv_key1,
+            priv_key2,
+            &mut test_rng(),
+        );
+        assert!(result.client_elapsed_us > 0);
+        assert!(result.server_elapsed_us > 0);
+    }
+
+    #[test]
+    fn test_client_elapsed_breakdown() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        for do_dprio in [false, true] {
+            let result = do_simulation(
+                do_dprio,
+                0.8_f64,
+                &[1; 200],
+                2,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                &mut test_rng(),
+            );
+            // Each of the parts is rounded down to a whole number of microseconds separately.
+            assert!(result.setup_elapsed_us + result.encode_elapsed_us <= result.client_elapsed_us);
+            assert!(
+                result.setup_elapsed_us + result.encode_elapsed_us + 1 >= result.client_elapsed_us
+            );
+        }
+    }
+
+    #[test]
+    fn test_server_elapsed_breakdown() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        for do_dprio in [false, true] {
+            let result = do_simulation(
+                do_dprio,
+                0.8_f64,
+                &[1; 200],
+                2,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                &mut test_rng(),
+            );
+            // Only the structure of the timings is checked, as their values depend on the load of
+            // the machine. Every phase that does work is recorded (verifying and aggregating 200
+            // shares, and for dprio hashing the commitments, take well over a microsecond), and
+            // the parts, which are each rounded down to a whole number of microseconds, don't add
+            // up to more than the total, which also includes the time between them.
+            if do_dprio {
+                assert!(result.commitment_elapsed_us > 0);
+            }
+            assert!(result.verification_elapsed_us > 0);
+            assert!(result.aggregation_elapsed_us > 0);
+            let parts_elapsed = result.commitment_elapsed_us
+                + result.verification_elapsed_us
+                + result.aggregation_elapsed_us;
+            assert!(parts_elapsed <= result.server_elapsed_us);
+        }
+    }
+
+    #[test]
+    fn test_estimate_server_cpu() {
+        // A run with 96 responders and 4 noises of dimension 30, i.e. 3000 elements, timed across
+        // both servers.
+        let n_noises = 4;
+        let mut result = synthetic_results(0, 0);
+        result.n_responders = 96;
+        result.verification_elapsed_us = 120;
+        result.aggregation_elapsed_us = 60;
+        let calibration = calibration_from_results(&result, n_noises, (1000.0_f64, 500.0_f64));
+        assert_eq!(
+            calibration,
+            capacity::Calibration {
+                commitment_ns: 1000.0_f64,
+                validation_ns: 500.0_f64,
+                verification_per_element_ns: 20.0_f64,
+                aggregation_per_element_ns: 10.0_f64,
+            }
+        );
+
+        // Selecting the noises takes 4 * (1000 + 2 * 500) ns = 8 us, and verifying and
+        // aggregating takes half of the run's 180 us, the share of one server.
+        let estimate_us = 1000.0_f64
+            * capacity::estimate_server_cpu_millis(96, n_noises, result.dimension, &calibration);
+        assert!((estimate_us - 98.0_f64).abs() < 1e-9_f64);
+        // With 10 times as many clients, only the per-element costs grow.
+        let estimate_us = 1000.0_f64
+            * capacity::estimate_server_cpu_millis(996, n_noises, result.dimension, &calibration);
+        assert!((estimate_us - 908.0_f64).abs() < 1e-9_f64);
+    }
+
+    #[test]
+    fn test_estimate_server_cpu_matches_run() {
+        // The estimate is calibrated with one run and compared with the time a server took in
+        // another, twice as large. The times depend on the load of the machine, so they only have
+        // to agree within a factor of 3.
+        let n_noises = 4;
+        let (priv_key1, priv_key2) = server_private_keys();
+        let run = |n_clients: usize, rng: &mut StdRng| {
+            let client_values = sample_client_values(n_clients, 0.0_f64, rng);
+            do_simulation(
+                true,
+                0.8_f64,
+                &client_values,
+                n_noises,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                rng,
+            )
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        let calibration_result = run(200, &mut rng);
+        let calibration =
+            calibration_from_results(&calibration_result, n_noises, measure_commitment_costs(100));
+        let result = run(400, &mut rng);
+        let estimate_us = 1000.0_f64
+            * capacity::estimate_server_cpu_millis(
+                result.n_responders,
+                n_noises,
+                result.dimension,
+                &calibration,
+            );
+        let measured_us = result.server_elapsed_us as f64 / 2.0_f64;
+        assert!(
+            estimate_us <= 3.0_f64 * measured_us && measured_us <= 3.0_f64 * estimate_us,
+            "estimated {} us, measured {} us",
+            estimate_us,
+            measured_us
+        );
+    }
+
+    #[test]
+    fn test_commitment_costs_from_benches() {
+        let criterion_dir =
+            std::env::temp_dir().join(format!("dprio-criterion-{}", std::process::id()));
+        assert_eq!(commitment_costs_from_benches(&criterion_dir), None);
+        for (bench_dir, mean_ns) in [
+            ("commitment_new", 700.0_f64),
+            ("commitment_commit", 300.0_f64),
+            ("commitment_validate", 500.0_f64),
+        ] {
+            let estimates_dir = criterion_dir.join(bench_dir).join("new");
+            std::fs::create_dir_all(&estimates_dir).unwrap();
+            let estimates = serde_json::json!({ "mean": { "point_estimate": mean_ns } });
+            std::fs::write(estimates_dir.join("estimates.json"), estimates.to_string()).unwrap();
+        }
+        let commitment_costs = commitment_costs_from_benches(&criterion_dir);
+        std::fs::remove_dir_all(&criterion_dir).unwrap();
+        assert_eq!(commitment_costs, Some((1000.0_f64, 500.0_f64)));
+
+        let (commitment_ns, validation_ns) = measure_commitment_costs(10);
+        assert!(commitment_ns > 0.0_f64);
+        assert!(validation_ns > 0.0_f64);
+    }
+
+    #[test]
+    fn test_peak_memory() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let result = do_simulation(
+            true,
+            0.8_f64,
+            &[1; 200],
+            2,
+            priv_key1,
+            priv_key2,
+            &mut test_rng(),
+        );
+        if cfg!(all(feature = "memory-stats", target_os = "linux")) {
+            assert!(result.peak_memory_kb > 0);
+        } else {
+            assert_eq!(result.peak_memory_kb, 0);
+        }
+    }
+
+    #[test]
+    fn test_ablation_errors() {
+        let epsilon = 0.8_f64;
+        let n_noises = 4;
+        let (laplace_error, uniform_error) =
+            ablation_errors(epsilon, 20, n_noises, 3, &mut StdRng::seed_from_u64(233));
+        // The uniform noise is bounded, so the error is too (each noise is rounded to an integer
+        // of magnitude at most ceil(sqrt(6) / epsilon) = 4).
+        assert!(uniform_error <= (n_noises * 4) as f64);
+        assert!(laplace_error.is_finite());
+        // Without selected noise, there's no error either way.
+        assert_eq!(
+            ablation_errors(epsilon, 20, 0, 3, &mut StdRng::seed_from_u64(233)),
+            (0.0_f64, 0.0_f64)
+        );
+    }
+
+    #[test]
+    fn test_distribution_noise() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        let dimension = 8;
+        let shift_value = shift_value(true, dimension);
+        // Every sample rounds to 5.
+        let noise_distribution =
+            distribution_noise(rand::distributions::Uniform::new(4.6_f64, 5.4_f64));
+        let mut rng = test_rng();
+        for _ in 0..10 {
+            let client = ClientState::new(
+                dimension,
+                shift_value,
+                1,
+                Some(&noise_distribution),
+                &public_key1,
+                &public_key2,
+                &mut rng,
+            );
+            assert_eq!(
+                client.noise,
+                Some(to_bits(shift_value as usize + 5, dimension))
+            );
+        }
+        let client = ClientState::new(
+            dimension,
+            shift_value,
+            1,
+            None,
+            &public_key1,
+            &public_key2,
+            &mut rng,
+        );
+        assert_eq!(client.noise, None);
+    }
+
+    #[test]
+    fn test_golden_fixed_noise() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        // For epsilon = 0.8, the dimension is 6, so the shift value is 32.
+        let epsilon = 0.8_f64;
+        let client_values = [1, 0, 1, 1, 0];
+        let noise_values = vec![3, -2, 4, -1, 0];
+        let noise_distribution = fixed_noise(noise_values.clone());
+        let encoded_clients = encode_clients_with_noise(
+            epsilon,
+            Some(&noise_distribution),
+            &client_values,
+            &public_key1,
+            &public_key2,
+            &mut test_rng(),
+        );
+        assert_eq!(encoded_clients.dimension, 6);
+        assert_eq!(shift_value(true, 6), 32);
+        // The noise bits, least significant first.
+        let noise_distribution = fixed_noise(noise_values);
+        let expected_noise_bits = [
+            vec![1, 1, 0, 0, 0, 1],
+            vec![0, 1, 1, 1, 1, 0],
+            vec![0, 0, 1, 0, 0, 1],
+            vec![1, 1, 1, 1, 1, 0],
+            vec![0, 0, 0, 0, 0, 1],
+        ];
+        let mut rng = test_rng();
+        for (client_value, expected) in client_values.iter().zip(expected_noise_bits.iter()) {
+            let client = ClientState::new(
+                6,
+                32,
+                *client_value,
+                Some(&noise_distribution),
+                &public_key1,
+                &public_key2,
+                &mut rng,
+            );
+            assert_eq!(client.noise.as_ref(), Some(expected));
+        }
+
+        // Selecting every noise makes the selection irrelevant to the sum: the 3 of the client
+        // values plus the 4 of the noise.
+        let n_noises = client_values.len();
+        let result = run_servers(
+            encoded_clients,
+            n_noises,
+            priv_key1,
+            priv_key2,
+            &mut test_rng(),
+        );
+        assert_eq!(result.actual_sum, 3);
+        assert_eq!(result.calculated_sum, 7);
+    }
+
+    #[test]
+    fn test_noise_only() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        let epsilon = 0.1_f64;
+        let corpus_size = 50;
+        // With zero noise, the selected noise sums to 0 too, however much of it is selected.
+        for n_noises in [1, 10, corpus_size].iter() {
+            let encoded_providers = encode_noise_providers(
+                epsilon,
+                &fixed_noise(vec![0]),
+                corpus_size,
+                &public_key1,
+                &public_key2,
+                &mut test_rng(),
+            );
+            assert_eq!(encoded_providers.noise_for_server1.len(), corpus_size);
+            assert!(encoded_providers.shares_for_server1.is_empty());
+            let result = run_servers(
+                encoded_providers,
+                *n_noises,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                &mut test_rng(),
+            );
+            assert_eq!(result.n_responders, 0);
+            assert_eq!(result.actual_sum, 0);
+            assert_eq!(result.calculated_sum, 0);
+        }
+
+        // With Laplace noise, the result is just the selected noise.
+        let encoded_providers = encode_noise_providers(
+            epsilon,
+            &laplace_noise(epsilon),
+            corpus_size,
+            &public_key1,
+            &public_key2,
+            &mut test_rng(),
+        );
+        let result = run_servers(encoded_providers, 10, priv_key1, priv_key2, &mut test_rng());
+        assert_eq!(result.actual_sum, 0);
+        assert!(!exceeds_noise_bound(
+            &result,
+            &Params::new(epsilon, 0, 10, 1)
+        ));
+    }
+
+    #[test]
+    fn test_server_events() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        let n_clients = 20;
+        let encoded_clients = encode_clients(
+            false,
+            0.8_f64,
+            &vec![1; n_clients],
+            &public_key1,
+            &public_key2,
+            &mut test_rng(),
+        );
+        let counting_sink = events::CountingSink::new();
+        let mut server1 = ServerState::new(encoded_clients.dimension, true, priv_key1)
+            .with_event_sink(Box::new(counting_sink.clone()));
+        let mut server2 = ServerState::new(encoded_clients.dimension, false, priv_key2);
+        let eval_at = Field32::from(12313);
+        let server1_verifications =
+            server1.generate_verifications(&encoded_clients.shares_for_server1, eval_at);
+        let server2_verifications =
+            server2.generate_verifications(&encoded_clients.shares_for_server2, eval_at);
+        server1.aggregate(
+            encoded_clients.shares_for_server1,
+            &server1_verifications,
+            &server2_verifications,
+        );
+        assert_eq!(counting_sink.count(events::Event::ShareAccepted), n_clients);
+        assert_eq!(counting_sink.count(events::Event::ShareRejected), 0);
+        // A simulation reports the shares its servers rejected through their sinks.
+        let (priv_key1, priv_key2) = server_private_keys();
+        let result = do_simulation(
+            true,
+            0.8_f64,
+            &vec![1; n_clients],
+            2,
+            priv_key1,
+            priv_key2,
+            &mut test_rng(),
+        );
+        assert_eq!(result.rejected_shares, 0);
+    }
+
+    #[test]
+    fn test_multiple_studies() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        // Each study has its own dimension (and could have its own keys).
+        let studies = [
+            (StudyId(1), 1, vec![1, 0, 1, 1]),
+            (StudyId(2), 4, vec![3, 9, 15]),
+        ];
+        let mut server1 = MultiStudyServer::new(true);
+        let mut server2 = MultiStudyServer::new(false);
+        for (study_id, dimension, _) in &studies {
+            server1
+                .add_study(
+                    *study_id,
+                    *dimension,
+                    priv_key1.clone(),
+                    Box::new(events::NoopSink),
+                )
+                .unwrap();
+            server2
+                .add_study(
+                    *study_id,
+                    *dimension,
+                    priv_key2.clone(),
+                    Box::new(events::NoopSink),
+                )
+                .unwrap();
+        }
+        assert!(matches!(
+            server1.add_study(StudyId(1), 1, priv_key1.clone(), Box::new(events::NoopSink)),
+            Err(DprioError::DuplicateStudy)
+        ));
+        assert!(matches!(
+            server1.study(StudyId(3)),
+            Err(DprioError::UnknownStudy)
+        ));
+
+        // The shares of the studies arrive interleaved.
+        let eval_at = Field32::from(12313);
+        for i in 0..4 {
+            for (study_id, dimension, values) in &studies {
+                if let Some(value) = values.get(i) {
+                    let (share1, share2) = ClientState::with_values(
+                        *dimension,
+                        0,
+                        *value,
+                        None,
+                        &public_key1,
+                        &public_key2,
+                    )
+                    .get_shares();
+                    let study1 = server1.study(*study_id).unwrap();
+                    let study2 = server2.study(*study_id).unwrap();
+                    let verifications1 =
+                        study1.generate_verifications(std::slice::from_ref(&share1), eval_at);
+                    let verifications2 =
+                        study2.generate_verifications(std::slice::from_ref(&share2), eval_at);
+                    study1.aggregate(vec![share1], &verifications1, &verifications2);
+                    study2.aggregate(vec![share2], &verifications1, &verifications2);
+                }
+            }
+        }
+        for (study_id, _, values) in &studies {
+            let share_from_server1 = *server1.study(*study_id).unwrap().total_sum();
+            let share_from_server2 = *server2.study(*study_id).unwrap().total_sum();
+            let total = combine_total_shares(
+                server1.study(*study_id).unwrap(),
+                server2.study(*study_id).unwrap(),
+                &share_from_server1,
+                &share_from_server2,
+            )
+            .unwrap();
+            assert_eq!(u32::from(total) as usize, values.iter().sum::<usize>());
+        }
+    }
+
+    #[test]
+    fn test_max_representable_sum() {
+        // The modulus of Field32 is the prime 2^32 - 2^20 + 1.
+        assert_eq!(max_representable_sum::<Field32>(), 4_293_918_720);
+        assert_eq!(
+            max_representable_sum::<Field32>() as i128,
+            field_modulus() - 1
+        );
+        assert!(check_sum_fits::<Field32>(1_000_000, 12).is_ok());
+        // 2^12 - 1 contributions of 20 bits fit, but a single extra contribution doesn't.
+        assert!(check_sum_fits::<Field32>((1 << 12) - 1, 20).is_ok());
+        assert!(matches!(
+            check_sum_fits::<Field32>(1 << 12, 20),
+            Err(DprioError::StudyTooLarge)
+        ));
+        assert!(matches!(
+            check_sum_fits::<Field32>(1, 200),
+            Err(DprioError::StudyTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_checked_sum() {
+        let modulus = field_modulus();
+        let sum = CheckedSum::from_integer(1000).sub(CheckedSum::from_integer(400));
+        assert_eq!(sum.to_integer(), 600);
+        // A sum that wraps is still congruent to the integer, and can be used as a field element.
+        let sum = CheckedSum::from_integer(400).sub(CheckedSum::from_integer(1000));
+        assert_eq!(u32::from(sum.to_field()) as i128, modulus - 600);
+        assert_eq!(
+            u32::from(CheckedSum::from_field(Field32::from(7)).to_field()),
+            7
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "the field arithmetic wrapped around the modulus")]
+    fn test_checked_sum_wrapped() {
+        // Treating the difference as an integer assumes it can't be negative.
+        CheckedSum::from_integer(400)
+            .sub(CheckedSum::from_integer(1000))
+            .to_integer();
+    }
+
+    #[test]
+    fn test_total_mismatch() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        let n_clients = 20;
+        let client_values = vec![1; n_clients];
+        for corrupt in [false, true] {
+            let encoded_clients = encode_clients(
+                false,
+                0.8_f64,
+                &client_values,
+                &public_key1,
+                &public_key2,
+                &mut test_rng(),
+            );
+            let mut server1 = ServerState::new(encoded_clients.dimension, true, priv_key1.clone());
+            let mut server2 = ServerState::new(encoded_clients.dimension, false, priv_key2.clone());
+            let eval_at = Field32::from(12313);
+            let server1_verifications =
+                server1.generate_verifications(&encoded_clients.shares_for_server1, eval_at);
+            let server2_verifications =
+                server2.generate_verifications(&encoded_clients.shares_for_server2, eval_at);
+            server1.aggregate(
+                encoded_clients.shares_for_server1,
+                &server1_verifications,
+                &server2_verifications,
+            );
+            server2.aggregate(
+                encoded_clients.shares_for_server2,
+                &server1_verifications,
+                &server2_verifications,
+            );
+            let share_from_server1 = *server1.total_sum();
+            let mut share_from_server2 = *server2.total_sum();
+            if corrupt {
+                share_from_server2 += Field32::from(1);
+            }
+            let total = combine_total_shares(
+                &mut server1,
+                &mut server2,
+                &share_from_server1,
+                &share_from_server2,
+            );
+            if corrupt {
+                assert!(matches!(total, Err(DprioError::TotalMismatch)));
+            } else {
+                assert_eq!(u32::from(total.unwrap()) as usize, n_clients);
+            }
+        }
+    }
+
+    #[test]
+    fn test_prio_compatibility() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        assert!(check_prio_compatibility(&priv_key1, &priv_key2).is_ok());
+    }
+
+    #[test]
+    fn test_abstaining_clients() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        let n_clients = 300;
+        let epsilon = 0.8_f64;
+        let rng = &mut StdRng::seed_from_u64(226);
+        for do_dprio in [false, true] {
+            let client_values = sample_client_values(n_clients, 0.3_f64, rng);
+            let encoded_clients = encode_clients(
+                do_dprio,
+                epsilon,
+                &client_values,
+                &public_key1,
+                &public_key2,
+                rng,
+            );
+            let n_responders = encoded_clients.n_responders;
+            assert!(n_responders > 150 && n_responders < 270);
+            assert_eq!(encoded_clients.shares_for_server1.len(), n_responders);
+            if do_dprio {
+                assert_eq!(encoded_clients.noise_for_server1.len(), n_responders);
+            }
+            // Without noise, the sum over the responders is recovered exactly, which means the
+            // shift was removed for the responders only.
+            let result = run_servers(
+                encoded_clients.clone(),
+                0,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                rng,
+            );
+            assert_eq!(result.calculated_sum, result.actual_sum);
+            assert!(result.actual_sum <= n_responders);
+            // With the noise of every responder selected, the shift is removed for the selected
+            // noise too. Each noise is within noise_bound with overwhelming probability, and
+            // their sum is within sqrt(n_responders) times that (about 10 standard deviations of
+            // the sum), which is much smaller than the error of removing the shift of every
+            // abstaining client as well (a multiple of the shift value).
+            if do_dprio {
+                let shift_value = shift_value(true, encoded_clients.dimension);
+                let result = run_servers(
+                    encoded_clients,
+                    n_responders,
+                    priv_key1.clone(),
+                    priv_key2.clone(),
+                    rng,
+                );
+                let bound =
+                    (n_responders as f64).sqrt() * laplace::noise_bound(1.0_f64, epsilon).unwrap();
+                assert!(bound < ((n_clients - n_responders) as u64 * shift_value) as f64);
+                assert!((signed_error(&result).abs() as f64) < bound);
+            }
+        }
+    }
+
+    // The distribution of the sums DPrio should recover: the sum of the clients' values plus the
+    // sum of n_noises noise values selected uniformly at random without replacement from the
+    // corpus. This enumerates every sequence of selections, each of which is equally likely.
+    fn brute_force_expected(
+        client_values: &[usize],
+        corpus: &[i64],
+        n_noises: usize,
+    ) -> BTreeMap<i64, f64> {
+        fn enumerate(
+            remaining: &mut Vec<i64>,
+            n_noises: usize,
+            sum: i64,
+            probability: f64,
+            distribution: &mut BTreeMap<i64, f64>,
+        ) {
+            if n_noises == 0 {
+                *distribution.entry(sum).or_insert(0.0_f64) += probability;
+                return;
+            }
+            let n_remaining = remaining.len();
+            for i in 0..n_remaining {
+                let noise = remaining.remove(i);
+                enumerate(
+                    remaining,
+                    n_noises - 1,
+                    sum + noise,
+                    probability / n_remaining as f64,
+                    distribution,
+                );
+                remaining.insert(i, noise);
+            }
+        }
+        let mut distribution = BTreeMap::new();
+        let sum = client_values.iter().sum::<usize>() as i64;
+        enumerate(
+            &mut corpus.to_vec(),
+            n_noises,
+            sum,
+            1.0_f64,
+            &mut distribution,
+        );
+        distribution
+    }
+
+    #[test]
+    fn test_against_brute_force() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        let client_values = [1, 0, 1, 1];
+        let corpus = [-2, 0, 3, 7];
+        let n_noises = 2;
+        let dimension = 6;
+        let shift_value = 32;
+        let expected = brute_force_expected(&client_values, &corpus, n_noises);
+        assert_eq!(expected.len(), 6);
+
+        let n_runs = 3000;
+        let mut counts = BTreeMap::new();
+        for _ in 0..n_runs {
+            let mut key_fingerprints = Vec::new();
+            let mut shares_for_server1 = Vec::new();
+            let mut shares_for_server2 = Vec::new();
+            let mut noise_for_server1 = Vec::new();
+            let mut noise_for_server2 = Vec::new();
+            for (actual_value, noise_value) in client_values.iter().zip(corpus.iter()) {
+                let mut client = ClientState::with_values(
+                    dimension,
+                    shift_value,
+                    *actual_value,
+                    Some(*noise_value),
+                    &public_key1,
+                    &public_key2,
+                );
+                key_fingerprints.push(client.key_fingerprint);
+                let (share1, share2) = client.get_shares();
+                shares_for_server1.push(share1);
+                shares_for_server2.push(share2);
+                let (noise1, noise2) = client.get_noise().unwrap();
+                noise_for_server1.push(noise1);
+                noise_for_server2.push(noise2);
+            }
+            let encoded_clients = EncodedClients {
+                do_dprio: true,
+                dimension,
+                n_responders: client_values.len(),
+                actual_sum: client_values.iter().sum(),
+                key_fingerprints,
+                shares_for_server1,
+                shares_for_server2,
+                noise_for_server1,
+                noise_for_server2,
+                setup_elapsed: Duration::ZERO,
+                encode_elapsed: Duration::ZERO,
+            };
+            let result = run_servers(
+                encoded_clients,
+                n_noises,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                &mut test_rng(),
+            );
+            *counts.entry(result.calculated_sum as i64).or_insert(0) += 1;
+        }
+        for sum in counts.keys() {
+            assert!(expected.contains_key(sum));
+        }
+        for (sum, probability) in expected {
+            let frequency = *counts.get(&sum).unwrap_or(&0) as f64 / n_runs as f64;
+            // Five standard errors of the frequency.
+            let tolerance =
+                5.0_f64 * (probability * (1.0_f64 - probability) / n_runs as f64).sqrt();
+            assert!((frequency - probability).abs() < tolerance);
+        }
+    }
+
+    #[test]
+    fn test_shared_client_population() {
+        let params = Params::new(0.8_f64, 100, 2, 5);
+        for reuse_client_shares in [false, true] {
+            let results = do_simulation_with_params(
+                params,
+                reuse_client_shares,
+                rand::thread_rng().gen::<u64>(),
+                None,
+            );
+            assert_eq!(results.prio_results.len(), params.trials);
+            assert_eq!(results.dprio_results.len(), params.trials);
+            for (prio_result, dprio_result) in results
+                .prio_results
+                .iter()
+                .zip(results.dprio_results.iter())
+            {
+                assert_eq!(prio_result.actual_sum, dprio_result.actual_sum);
+            }
+        }
+    }
+
+    #[test]
+    fn test_seeded_simulation() {
+        let params = Params::new(0.8_f64, 50, 2, 2);
+        let first_results = do_simulation_with_params(params, false, 7, None);
+        let second_results = do_simulation_with_params(params, false, 7, None);
+        for (first_result, second_result) in first_results
+            .prio_results
+            .iter()
+            .zip(second_results.prio_results.iter())
+        {
+            assert_eq!(first_result.calculated_sum, second_result.calculated_sum);
+            assert_eq!(first_result.actual_sum, second_result.actual_sum);
+        }
+        // Which noise is selected is reproduced too.
+        for (first_result, second_result) in first_results
+            .dprio_results
+            .iter()
+            .zip(second_results.dprio_results.iter())
+        {
+            assert_eq!(first_result.calculated_sum, second_result.calculated_sum);
+            assert_eq!(first_result.actual_sum, second_result.actual_sum);
+        }
+    }
+
+    #[test]
+    fn test_only_trial() {
+        assert_eq!(trial_seed(7, 2), trial_seed(7, 2));
+        assert_ne!(trial_seed(7, 2), trial_seed(7, 3));
+        assert_ne!(trial_seed(7, 2), trial_seed(8, 2));
+
+        // Running a single trial reproduces that trial of the full run, whether or not the trials
+        // reuse the clients' shares.
+        let params = Params::new(0.8_f64, 50, 2, 3);
+        let master_seed = rand::thread_rng().gen::<u64>();
+        for reuse_client_shares in [false, true] {
+            let results = do_simulation_with_params(params, reuse_client_shares, master_seed, None);
+            for trial_index in 0..params.trials {
+                let trial_results = do_simulation_with_params(
+                    params,
+                    reuse_client_shares,
+                    master_seed,
+                    Some(trial_index),
+                );
+                assert_eq!(trial_results.params.trials, 1);
+                assert_eq!(trial_results.prio_results.len(), 1);
+                assert_eq!(trial_results.dprio_results.len(), 1);
+                let (prio_result, dprio_result) = (
+                    &trial_results.prio_results[0],
+                    &trial_results.dprio_results[0],
+                );
+                assert_eq!(
+                    prio_result.calculated_sum,
+                    results.prio_results[trial_index].calculated_sum
+                );
+                assert_eq!(
+                    prio_result.actual_sum,
+                    results.prio_results[trial_index].actual_sum
+                );
+                assert_eq!(
+                    dprio_result.actual_sum,
+                    results.dprio_results[trial_index].actual_sum
+                );
+                // The noised sum depends on the trial's noise and on the commitments selecting it.
+                assert_eq!(
+                    dprio_result.calculated_sum,
+                    results.dprio_results[trial_index].calculated_sum
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_dp_mean() {
+        let params = Params::new(0.8_f64, 200, 2, 3);
+        let results =
+            do_simulation_with_params(params, false, rand::thread_rng().gen::<u64>(), None);
+        for result in results.dprio_results.iter() {
+            assert_eq!(result.n_responders, params.clients);
+            let mean = dp_mean(result, &params, 1.0_f64 - 1e-6_f64).unwrap();
+            assert_eq!(mean.count, params.clients);
+            let true_mean = result.actual_sum as f64 / result.n_responders as f64;
+            assert!(mean.confidence_interval.0 <= true_mean);
+            assert!(true_mean <= mean.confidence_interval.1);
+            // With 200 clients, the noise of the mean is 200 times smaller than that of the sum.
+            let sum_noise_bound = 2.0_f64 * (2.0_f64 / 1e-6_f64).ln() / params.epsilon;
+            assert!((mean.value - true_mean).abs() <= sum_noise_bound / 200.0_f64);
+        }
+
+        // Nobody responded, so there's no mean.
+        let mut result = synthetic_results(3, 0);
+        result.n_responders = 0;
+        assert!(dp_mean(&result, &params, 0.95_f64).is_err());
+    }
+
+    #[test]
+    fn test_key_fingerprints() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        let server_key_fingerprint = KeyFingerprint::new(&public_key1, &public_key2);
+        let client = ClientState::with_values(1, 0, 1, None, &public_key1, &public_key2);
+        assert_eq!(client.key_fingerprint, server_key_fingerprint);
+        // The order of the keys matters, as each share is meant for a particular server.
+        assert_ne!(
+            KeyFingerprint::new(&public_key2, &public_key1),
+            server_key_fingerprint
+        );
+
+        // A client misconfigured with another public key is rejected before any verification.
+        let misconfigured_client =
+            ClientState::with_values(1, 0, 1, None, &public_key1, &public_key1);
+        assert!(check_key_fingerprints(server_key_fingerprint, &[client.key_fingerprint]).is_ok());
+        assert!(matches!(
+            check_key_fingerprints(
+                server_key_fingerprint,
+                &[client.key_fingerprint, misconfigured_client.key_fingerprint]
+            ),
+            Err(DprioError::PublicKeyMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_evaluation_harness() {
+        let mut harness = EvaluationHarness::new();
+        assert_eq!(harness.true_sum(), 0);
+        let client_values = [1, 0, 1, 1, 0, 1];
+        for client_value in client_values.iter() {
+            harness.record_client(*client_value);
+        }
+        assert_eq!(harness.true_sum(), 4);
+        assert_eq!(harness.n_clients(), client_values.len());
+
+        // The ground truth reported with a noised result is the sum of the client values, however
+        // the noise turns out.
+        let mut rng = test_rng();
+        let (priv_key1, priv_key2) = server_private_keys();
+        for _ in 0..5 {
+            let result = do_simulation(
+                true,
+                0.5_f64,
+                &client_values,
+                2,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                &mut rng,
+            );
+            assert_eq!(result.actual_sum, harness.true_sum());
+        }
+    }
+
+    #[test]
+    fn test_reused_client_shares() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let public_key1 = PublicKey::from(&priv_key1);
+        let public_key2 = PublicKey::from(&priv_key2);
+        let n_clients = 50;
+        let client_values = vec![1; n_clients];
+        let prio_clients = encode_clients(
+            false,
+            0.8_f64,
+            &client_values,
+            &public_key1,
+            &public_key2,
+            &mut test_rng(),
+        );
+        // Selecting every client's noise makes the dprio result independent of which noise is
+        // selected when.
+        let dprio_clients = encode_clients(
+            true,
+            0.8_f64,
+            &client_values,
+            &public_key1,
+            &public_key2,
+            &mut test_rng(),
+        );
+        for (encoded_clients, n_noises) in [(prio_clients, 0), (dprio_clients, n_clients)] {
+            let first_result = run_servers(
+                encoded_clients.clone(),
+                n_noises,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                &mut test_rng(),
+            );
+            for _ in 0..3 {
+                let result = run_servers(
+                    encoded_clients.clone(),
+                    n_noises,
+                    priv_key1.clone(),
+                    priv_key2.clone(),
+                    &mut test_rng(),
+                );
+                assert_eq!(result.calculated_sum, first_result.calculated_sum);
+                assert_eq!(result.actual_sum, first_result.actual_sum);
+            }
+        }
+    }
+
+    #[test]
+    fn test_minimize() {
+        let failing_params = Params::new(0.1_f64, 10_000, 14, 50);
+        let mut evaluations = 0;
+        let minimized_params = minimize(failing_params, 1234, |params, seed| {
+            assert_eq!(seed, 1234);
+            evaluations += 1;
+            params.clients >= 37
+        })
+        .unwrap();
+        assert_eq!(minimized_params.clients, 37);
+        assert_eq!(minimized_params.noises, 0);
+        assert_eq!(minimized_params.trials, 1);
+        assert_eq!(minimized_params.epsilon, 0.1_f64);
+        // Bisection shouldn't need anywhere near one evaluation per client.
+        assert!(evaluations < 100);
+
+        // A failure that needs some noise and more clients than noises is preserved.
+        let minimized_params = minimize(failing_params, 1234, |params, _seed| {
+            params.noises >= 3 && params.clients >= 2 * params.noises
+        })
+        .unwrap();
+        assert_eq!(minimized_params.noises, 3);
+        assert_eq!(minimized_params.clients, 6);
+
+        assert!(minimize(failing_params, 1234, |_params, _seed| false).is_none());
+    }
+
+    #[test]
+    fn test_latex_tables() {
+        let results_batch = vec![
+            BatchResults {
+                params: Params::new(0.1_f64, 1000, 10, 2),
+                prio_results: vec![
+                    synthetic_timed_results(false, 0, 10, 100),
+                    synthetic_timed_results(false, 0, 30, 300),
+                ],
+                dprio_results: vec![
+                    synthetic_timed_results(true, 3, 25, 250),
+                    synthetic_timed_results(true, 6, 35, 251),
+                ],
+            },
+            BatchResults {
+                params: Params::new(0.2_f64, 100, 7, 1),
+                prio_results: vec![synthetic_timed_results(false, 0, 3, 40)],
+                dprio_results: vec![synthetic_timed_results(true, 10, 4, 50)],
+            },
+        ];
+        let expected_server_table = "\\begin{tabular}{|c|c|c|c|c|c|c|} \\hline
+$\\epsilon$ & Population Size & Client Noises Selected & Prio Server Processing Time ($\\mu$s) & \\dpprio Server Processing Time ($\\mu$s) & Overhead (\\%) & Error \\\\ \\hline
+0.1 & 1000 & 10 & 200.0 & 250.5 & 25.25\\% & 4.5 \\\\ \\hline
+0.2 & 100 & 7 & 40.0 & 50.0 & 25.00\\% & 10.0 \\\\ \\hline
+\\end{tabular}
+";
+        assert_eq!(
+            server_table(&results_batch).to_string(),
+            expected_server_table
+        );
+        let expected_client_table = "\\begin{tabular}{|c|c|c|c|} \\hline
+$\\epsilon$ & Population Size & Client Noises Selected & Client Overhead (\\%) \\\\ \\hline
+0.1 & 1000 & 10 & 50.00\\% \\\\ \\hline
+0.2 & 100 & 7 & 33.33\\% \\\\ \\hline
+\\end{tabular}
+";
+        assert_eq!(
+            client_table(&results_batch).to_string(),
+            expected_client_table
+        );
+    }
+
+    #[test]
+    fn test_latex_from_saved_results() {
+        let results_batches = vec![
+            vec![
+                BatchResults {
+                    params: Params::new(0.1_f64, 1000, 10, 2),
+                    prio_results: vec![
+                        synthetic_timed_results(false, 0, 10, 100),
+                        synthetic_timed_results(false, 0, 30, 300),
+                    ],
+                    dprio_results: vec![
+                        synthetic_timed_results(true, 3, 25, 250),
+                        synthetic_timed_results(true, 6, 35, 251),
+                    ],
+                },
+                BatchResults {
+                    params: Params::new(0.025_f64, 100, 7, 1),
+                    prio_results: vec![synthetic_timed_results(false, 0, 3, 40)],
+                    dprio_results: vec![synthetic_timed_results(true, 10, 4, 50)],
+                },
+            ],
+            vec![BatchResults {
+                params: Params::new(0.8_f64, 10, 1, 1),
+                prio_results: vec![synthetic_timed_results(false, 0, 1, 7)],
+                dprio_results: vec![synthetic_timed_results(true, 1, 2, 9)],
+            }],
+        ];
+        let saved_results = serde_json::to_string(&results_batches).unwrap();
+        let loaded_results_batches: Vec<Vec<BatchResults>> =
+            serde_json::from_str(&saved_results).unwrap();
+        assert_eq!(loaded_results_batches.len(), results_batches.len());
+        for (loaded_results_batch, results_batch) in
+            loaded_results_batches.iter().zip(results_batches.iter())
+        {
+            assert_eq!(
+                latex_from_results(loaded_results_batch),
+                latex_from_results(results_batch)
+            );
+        }
+        assert!(latex_from_results(&results_batches[0])
+            .contains("(dprio error from noise/sampling: 4.5/0.0 10.0/450.0)"));
+        assert!(latex_from_results(&results_batches[0])
+            .contains("(dprio server overhead 95% confidence intervals, in %: ["));
+    }
+
+    #[test]
+    fn test_t_quantile_95() {
+        // Exact quantiles of Student's t distribution.
+        for (df, expected) in [
+            (3.0_f64, 3.182_f64),
+            (10.0_f64, 2.228_f64),
+            (30.0_f64, 2.042_f64),
+        ]
+        .iter()
+        {
+            assert!((t_quantile_95(*df) - expected).abs() / expected < 0.01_f64);
+        }
+        assert!((t_quantile_95(1e9_f64) - Z_95).abs() < 1e-6_f64);
+    }
+
+    #[test]
+    fn test_overhead_confidence_interval() {
+        // Server times spread evenly around 1000us for prio and 1200us for dprio, for a true
+        // overhead of 20%.
+        let timed_results = |dprio: bool, n_trials: usize| -> Vec<Results> {
+            let mean = if dprio { 1200 } else { 1000 };
+            (0..n_trials)
+                .map(|i| synthetic_timed_results(dprio, 0, 10, mean - 100 + (i % 5) as u128 * 50))
+                .collect()
+        };
+        let mut previous_width = f64::INFINITY;
+        for n_trials in [5, 10, 20, 40, 80].iter() {
+            let (low, high) = overhead_confidence_interval(
+                &timed_results(false, *n_trials),
+                &timed_results(true, *n_trials),
+            );
+            assert!(low < 20.0_f64 && 20.0_f64 < high);
+            assert!(high - low < previous_width);
+            previous_width = high - low;
+        }
+        // With this many trials, the overhead is clearly real.
+        let (low, _) =
+            overhead_confidence_interval(&timed_results(false, 80), &timed_results(true, 80));
+        assert!(low > 0.0_f64);
+
+        assert_eq!(
+            overhead_confidence_interval(&timed_results(false, 1), &timed_results(true, 5)),
+            (f64::NEG_INFINITY, f64::INFINITY)
+        );
+        let constant: Vec<Results> = (0..3)
+            .map(|_| synthetic_timed_results(false, 0, 10, 100))
+            .collect();
+        let (low, high) = overhead_confidence_interval(&constant, &constant);
+        assert_eq!((low, high), (0.0_f64, 0.0_f64));
+    }
+
+    #[test]
+    fn test_signed_error() {
+        assert_eq!(signed_error(&synthetic_results(110, 100)), 10);
+        assert_eq!(signed_error(&synthetic_results(90, 100)), -10);
+        // A noised sum of -3 is calculated as the modulus - 3.
+        assert_eq!(signed_error(&synthetic_results(4293918718, 2)), -5);
+        let params = Params::new(0.1_f64, 100, 1, 1);
+        assert!(!exceeds_noise_bound(
+            &synthetic_results(4293918718, 2),
+            &params
+        ));
+        assert!(exceeds_noise_bound(&synthetic_results(1000, 2), &params));
+    }
+
+    #[test]
+    fn test_error_histogram() {
+        let results = vec![
+            synthetic_results(100, 100),
+            synthetic_results(104, 100),
+            synthetic_results(109, 100),
+            synthetic_results(110, 100),
+            synthetic_results(99, 100),
+            synthetic_results(90, 100),
+            synthetic_results(89, 100),
+            // A noised sum of -1, which wrapped around.
+            synthetic_results(4293918720, 5),
+        ];
+        let histogram = ErrorHistogram::new(&results, 10);
+        let expected_counts: BTreeMap<i64, usize> =
+            vec![(-2, 1), (-1, 3), (0, 3), (1, 1)].into_iter().collect();
+        assert_eq!(histogram.counts, expected_counts);
+        assert_eq!(
+            histogram.to_string(),
+            "[-20, -10): 1\n[-10, 0): 3\n[0, 10): 3\n[10, 20): 1\n"
+        );
+        assert_eq!(ErrorHistogram::new(&[], 10).to_string(), "");
+    }
+
+    #[test]
+    fn test_error_decomposition() {
+        let (priv_key1, priv_key2) = server_private_keys();
+        let run = |params: &Params, seed: u64| {
+            let rng = &mut StdRng::seed_from_u64(seed);
+            let client_values = sample_client_values(params.clients, 0.0_f64, rng);
+            let result = do_simulation(
+                true,
+                params.epsilon,
+                &client_values,
+                params.noises,
+                priv_key1.clone(),
+                priv_key2.clone(),
+                rng,
+            );
+            error_decomposition(&result, params)
+        };
+
+        // With a large population and little noise, a typical deviation of the actual sum from
+        // the expected count (one standard deviation is sqrt(n * p * (1 - p)), about 22 here)
+        // dwarfs the noise.
+        let params = Params::new(5.0_f64, 2000, 1, 1);
+        let (noise, sampling) = run(&params, 17);
+        assert!(sampling > noise);
+
+        // With a tiny population and a lot of noise, the noise dominates.
+        let params = Params::new(0.1_f64, 3, 3, 1);
+        let (noise, sampling) = run(&params, 17);
+        assert!(sampling <= 1.5_f64);
+        assert!(noise > sampling);
+
+        // A negative noised sum is a negative error rather than one wrapped around the modulus.
+        let (noise, _) = error_decomposition(
+            &synthetic_results(4293918718, 2),
+            &Params::new(0.1_f64, 4, 1, 1),
+        );
+        assert_eq!(noise, 5.0_f64);
     }
 }
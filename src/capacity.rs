@@ -0,0 +1,83 @@
+// A model of the CPU time a server spends on one aggregation, for capacity planning. The costs of
+// the individual operations are measured once (e.g. with `cargo bench --bench commitment` for the
+// commitment protocol, and from the timing breakdown of the comparison example for the prio
+// verification and aggregation) and the model extrapolates them to other client counts, numbers of
+// noises, and dimensions.
+//
+// Per server, selecting each noise takes one commitment (creating and closing it) and the
+// validation of every server's opened commitment, and every contribution (each client's share and
+// each selected noise) is verified and aggregated element by element. Fixed costs (e.g. combining
+// the total shares) are negligible at any scale worth planning for, so they're not modeled.
+
+// The number of servers taking part in the protocol.
+const N_SERVERS: f64 = 2.0_f64;
+
+// The costs of the operations a server performs, in nanoseconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    // Creating and closing one commitment (`Commitment::new` and `Commitment::commit`).
+    pub commitment_ns: f64,
+    // Validating one opened commitment (`ClosedCommitment::validate`).
+    pub validation_ns: f64,
+    // Verifying one element of one contribution.
+    pub verification_per_element_ns: f64,
+    // Aggregating one element of one contribution.
+    pub aggregation_per_element_ns: f64,
+}
+
+// Returns the estimated CPU time, in milliseconds, one server spends on an aggregation of
+// n_clients clients' values of the given dimension with n_noises selected noises.
+pub fn estimate_server_cpu_millis(
+    n_clients: usize,
+    n_noises: usize,
+    dimension: usize,
+    calibration: &Calibration,
+) -> f64 {
+    let n_noises = n_noises as f64;
+    let selection_ns =
+        n_noises * (calibration.commitment_ns + N_SERVERS * calibration.validation_ns);
+    let n_elements = (n_clients as f64 + n_noises) * dimension as f64;
+    let contribution_ns = n_elements
+        * (calibration.verification_per_element_ns + calibration.aggregation_per_element_ns);
+    (selection_ns + contribution_ns) / 1e6_f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calibration() -> Calibration {
+        Calibration {
+            commitment_ns: 1000.0_f64,
+            validation_ns: 500.0_f64,
+            verification_per_element_ns: 20.0_f64,
+            aggregation_per_element_ns: 10.0_f64,
+        }
+    }
+
+    #[test]
+    fn test_estimate_server_cpu_millis() {
+        // 10 noises take 10 * (1000 + 2 * 500) ns = 20000 ns to select, and 1010 contributions
+        // of 8 elements take 1010 * 8 * 30 ns = 242400 ns to verify and aggregate.
+        let estimate = estimate_server_cpu_millis(1000, 10, 8, &calibration());
+        assert!((estimate - 0.2624_f64).abs() < 1e-9_f64);
+        assert_eq!(estimate_server_cpu_millis(0, 0, 8, &calibration()), 0.0_f64);
+
+        // The estimate grows with each input.
+        let base = estimate_server_cpu_millis(1000, 10, 8, &calibration());
+        for (n_clients, n_noises, dimension) in
+            [(2000, 10, 8), (1000, 20, 8), (1000, 10, 16)].iter()
+        {
+            assert!(
+                estimate_server_cpu_millis(*n_clients, *n_noises, *dimension, &calibration())
+                    > base
+            );
+        }
+        for n_clients in 0..100 {
+            assert!(
+                estimate_server_cpu_millis(n_clients + 1, 10, 8, &calibration())
+                    > estimate_server_cpu_millis(n_clients, 10, 8, &calibration())
+            );
+        }
+    }
+}
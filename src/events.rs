@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// The protocol events reported to an EventSink.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Event {
+    // A closed commitment was submitted to a reveal round and accepted.
+    CommitmentReceived,
+    // A closed commitment was rejected, either when it was submitted or because it was replayed.
+    CommitmentRejected,
+    // A published value opened its commitment.
+    OpeningValidated,
+    // A published value didn't open its commitment (or was out of range).
+    OpeningRejected,
+    // A reveal round selected an index.
+    IndexSelected,
+    // A server aggregated a client's share.
+    ShareAccepted,
+    // A server rejected a client's share because it didn't verify.
+    ShareRejected,
+}
+
+// Receives the events of the protocol as they happen. Integrators implement this to feed the
+// events into their metrics system (e.g. by incrementing a Prometheus counter per event). Sinks are
+// Send, so that the protocol state holding one (e.g. a `RevealRound`) can be moved between the
+// threads of a server.
+pub trait EventSink: Send {
+    fn record(&mut self, event: Event);
+}
+
+// Discards every event. This is the sink used unless another one is configured.
+pub struct NoopSink;
+
+impl EventSink for NoopSink {
+    fn record(&mut self, _event: Event) {}
+}
+
+// Counts the events of each kind. Clones share their counts, so a clone can be handed to the
+// protocol while the original is used to read the counts, including from another thread.
+#[derive(Clone, Default)]
+pub struct CountingSink {
+    counts: Arc<Mutex<HashMap<Event, usize>>>,
+}
+
+impl CountingSink {
+    pub fn new() -> CountingSink {
+        CountingSink::default()
+    }
+
+    pub fn count(&self, event: Event) -> usize {
+        *self.counts.lock().unwrap().get(&event).unwrap_or(&0)
+    }
+}
+
+impl EventSink for CountingSink {
+    fn record(&mut self, event: Event) {
+        *self.counts.lock().unwrap().entry(event).or_insert(0) += 1;
+    }
+}
@@ -0,0 +1,247 @@
+// Exact samplers for the discrete Laplace (two-sided geometric) and discrete Gaussian
+// distributions, which use only integer arithmetic and uniformly random integers.
+// The samplers in `laplace` compute with f64, so the distribution they sample from is only an
+// approximation of the discrete Laplace distribution, and the ways in which floating point
+// arithmetic deviates from it can leak information about the data being protected. This sampler
+// draws from exactly the discrete Laplace distribution with the rational parameter
+// lambda = num / den, i.e. Pr[x] is proportional to e^(-lambda |x|), so it's free of this attack
+// surface (at the cost of being slower).
+// This follows Canonne, Kamath, and Steinke, "The Discrete Gaussian for Differential Privacy"
+// (https://arxiv.org/abs/2004.00010), Algorithms 1 and 2 (and Algorithm 3 for the discrete
+// Gaussian, which `gaussian` builds on).
+// Clippy rejects any floating point arithmetic in this module outside of the tests, which compare
+// against the f64 sampler.
+#![cfg_attr(not(test), deny(clippy::float_arithmetic))]
+
+use rand::Rng;
+
+use crate::ParameterError;
+
+// Returns true with probability num / den, where 0 <= num <= den.
+fn sample_bernoulli<R: Rng>(rng: &mut R, num: u128, den: u128) -> bool {
+    rng.gen_range(0, den) < num
+}
+
+// Returns true with probability e^(-num / den), for num <= den.
+fn sample_bernoulli_exp_at_most_1<R: Rng>(rng: &mut R, num: u128, den: u128) -> bool {
+    let mut k: u128 = 1;
+    loop {
+        // The probability num / (den * k) is at most 1.
+        match den.checked_mul(k) {
+            Some(den_k) if sample_bernoulli(rng, num, den_k) => k += 1,
+            _ => break,
+        }
+    }
+    k % 2 == 1
+}
+
+// Returns true with probability e^(-num / den).
+fn sample_bernoulli_exp<R: Rng>(rng: &mut R, num: u128, den: u128) -> bool {
+    for _ in 0..num / den {
+        if !sample_bernoulli_exp_at_most_1(rng, 1, 1) {
+            return false;
+        }
+    }
+    sample_bernoulli_exp_at_most_1(rng, num % den, den)
+}
+
+// Draws a sample from the discrete Laplace distribution with parameter lambda = num / den, i.e.
+// Pr[x] is proportional to e^(-lambda |x|). This is the distribution `laplace` approximates with
+// f64 for the lambda returned by `laplace::epsilon_to_lambda`.
+pub fn sample_discrete_laplace<R: Rng>(
+    rng: &mut R,
+    num: u64,
+    den: u64,
+) -> Result<i64, ParameterError> {
+    if num == 0 || den == 0 {
+        return Err(ParameterError::InvalidFraction);
+    }
+    // In terms of the paper, the scale is t / s = 1 / lambda = den / num.
+    let (s, t) = (num, den);
+    loop {
+        let u = rng.gen_range(0, t);
+        if !sample_bernoulli_exp(rng, u.into(), t.into()) {
+            continue;
+        }
+        let mut v: u64 = 0;
+        while sample_bernoulli_exp_at_most_1(rng, 1, 1) {
+            v += 1;
+        }
+        let x = match t.checked_mul(v).and_then(|tv| tv.checked_add(u)) {
+            Some(x) => x,
+            // This is so unlikely that resampling doesn't measurably change the distribution.
+            None => continue,
+        };
+        let y = x / s;
+        let negative = sample_bernoulli(rng, 1, 2);
+        if negative && y == 0 {
+            continue;
+        }
+        if y > i64::MAX as u64 {
+            continue;
+        }
+        return Ok(if negative { -(y as i64) } else { y as i64 });
+    }
+}
+
+// Returns floor(sqrt(x)).
+fn isqrt(x: u64) -> u64 {
+    // Binary search for the largest r with r^2 <= x, which is at most 2^32 - 1.
+    let (mut low, mut high) = (0_u64, 1_u64 << 32);
+    while low + 1 < high {
+        let mid = low + (high - low) / 2;
+        if mid * mid <= x {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+// Draws a sample from the discrete Gaussian distribution with parameter sigma^2 = num / den, i.e.
+// Pr[x] is proportional to e^(-x^2 / (2 sigma^2)). Candidates are drawn from the discrete Laplace
+// distribution with scale t = floor(sigma) + 1 and accepted with probability
+// e^(-(|y| - sigma^2 / t)^2 / (2 sigma^2)).
+pub fn sample_discrete_gaussian<R: Rng>(
+    rng: &mut R,
+    num: u64,
+    den: u64,
+) -> Result<i64, ParameterError> {
+    if num == 0 || den == 0 {
+        return Err(ParameterError::InvalidFraction);
+    }
+    // floor(sqrt(num / den)) = floor(sqrt(floor(num / den))).
+    let t = isqrt(num / den) + 1;
+    // The exponent is (|y| * den * t - num)^2 / (2 * num * den * t^2).
+    let (num, den, t_u128) = (u128::from(num), u128::from(den), u128::from(t));
+    let exponent_den = 2_u128
+        .checked_mul(num)
+        .and_then(|x| x.checked_mul(den))
+        .and_then(|x| x.checked_mul(t_u128))
+        .and_then(|x| x.checked_mul(t_u128))
+        .ok_or(ParameterError::Overflow)?;
+    loop {
+        let y = sample_discrete_laplace(rng, 1, t)?;
+        let exponent_num = u128::from(y.unsigned_abs())
+            .checked_mul(den * t_u128)
+            .map(|x| x.abs_diff(num))
+            .and_then(|x| x.checked_mul(x));
+        match exponent_num {
+            Some(exponent_num) if sample_bernoulli_exp(rng, exponent_num, exponent_den) => {
+                return Ok(y)
+            }
+            // If the exponent overflows, the candidate would be accepted with a probability so
+            // small that rejecting it doesn't measurably change the distribution.
+            _ => continue,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn test_bernoulli_exp() {
+        let mut rng = seeded_rng(1);
+        let n_samples = SAMPLE_COUNT;
+        for (num, den) in [(1, 2), (1, 1), (5, 2)].iter() {
+            let expected = (-(*num as f64) / *den as f64).exp();
+            let count = (0..n_samples)
+                .filter(|_| sample_bernoulli_exp(&mut rng, *num, *den))
+                .count();
+            assert!(
+                (count as f64 / n_samples as f64 - expected).abs()
+                    < frequency_tolerance(expected, n_samples)
+            );
+        }
+    }
+
+    #[test]
+    fn test_discrete_laplace() {
+        assert!(sample_discrete_laplace(&mut rand::thread_rng(), 0, 1).is_err());
+        assert!(sample_discrete_laplace(&mut rand::thread_rng(), 1, 0).is_err());
+
+        // Compare against the f64 sampler with the same lambda = 1 / 2 and the exact moments: the
+        // discrete Laplace distribution has mean 0, Pr[0] = (1 - q) / (1 + q), and variance
+        // 2q / (1 - q)^2, where q = e^(-lambda).
+        let (num, den) = (1, 2);
+        let lambda = num as f64 / den as f64;
+        let q = (-lambda).exp();
+        let expected_zero_frequency = (1.0_f64 - q) / (1.0_f64 + q);
+        let expected_variance = 2.0_f64 * q / (1.0_f64 - q).powi(2);
+        // The kurtosis of the discrete Laplace distribution is close to that of the Laplace
+        // distribution (6) for small lambda, and smaller for larger lambda.
+        let kurtosis = 6.0_f64;
+
+        let n_samples = SAMPLE_COUNT;
+        let mut rng = seeded_rng(2);
+        let exact_samples: Vec<i64> = (0..n_samples)
+            .map(|_| sample_discrete_laplace(&mut rng, num, den).unwrap())
+            .collect();
+        let f64_samples =
+            crate::laplace::two_sided_geometric_samples(&mut rng, lambda, n_samples).unwrap();
+        for samples in [exact_samples, f64_samples].iter() {
+            let mean = samples.iter().sum::<i64>() as f64 / n_samples as f64;
+            assert!(mean.abs() < mean_tolerance(expected_variance.sqrt(), n_samples));
+            let variance = samples.iter().map(|x| (*x as f64).powi(2)).sum::<f64>()
+                / n_samples as f64
+                - mean.powi(2);
+            assert!(
+                (variance - expected_variance).abs()
+                    < variance_tolerance(expected_variance, kurtosis, n_samples)
+            );
+            let zero_frequency =
+                samples.iter().filter(|x| **x == 0).count() as f64 / n_samples as f64;
+            assert!(
+                (zero_frequency - expected_zero_frequency).abs()
+                    < frequency_tolerance(expected_zero_frequency, n_samples)
+            );
+        }
+    }
+
+    #[test]
+    fn test_isqrt() {
+        for x in [0, 1, 2, 3, 4, 15, 16, 17, 1 << 40, u64::MAX].iter() {
+            let r = isqrt(*x) as u128;
+            assert!(r * r <= *x as u128);
+            assert!((r + 1) * (r + 1) > *x as u128);
+        }
+    }
+
+    #[test]
+    fn test_discrete_gaussian() {
+        assert!(sample_discrete_gaussian(&mut rand::thread_rng(), 0, 1).is_err());
+        assert!(sample_discrete_gaussian(&mut rand::thread_rng(), 1, 0).is_err());
+
+        // With sigma^2 = 9 / 4, the discrete Gaussian distribution has mean 0 and (to within
+        // 10^-15) variance sigma^2, and Pr[0] = 1 / sum_x e^(-x^2 / (2 sigma^2)). Its kurtosis is
+        // that of the normal distribution, 3.
+        for (num, den) in [(9, 4), (1, 1), (400, 1)].iter() {
+            let sigma_squared = *num as f64 / *den as f64;
+            let expected_zero_frequency = 1.0_f64
+                / (-200..=200)
+                    .map(|x: i32| (-f64::from(x * x) / (2.0_f64 * sigma_squared)).exp())
+                    .sum::<f64>();
+            let n_samples = SAMPLE_COUNT;
+            let mut rng = seeded_rng(3);
+            let samples: Vec<i64> = (0..n_samples)
+                .map(|_| sample_discrete_gaussian(&mut rng, *num, *den).unwrap())
+                .collect();
+            let (mean, variance) = mean_and_variance(&samples);
+            assert!(mean.abs() < mean_tolerance(sigma_squared.sqrt(), n_samples));
+            assert!(
+                (variance - sigma_squared).abs()
+                    < variance_tolerance(sigma_squared, 3.0_f64, n_samples)
+            );
+            let zero_frequency =
+                samples.iter().filter(|x| **x == 0).count() as f64 / n_samples as f64;
+            assert!(
+                (zero_frequency - expected_zero_frequency).abs()
+                    < frequency_tolerance(expected_zero_frequency, n_samples)
+            );
+        }
+    }
+}
@@ -0,0 +1,144 @@
+// A discrete Gaussian mechanism, for queries whose sensitivity is naturally measured in the l2 norm
+// (e.g. a vector of counts to which a client may contribute to several entries). Unlike the
+// Laplace mechanism, it's (epsilon, delta)-differentially private rather than pure epsilon-DP.
+//
+// The noise is drawn exactly from the discrete Gaussian distribution (see
+// `exact::sample_discrete_gaussian`), so the query must be integer-valued. By Canonne, Kamath, and
+// Steinke, "The Discrete Gaussian for Differential Privacy" (https://arxiv.org/abs/2004.00010),
+// Theorem 4, adding discrete Gaussian noise with parameter sigma to a query with l2 sensitivity
+// delta_2 is rho-zCDP for rho = delta_2^2 / (2 sigma^2). By Bun and Steinke, "Concentrated
+// Differential Privacy: Simplifications, Extensions, and Lower Bounds"
+// (https://arxiv.org/abs/1605.02065), Proposition 1.3, rho-zCDP implies
+// (rho + 2 sqrt(rho ln(1/delta)), delta)-DP for every delta in (0, 1), so `sigma` solves
+// epsilon = rho + 2 sqrt(rho ln(1/delta)) for rho.
+
+use rand::Rng;
+
+use crate::exact;
+use crate::ParameterError;
+
+// sigma^2 is given to the exact sampler as a rational with this denominator. The numerator is
+// rounded up, which only adds noise.
+const SIGMA_SQUARED_DENOMINATOR: u64 = 1 << 20;
+
+// The largest sigma^2 numerator the exact sampler is given, so that its intermediate values
+// don't overflow.
+const MAX_SIGMA_SQUARED_NUMERATOR: u64 = 1 << 52;
+
+// Returns the parameter sigma of the discrete Gaussian noise that makes a query with the given l2
+// sensitivity (epsilon, delta)-differentially private. Epsilon must be positive and delta must be
+// in (0, 1).
+pub fn sigma(l2_sensitivity: f64, epsilon: f64, delta: f64) -> Result<f64, ParameterError> {
+    if !l2_sensitivity.is_finite() || l2_sensitivity <= 0.0_f64 {
+        return Err(ParameterError::SensitivityOutOfRange);
+    }
+    if epsilon.is_nan() || epsilon <= 0.0_f64 {
+        return Err(ParameterError::NonPositiveEpsilon);
+    }
+    if epsilon.is_infinite() {
+        return Err(ParameterError::EpsilonTooLarge);
+    }
+    if !(delta > 0.0_f64 && delta < 1.0_f64) {
+        return Err(ParameterError::DeltaOutOfRange);
+    }
+    let log_inverse_delta = -libm::log(delta);
+    // sqrt(rho) = sqrt(ln(1/delta) + epsilon) - sqrt(ln(1/delta)), written so that it doesn't
+    // cancel catastrophically when epsilon is much smaller than ln(1/delta).
+    let sqrt_rho = epsilon / ((log_inverse_delta + epsilon).sqrt() + log_inverse_delta.sqrt());
+    Ok(l2_sensitivity / (2.0_f64.sqrt() * sqrt_rho))
+}
+
+pub fn noise(l2_sensitivity: f64, epsilon: f64, delta: f64) -> Result<f64, ParameterError> {
+    noise_with_rng(&mut rand::thread_rng(), l2_sensitivity, epsilon, delta)
+}
+
+// Like `noise`, but draws the randomness from rng (see `laplace::noise_with_rng`).
+pub fn noise_with_rng<R: Rng>(
+    rng: &mut R,
+    l2_sensitivity: f64,
+    epsilon: f64,
+    delta: f64,
+) -> Result<f64, ParameterError> {
+    let sigma = sigma(l2_sensitivity, epsilon, delta)?;
+    let sigma_squared_numerator = (sigma * sigma * SIGMA_SQUARED_DENOMINATOR as f64).ceil();
+    if sigma_squared_numerator > MAX_SIGMA_SQUARED_NUMERATOR as f64 {
+        return Err(ParameterError::EpsilonTooSmall);
+    }
+    let sample = exact::sample_discrete_gaussian(
+        rng,
+        sigma_squared_numerator as u64,
+        SIGMA_SQUARED_DENOMINATOR,
+    )?;
+    Ok(sample as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn test_sigma() {
+        // For epsilon = 1 and delta = 10^-6, rho = (sqrt(ln(10^6) + 1) - sqrt(ln(10^6)))^2, i.e.
+        // about 0.01747, so sigma is about 5.35 (close to the classical Gaussian mechanism's
+        // sqrt(2 ln(1.25 / delta)) / epsilon = 5.30).
+        let sigma_1 = sigma(1.0_f64, 1.0_f64, 1e-6_f64).unwrap();
+        let log_inverse_delta = 1e6_f64.ln();
+        let rho = ((log_inverse_delta + 1.0_f64).sqrt() - log_inverse_delta.sqrt()).powi(2);
+        assert!((sigma_1 - 1.0_f64 / (2.0_f64 * rho).sqrt()).abs() < 1e-9_f64);
+        assert!((sigma_1 - 5.35_f64).abs() < 0.01_f64);
+        // The achieved epsilon is the requested one.
+        let rho = 1.0_f64 / (2.0_f64 * sigma_1 * sigma_1);
+        assert!((rho + 2.0_f64 * (rho * log_inverse_delta).sqrt() - 1.0_f64).abs() < 1e-9_f64);
+
+        // Sigma scales with the sensitivity and shrinks as epsilon or delta grow.
+        assert!((sigma(3.0_f64, 1.0_f64, 1e-6_f64).unwrap() - 3.0_f64 * sigma_1).abs() < 1e-9_f64);
+        assert!(sigma(1.0_f64, 2.0_f64, 1e-6_f64).unwrap() < sigma_1);
+        assert!(sigma(1.0_f64, 1.0_f64, 1e-3_f64).unwrap() < sigma_1);
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        for (l2_sensitivity, epsilon, delta) in [
+            (1.0_f64, 0.0_f64, 1e-6_f64),
+            (1.0_f64, -1.0_f64, 1e-6_f64),
+            (1.0_f64, f64::NAN, 1e-6_f64),
+            (1.0_f64, f64::INFINITY, 1e-6_f64),
+            (1.0_f64, 1.0_f64, 0.0_f64),
+            (1.0_f64, 1.0_f64, 1.0_f64),
+            (1.0_f64, 1.0_f64, f64::NAN),
+            (0.0_f64, 1.0_f64, 1e-6_f64),
+            (f64::NAN, 1.0_f64, 1e-6_f64),
+        ]
+        .iter()
+        {
+            assert!(sigma(*l2_sensitivity, *epsilon, *delta).is_err());
+            assert!(noise(*l2_sensitivity, *epsilon, *delta).is_err());
+        }
+        // Sigma is too large for the exact sampler.
+        assert!(noise(1.0_f64, 1e-12_f64, 1e-6_f64).is_err());
+    }
+
+    #[test]
+    fn test_noise() {
+        let (l2_sensitivity, epsilon, delta) = (2.0_f64, 1.0_f64, 1e-6_f64);
+        let sigma = sigma(l2_sensitivity, epsilon, delta).unwrap();
+        let n_samples = SAMPLE_COUNT;
+        let mut rng = seeded_rng(4);
+        let samples: Vec<i64> = (0..n_samples)
+            .map(|_| {
+                let sample = noise_with_rng(&mut rng, l2_sensitivity, epsilon, delta).unwrap();
+                assert_eq!(sample, sample.round());
+                sample as i64
+            })
+            .collect();
+        // The discrete Gaussian distribution has mean 0, variance sigma^2 (up to the rounding of
+        // sigma^2, and to within 10^-15 for sigma >= 1), and kurtosis 3.
+        let (mean, variance) = mean_and_variance(&samples);
+        assert!(mean.abs() < mean_tolerance(sigma, n_samples));
+        assert!(
+            (variance - sigma * sigma).abs()
+                < variance_tolerance(sigma * sigma, 3.0_f64, n_samples)
+        );
+    }
+}
@@ -1,13 +1,14 @@
 extern crate libm;
 extern crate rand;
 
-use rand::distributions::Standard;
-use rand::rngs::ThreadRng;
+use rand::distributions::{Distribution, Standard};
 use rand::Rng;
 
 use crate::ParameterError;
 
 use std::cmp;
+use std::convert::TryFrom;
+use std::fmt;
 
 // For the following on approximating a laplace distribution, see
 // https://raw.githubusercontent.com/google/differential-privacy/74d5be96d4abe6820ef4838c00a1b78c72ae01af/common_docs/Secure_Noise_Generation.pdf
@@ -32,15 +33,15 @@ use std::cmp;
 // limitations under the License.
 
 // Returns a f64 value in the range [0,1).
-fn next_double(rng: &mut ThreadRng) -> f64 {
+fn next_double<R: Rng>(rng: &mut R) -> f64 {
     rng.sample::<f64, Standard>(Standard)
 }
 
 // Draws a sample from the geometric distribution parameterized by p = 1 - e^(-lambda).
 // Lambda must be greater than 2^(-59).
-fn sample_geometric(rng: &mut ThreadRng, lambda: f64) -> Result<i64, ParameterError> {
+fn sample_geometric<R: Rng>(rng: &mut R, lambda: f64) -> Result<i64, ParameterError> {
     if lambda <= libm::exp2(-59.0_f64) {
-        return Err(ParameterError);
+        return Err(ParameterError::LambdaTooSmall);
     }
 
     // If the sample exceeds the maximum i64 value, the sample is truncated.
@@ -57,8 +58,7 @@ fn sample_geometric(rng: &mut ThreadRng, lambda: f64) -> Result<i64, ParameterEr
                     / lambda,
         ) as i64;
         let mid = cmp::min(cmp::max(mid, left + 1), right - 1);
-        let q = libm::expm1(lambda * ((left - mid) as f64))
-            / libm::expm1(lambda * ((left - right) as f64));
+        let q = geometric_split_probability(lambda, mid - left, right - left);
         if next_double(rng) <= q {
             right = mid;
         } else {
@@ -68,7 +68,32 @@ fn sample_geometric(rng: &mut ThreadRng, lambda: f64) -> Result<i64, ParameterEr
     Ok(right)
 }
 
-fn sample_two_sided_geometric(rng: &mut ThreadRng, lambda: f64) -> Result<i64, ParameterError> {
+// Returns ln(1 - e^(-x)) for x > 0. Which of the two formulations is accurate depends on x (see
+// Maechler, "Accurately Computing log(1 - exp(-|a|))"): for small x, 1 - e^(-x) must be computed
+// with expm1, and for large x, its logarithm must be computed with log1p.
+fn ln_one_minus_exp_neg(x: f64) -> f64 {
+    if x <= std::f64::consts::LN_2 {
+        libm::log(-libm::expm1(-x))
+    } else {
+        libm::log1p(-libm::exp(-x))
+    }
+}
+
+// Returns the probability that a geometric sample known to be in (left, right] is at most mid,
+// given the distances d_mid = mid - left and d_right = right - left, which is
+// (1 - e^(-lambda * d_mid)) / (1 - e^(-lambda * d_right)). For lambda as small as 2^-59, both
+// terms are tiny and 1 - e^(-x) computed naively is mostly (or entirely) rounding error, so this
+// is computed in log space.
+fn geometric_split_probability(lambda: f64, d_mid: i64, d_right: i64) -> f64 {
+    libm::exp(
+        ln_one_minus_exp_neg(lambda * d_mid as f64) - ln_one_minus_exp_neg(lambda * d_right as f64),
+    )
+}
+
+pub(crate) fn sample_two_sided_geometric<R: Rng>(
+    rng: &mut R,
+    lambda: f64,
+) -> Result<i64, ParameterError> {
     let mut geometric_sample = 0;
     let mut positive = false;
     while geometric_sample == 0 && !positive {
@@ -83,14 +108,131 @@ fn sample_two_sided_geometric(rng: &mut ThreadRng, lambda: f64) -> Result<i64, P
     }
 }
 
+// Draws n_samples raw samples of `sample_two_sided_geometric`, for the statistical tests.
+#[cfg(test)]
+pub(crate) fn two_sided_geometric_samples<R: Rng>(
+    rng: &mut R,
+    lambda: f64,
+    n_samples: usize,
+) -> Result<Vec<i64>, ParameterError> {
+    (0..n_samples)
+        .map(|_| sample_two_sided_geometric(rng, lambda))
+        .collect()
+}
+
+// Generates noise for several queries over the same data from a single draw of the mechanism: one
+// sample calibrated for an l1 sensitivity of 1 is scaled by each query's l1 sensitivity and then
+// rounded. On its own, each query's noise is distributed like `noise(l1_sensitivity, epsilon)`
+// for that query's sensitivity, but the noise is perfectly correlated across queries.
+// Privacy model: releasing all of the noised queries together is only epsilon-DP if every query
+// is a scalar multiple of one underlying query (e.g. the same count reported in different units),
+// because then the releases are post-processing of a single epsilon-DP release. For unrelated
+// queries, an appropriate linear combination of the results cancels the noise entirely, so
+// independent draws of `noise` with a split budget must be used instead.
+pub struct CorrelatedNoise {
+    l1_sensitivities: Vec<f64>,
+    epsilon: f64,
+}
+
+impl CorrelatedNoise {
+    pub fn new(
+        l1_sensitivities: Vec<f64>,
+        epsilon: f64,
+    ) -> Result<CorrelatedNoise, ParameterError> {
+        if l1_sensitivities.is_empty() {
+            return Err(ParameterError::NoQueries);
+        }
+        if l1_sensitivities
+            .iter()
+            .any(|l1_sensitivity| !l1_sensitivity.is_finite() || *l1_sensitivity <= 0.0_f64)
+        {
+            return Err(ParameterError::SensitivityOutOfRange);
+        }
+        get_granularity(1.0_f64, epsilon)?;
+        Ok(CorrelatedNoise {
+            l1_sensitivities,
+            epsilon,
+        })
+    }
+
+    // Returns one noise value for each query, in the order the sensitivities were given.
+    pub fn sample(&self) -> Result<Vec<i64>, ParameterError> {
+        self.sample_with_rng(&mut rand::thread_rng())
+    }
+
+    // Like `sample`, but draws the randomness from rng (see `noise_with_rng`).
+    pub fn sample_with_rng<R: Rng>(&self, rng: &mut R) -> Result<Vec<i64>, ParameterError> {
+        let granularity = get_granularity(1.0_f64, self.epsilon)?;
+        let two_sided_geometric_sample =
+            sample_two_sided_geometric(rng, epsilon_to_lambda(1.0_f64, self.epsilon)?)?;
+        let unit_noise = two_sided_geometric_sample as f64 * granularity;
+        Ok(self
+            .l1_sensitivities
+            .iter()
+            .map(|l1_sensitivity| (l1_sensitivity * unit_noise).round() as i64)
+            .collect())
+    }
+}
+
 pub fn noise(l1_sensitivity: f64, epsilon: f64) -> Result<i64, ParameterError> {
-    // TODO: check parameters
-    let granularity = get_granularity(l1_sensitivity, epsilon)?;
-    let mut rng = rand::thread_rng();
-    let two_sided_geometric_sample = sample_two_sided_geometric(
-        &mut rng,
-        granularity * epsilon / (l1_sensitivity + granularity),
-    )?;
+    noise_with_rng(&mut rand::thread_rng(), l1_sensitivity, epsilon)
+}
+
+// Like `noise`, but draws the randomness from rng, so that a seeded generator gives a
+// reproducible stream of noise (e.g. for tests or for reproducing a simulation). For the privacy
+// guarantee to hold, rng must be a cryptographically secure generator whose seed is kept secret.
+pub fn noise_with_rng<R: Rng>(
+    rng: &mut R,
+    l1_sensitivity: f64,
+    epsilon: f64,
+) -> Result<i64, ParameterError> {
+    sample_noise(rng, l1_sensitivity, epsilon, GRANULARITY_PARAM)
+}
+
+// Like `noise`, but with the granularity parameter (see `get_granularity_with_param`) given
+// rather than 2^40, e.g. to compare parameterizations. It must be a power of 2 (at least 1), and
+// large enough that the granularity is at most 1: with a coarser granularity, every noise value is
+// a multiple of it, so the noised integer result would reveal the exact value modulo the
+// granularity. Such parameters are rejected with GranularityOutOfRange. The achieved epsilon (see
+// `achieved_epsilon`) and the other functions of this module assume 2^40.
+pub fn noise_with_granularity(
+    l1_sensitivity: f64,
+    epsilon: f64,
+    granularity_param: f64,
+) -> Result<i64, ParameterError> {
+    noise_with_granularity_and_rng(
+        &mut rand::thread_rng(),
+        l1_sensitivity,
+        epsilon,
+        granularity_param,
+    )
+}
+
+// Like `noise_with_granularity`, but draws the randomness from rng (see `noise_with_rng`).
+pub fn noise_with_granularity_and_rng<R: Rng>(
+    rng: &mut R,
+    l1_sensitivity: f64,
+    epsilon: f64,
+    granularity_param: f64,
+) -> Result<i64, ParameterError> {
+    check_noise_parameters(l1_sensitivity, epsilon)?;
+    if get_granularity_with_param(l1_sensitivity, epsilon, granularity_param)? > 1.0_f64 {
+        return Err(ParameterError::GranularityOutOfRange);
+    }
+    sample_noise(rng, l1_sensitivity, epsilon, granularity_param)
+}
+
+fn sample_noise<R: Rng>(
+    rng: &mut R,
+    l1_sensitivity: f64,
+    epsilon: f64,
+    granularity_param: f64,
+) -> Result<i64, ParameterError> {
+    check_noise_parameters(l1_sensitivity, epsilon)?;
+    // This checks the granularity parameter.
+    let granularity = get_granularity_with_param(l1_sensitivity, epsilon, granularity_param)?;
+    let lambda = granularity * epsilon / (l1_sensitivity + granularity);
+    let two_sided_geometric_sample = sample_two_sided_geometric(rng, lambda)?;
     if granularity <= 1.0_f64 {
         Ok((two_sided_geometric_sample as f64 * granularity).round() as i64)
     } else {
@@ -98,37 +240,1301 @@ pub fn noise(l1_sensitivity: f64, epsilon: f64) -> Result<i64, ParameterError> {
     }
 }
 
-// The granularity parameter is 2^40.
-const GRANULARITY_PARAM: f64 = 1099511627776.0_f64;
+// Writes `n_samples` raw two-sided geometric samples, as drawn by `noise` for the given
+// parameters, to the file at `path`, so that the noise distribution can be checked with external
+// tools (e.g. a goodness-of-fit test in R or Python). The first two lines are
+// "granularity <r>" and "lambda <lambda>", followed by one sample per line; `noise` would have
+// returned each sample multiplied by r (see `get_granularity`).
+// This is for validation only: the samples are exactly the kind of secret `noise` must never
+// reveal, so the `noise-dump` feature must never be enabled in a production build.
+#[cfg(feature = "noise-dump")]
+pub fn dump_noise_samples(
+    path: &std::path::Path,
+    l1_sensitivity: f64,
+    epsilon: f64,
+    n_samples: usize,
+) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let invalid_parameters =
+        |_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid noise parameters");
+    let granularity = get_granularity(l1_sensitivity, epsilon).map_err(invalid_parameters)?;
+    let lambda = epsilon_to_lambda(l1_sensitivity, epsilon).map_err(invalid_parameters)?;
+    let mut rng = rand::thread_rng();
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+    writeln!(writer, "granularity {}", granularity)?;
+    writeln!(writer, "lambda {}", lambda)?;
+    for _ in 0..n_samples {
+        let sample = sample_two_sided_geometric(&mut rng, lambda).map_err(invalid_parameters)?;
+        writeln!(writer, "{}", sample)?;
+    }
+    writer.flush()
+}
+
+// Draws a noise value from an arbitrary distribution, e.g. to compare the mechanisms in this module
+// against other noise in ablation studies.
+// WARNING: only the mechanisms provided by this module (`noise` and `CorrelatedNoise`) come with
+// a differential privacy guarantee. Noise drawn from any other distribution gives NO privacy
+// guarantee whatsoever and must not be used to protect real data.
+pub fn noise_from_distribution<D: Distribution<f64>, R: Rng>(dist: &D, rng: &mut R) -> f64 {
+    dist.sample(rng)
+}
+
+// The result of a differentially private aggregation: the noised value along with the privacy
+// parameters and the uncertainty of the noise, so that it can't be mistaken for an exact value.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DpResult {
+    pub value: i64,
+    pub epsilon: f64,
+    // The noise is pure epsilon-differentially private, so this is always 0.
+    pub delta: f64,
+    // The probability that the confidence interval contains the exact value.
+    pub confidence: f64,
+    pub confidence_interval: (f64, f64),
+}
+
+impl DpResult {
+    // `value` is the sum of the exact value and n_noises independent draws of
+    // `noise(l1_sensitivity, epsilon)`. Each draw exceeds t = ln(n_noises / (1 - confidence)) *
+    // l1_sensitivity / epsilon in magnitude with probability (1 - confidence) / n_noises, so by a
+    // union bound, the total noise exceeds n_noises * t with probability at most 1 - confidence.
+    // (The interval is therefore conservative. It ignores the granularity of the noise, which is
+    // negligible for all practical parameters.)
+    pub fn new(
+        value: i64,
+        l1_sensitivity: f64,
+        epsilon: f64,
+        n_noises: usize,
+        confidence: f64,
+    ) -> Result<DpResult, ParameterError> {
+        if n_noises == 0 {
+            return Err(ParameterError::NoNoise);
+        }
+        if !(confidence > 0.0_f64 && confidence < 1.0_f64) {
+            return Err(ParameterError::ConfidenceOutOfRange);
+        }
+        if l1_sensitivity.is_nan() || l1_sensitivity <= 0.0_f64 {
+            return Err(ParameterError::SensitivityOutOfRange);
+        }
+        get_granularity(l1_sensitivity, epsilon)?;
+        let n_noises = n_noises as f64;
+        let noise_bound =
+            n_noises * libm::log(n_noises / (1.0_f64 - confidence)) * l1_sensitivity / epsilon;
+        Ok(DpResult {
+            value,
+            epsilon,
+            delta: 0.0_f64,
+            confidence,
+            confidence_interval: (value as f64 - noise_bound, value as f64 + noise_bound),
+        })
+    }
+
+    // Returns the mean of the noised sum over `count` contributions (the number of clients whose
+    // values were summed, not counting the noise contributions). The count is assumed to be
+    // public: in the protocol, the servers learn how many clients responded from the number of
+    // shares they receive, so dividing by it is post-processing and doesn't cost any privacy
+    // budget, and the confidence interval is simply scaled by 1 / count. If the count had to be
+    // kept private, it would need to be noised with its own share of the privacy budget, and the
+    // ratio of two noised values is biased (and unbounded when the noised count is close to 0),
+    // so that isn't supported here.
+    pub fn mean(&self, count: usize) -> Result<DpMean, ParameterError> {
+        if count == 0 {
+            return Err(ParameterError::ZeroCount);
+        }
+        let count_f64 = count as f64;
+        Ok(DpMean {
+            value: self.value as f64 / count_f64,
+            count,
+            epsilon: self.epsilon,
+            delta: self.delta,
+            confidence: self.confidence,
+            confidence_interval: (
+                self.confidence_interval.0 / count_f64,
+                self.confidence_interval.1 / count_f64,
+            ),
+        })
+    }
+}
+
+// The mean of a differentially private sum (see `DpResult::mean`), with the same privacy
+// parameters and confidence as the sum.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DpMean {
+    pub value: f64,
+    // The public number of contributions the sum was divided by.
+    pub count: usize,
+    pub epsilon: f64,
+    pub delta: f64,
+    pub confidence: f64,
+    pub confidence_interval: (f64, f64),
+}
+
+// Returns the smallest epsilon (i.e. the strongest privacy) for which the noise of a single query
+// is within target_error in magnitude with the given confidence, the inverse of the confidence
+// interval of `DpResult::new` with one noise draw. Laplace noise exceeds t in magnitude with
+// probability e^(-t * epsilon / l1_sensitivity), so this solves
+// e^(-target_error * epsilon / l1_sensitivity) = 1 - confidence for epsilon. Any larger epsilon
+// meets the target too, with weaker privacy.
+pub fn epsilon_for_target_error(
+    target_error: f64,
+    l1_sensitivity: f64,
+    confidence: f64,
+) -> Result<f64, ParameterError> {
+    if !target_error.is_finite() || target_error <= 0.0_f64 {
+        return Err(ParameterError::TargetErrorOutOfRange);
+    }
+    if !l1_sensitivity.is_finite() || l1_sensitivity <= 0.0_f64 {
+        return Err(ParameterError::SensitivityOutOfRange);
+    }
+    if !(confidence > 0.0_f64 && confidence < 1.0_f64) {
+        return Err(ParameterError::ConfidenceOutOfRange);
+    }
+    let epsilon = -libm::log1p(-confidence) * l1_sensitivity / target_error;
+    get_granularity(l1_sensitivity, epsilon)?;
+    Ok(epsilon)
+}
+
+impl fmt::Display for DpResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (epsilon = {}, delta = {}, {}% confidence interval [{:.1}, {:.1}])",
+            self.value,
+            self.epsilon,
+            self.delta,
+            self.confidence * 100.0_f64,
+            self.confidence_interval.0,
+            self.confidence_interval.1
+        )
+    }
+}
+
+// The granularity parameter is 2^40 (see `get_granularity_with_param`).
+pub const GRANULARITY_PARAM: f64 = 1099511627776.0_f64;
+
+// How `power_of_two` rounds a value that isn't a power of two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    // The smallest power of 2 greater than or equal to x.
+    Up,
+    // The largest power of 2 less than or equal to x.
+    Down,
+    // Whichever of the two is closer to x, or the larger one if x is halfway between them.
+    Nearest,
+}
+
+// Rounds x to a power of 2 according to mode. Only the powers 2^0, ..., 2^1023 are considered, so
+// any x below 1 rounds to 1.
+// x is a non-negative number less than or equal to 2^1023.
+pub fn power_of_two(x: f64, mode: RoundingMode) -> Result<f64, ParameterError> {
+    // This also rejects NaN.
+    if !(x >= 0.0_f64 && x <= (1023.0_f64).exp2()) {
+        return Err(ParameterError::ValueOutOfRange);
+    }
+    if x <= 1.0_f64 {
+        return Ok(1.0_f64);
+    }
+    // x is now a normal float of at least 1, i.e. (1 + mantissa / 2^52) * 2^exponent with the
+    // biased exponent in the 11 bits above the 52 bits of mantissa. x is a power of 2 exactly when
+    // the mantissa is 0, and otherwise lies strictly between 2^exponent and 2^(exponent + 1).
+    const MANTISSA_BITS: u32 = 52;
+    let bits = x.to_bits();
+    if bits & ((1 << MANTISSA_BITS) - 1) == 0 {
+        return Ok(x);
+    }
+    let biased_exponent = bits >> MANTISSA_BITS;
+    let lower = f64::from_bits(biased_exponent << MANTISSA_BITS);
+    // Since x <= 2^1023 isn't a power of 2, x < 2^1023, so the biased exponent of val is at most
+    // that of 2^1023, and val is finite.
+    let val = f64::from_bits((biased_exponent + 1) << MANTISSA_BITS);
+    Ok(match mode {
+        RoundingMode::Up => val,
+        RoundingMode::Down => lower,
+        RoundingMode::Nearest if x - lower < val - x => lower,
+        RoundingMode::Nearest => val,
+    })
+}
 
 // Returns the smallest power of 2 greater than or equal to x.
 // x is a positive number less than or equal to 2^1023.
 fn ceil_power_of_two(x: f64) -> Result<f64, ParameterError> {
-    if x < 0.0_f64 {
-        return Err(ParameterError);
+    power_of_two(x, RoundingMode::Up)
+}
+
+// Returns the granularity r of the noise: `noise` returns a two-sided geometric sample multiplied
+// by r (and rounded to an integer if r is less than 1).
+pub fn get_granularity(l1_sensitivity: f64, epsilon: f64) -> Result<f64, ParameterError> {
+    get_granularity_with_param(l1_sensitivity, epsilon, GRANULARITY_PARAM)
+}
+
+// Like `get_granularity`, with a granularity parameter other than GRANULARITY_PARAM: the
+// granularity is l1_sensitivity / epsilon rounded up to a power of 2, divided by
+// granularity_param. A larger parameter gives a finer granularity, at the cost of a smaller lambda
+// (and so of more work to sample). granularity_param must be a power of 2 (at least 1), so that
+// the granularity is one too.
+pub fn get_granularity_with_param(
+    l1_sensitivity: f64,
+    epsilon: f64,
+    granularity_param: f64,
+) -> Result<f64, ParameterError> {
+    check_sensitivity_and_epsilon(l1_sensitivity, epsilon)?;
+    match ceil_power_of_two(granularity_param) {
+        Ok(rounded) if rounded == granularity_param => {}
+        _ => return Err(ParameterError::GranularityOutOfRange),
     }
-    if x > (1023.0_f64).exp2() {
-        return Err(ParameterError);
+    let rounded =
+        ceil_power_of_two(l1_sensitivity / epsilon).map_err(|_| ParameterError::EpsilonTooSmall)?;
+    Ok(rounded / granularity_param)
+}
+
+// Checks the parameters common to the mechanisms, so that the error says which one is wrong.
+// Epsilon only has to be positive: an infinite epsilon results in the smallest granularity.
+fn check_sensitivity_and_epsilon(l1_sensitivity: f64, epsilon: f64) -> Result<(), ParameterError> {
+    if epsilon.is_nan() || epsilon <= 0.0_f64 {
+        return Err(ParameterError::NonPositiveEpsilon);
     }
-    let mut exponent = 0.0_f64;
-    let mut val = exponent.exp2();
-    while val < x {
-        exponent += 1.0_f64;
-        val = exponent.exp2();
+    if l1_sensitivity.is_nan() || l1_sensitivity < 0.0_f64 || l1_sensitivity.is_infinite() {
+        return Err(ParameterError::SensitivityOutOfRange);
     }
-    Ok(val)
+    Ok(())
 }
 
-fn get_granularity(l1_sensitivity: f64, epsilon: f64) -> Result<f64, ParameterError> {
-    Ok(ceil_power_of_two(l1_sensitivity / epsilon)? / GRANULARITY_PARAM)
+// The checks of the parameters of a noise sample, which are stricter than those of the other
+// functions of this module: a sensitivity of 0 or an infinite epsilon would make the noise
+// meaningless (releasing the exact value), so the caller is told rather than given such noise.
+fn check_noise_parameters(l1_sensitivity: f64, epsilon: f64) -> Result<(), ParameterError> {
+    if epsilon.is_nan() || epsilon <= 0.0_f64 {
+        return Err(ParameterError::NonPositiveEpsilon);
+    }
+    if epsilon.is_infinite() {
+        return Err(ParameterError::EpsilonTooLarge);
+    }
+    if l1_sensitivity.is_nan() || l1_sensitivity <= 0.0_f64 || l1_sensitivity.is_infinite() {
+        return Err(ParameterError::SensitivityOutOfRange);
+    }
+    Ok(())
 }
 
-// Given our analysis, the minimum number of bits required is ceil(log_2(r/lambda * 6 ln 10)), where
-// r is the granularity (see `get_granularity`, and lambda is r * epsilon / (1l_sensitivity + r).
-pub fn min_bits(l1_sensitivity: f64, epsilon: f64) -> Result<usize, ParameterError> {
+// Like `get_granularity`, but rounds l1_sensitivity / epsilon to a power of 2 with the given mode,
+// to experiment with finer (or coarser) granularities. WARNING: `epsilon_to_lambda` and the rest of
+// the privacy analysis of `noise` assume the granularity of `get_granularity`, i.e.
+// RoundingMode::Up.
+pub fn get_granularity_with_rounding(
+    l1_sensitivity: f64,
+    epsilon: f64,
+    mode: RoundingMode,
+) -> Result<f64, ParameterError> {
+    check_sensitivity_and_epsilon(l1_sensitivity, epsilon)?;
+    let rounded = power_of_two(l1_sensitivity / epsilon, mode)
+        .map_err(|_| ParameterError::EpsilonTooSmall)?;
+    Ok(rounded / GRANULARITY_PARAM)
+}
+
+// Returns the parameter lambda of the two-sided geometric distribution `noise` samples from,
+// which is r * epsilon / (l1_sensitivity + r), where r is the granularity. Note that lambda is
+// relative to the granularity (a sample of 1 corresponds to noise of r).
+pub fn epsilon_to_lambda(l1_sensitivity: f64, epsilon: f64) -> Result<f64, ParameterError> {
     let granularity = get_granularity(l1_sensitivity, epsilon)?;
-    let lambda = granularity * epsilon / (l1_sensitivity + granularity);
-    Ok((6.0_f64 * 10.0_f64.ln() * granularity / lambda)
-        .log2()
-        .ceil() as usize)
+    Ok(granularity * epsilon / (l1_sensitivity + granularity))
+}
+
+// The inverse of `epsilon_to_lambda`. Since lambda is relative to the granularity, which depends
+// on epsilon, the granularity (see `get_granularity`) must be given as well - e.g. for an l1
+// sensitivity of 1, every epsilon results in a lambda between 2^-40 and 2^-39.
+pub fn lambda_to_epsilon(
+    l1_sensitivity: f64,
+    lambda: f64,
+    granularity: f64,
+) -> Result<f64, ParameterError> {
+    if l1_sensitivity.is_nan() || l1_sensitivity <= 0.0_f64 {
+        return Err(ParameterError::SensitivityOutOfRange);
+    }
+    if lambda.is_nan() || lambda <= 0.0_f64 {
+        return Err(ParameterError::LambdaTooSmall);
+    }
+    if granularity.is_nan() || granularity <= 0.0_f64 {
+        return Err(ParameterError::GranularityOutOfRange);
+    }
+    Ok(lambda * (l1_sensitivity + granularity) / granularity)
+}
+
+// Returns the epsilon actually achieved by `noise` for the given parameters, as opposed to the
+// requested epsilon. The output of `noise` is a multiple of the granularity r, with lambda (see
+// `epsilon_to_lambda`) being the privacy loss per multiple of r. The reference implementation
+// rounds the value being protected to a multiple of r, which can move neighboring values up to
+// l1_sensitivity + r apart, hence lambda = r * epsilon / (l1_sensitivity + r) and an achieved
+// epsilon of exactly epsilon. When r <= 1, however, integer values are already multiples of r,
+// neighboring values are at most l1_sensitivity apart, and the achieved epsilon is
+// lambda * l1_sensitivity / r = epsilon * l1_sensitivity / (l1_sensitivity + r), which is
+// slightly smaller (i.e. better) than requested. When r > 1, the value being protected must be
+// rounded to a multiple of r for the mechanism to be differentially private at all, in which case
+// the achieved epsilon is the requested epsilon.
+// (The truncation of geometric samples at i64::MAX affects the privacy guarantee only with
+// negligible probability and isn't accounted for.)
+pub fn achieved_epsilon(l1_sensitivity: f64, epsilon: f64) -> Result<f64, ParameterError> {
+    let granularity = get_granularity(l1_sensitivity, epsilon)?;
+    let lambda = epsilon_to_lambda(l1_sensitivity, epsilon)?;
+    if granularity <= 1.0_f64 {
+        Ok(lambda * l1_sensitivity / granularity)
+    } else {
+        Ok(lambda * (l1_sensitivity + granularity) / granularity)
+    }
+}
+
+// If `noise` returns 0 with at least this probability, the noise is effectively absent (see
+// `is_noise_negligible`).
+pub const NEGLIGIBLE_NOISE_THRESHOLD: f64 = 0.99_f64;
+
+// Returns the probability that `noise` returns 0 for the given parameters. A two-sided geometric
+// sample k with parameter lambda is k with probability (1 - a) / (1 + a) * a^|k|, where
+// a = e^(-lambda). When the granularity r is at most 1, the noise rounds to 0 whenever
+// |k| < 0.5 / r, which happens with probability 1 - 2 * a^m / (1 + a) for m = ceil(0.5 / r).
+// Otherwise, the noise is 0 only when k is.
+pub fn zero_noise_probability(l1_sensitivity: f64, epsilon: f64) -> Result<f64, ParameterError> {
+    let granularity = get_granularity(l1_sensitivity, epsilon)?;
+    let lambda = epsilon_to_lambda(l1_sensitivity, epsilon)?;
+    let a = libm::exp(-lambda);
+    if granularity <= 1.0_f64 {
+        let m = (0.5_f64 / granularity).ceil();
+        Ok(1.0_f64 - 2.0_f64 * libm::exp(-lambda * m) / (1.0_f64 + a))
+    } else {
+        Ok(-libm::expm1(-lambda) / (1.0_f64 + a))
+    }
+}
+
+// Returns true if epsilon is so large (relative to the l1 sensitivity) that `noise` almost always
+// returns 0, which probably means epsilon was mistyped. Such parameters are still usable, but
+// provide next to no privacy.
+pub fn is_noise_negligible(l1_sensitivity: f64, epsilon: f64) -> Result<bool, ParameterError> {
+    Ok(zero_noise_probability(l1_sensitivity, epsilon)? >= NEGLIGIBLE_NOISE_THRESHOLD)
+}
+
+// The largest dimension (number of bits) `shift_value_for_dimension` supports.
+pub const MAX_DIMENSION: usize = 64;
+
+// Since noise can be negative but values are encoded as unsigned integers of `dimension` bits,
+// clients add 2^(dimension - 1) to every value they send (so noise in [-2^(dimension - 1),
+// 2^(dimension - 1)) can be encoded), and the servers subtract it from the aggregate for every
+// contribution. Clients and servers must use the same shift value, so both should get it from
+// here. The dimension must be between 1 and MAX_DIMENSION, as otherwise the shift value doesn't
+// fit in a u64.
+pub fn shift_value_for_dimension(dimension: usize) -> Result<u64, ParameterError> {
+    if dimension == 0 || dimension > MAX_DIMENSION {
+        return Err(ParameterError::DimensionOutOfRange);
+    }
+    1u64.checked_shl((dimension - 1) as u32)
+        .ok_or(ParameterError::DimensionOutOfRange)
+}
+
+// Encodes value + shift (e.g. a client's value or noise, plus `shift_value_for_dimension`) as the
+// vector of its dimension bits, least significant first, which is how clients submit values to
+// Prio. Fails if value + shift is negative or doesn't fit in dimension bits.
+pub fn encode_bits(
+    value: isize,
+    shift: isize,
+    dimension: usize,
+) -> Result<Vec<u32>, ParameterError> {
+    if dimension == 0 || dimension > MAX_DIMENSION {
+        return Err(ParameterError::DimensionOutOfRange);
+    }
+    let shifted = value.checked_add(shift).ok_or(ParameterError::Overflow)?;
+    if shifted < 0 || (dimension < 64 && shifted as u64 >= 1 << dimension) {
+        return Err(ParameterError::ValueOutOfRange);
+    }
+    Ok((0..dimension)
+        .map(|i| ((shifted as u64 >> i) & 1) as u32)
+        .collect())
+}
+
+// The inverse of `encode_bits`: recomposes the bits (least significant first) and subtracts shift.
+pub fn decode_bits(bits: &[u32], shift: isize) -> Result<isize, ParameterError> {
+    if bits.is_empty() || bits.len() > MAX_DIMENSION {
+        return Err(ParameterError::DimensionOutOfRange);
+    }
+    let mut shifted = 0_u64;
+    for (i, bit) in bits.iter().enumerate() {
+        if *bit > 1 {
+            return Err(ParameterError::ValueOutOfRange);
+        }
+        shifted |= (*bit as u64) << i;
+    }
+    if shifted > isize::MAX as u64 {
+        return Err(ParameterError::Overflow);
+    }
+    (shifted as isize)
+        .checked_sub(shift)
+        .ok_or(ParameterError::Overflow)
+}
+
+// Compares a per-bit vector (e.g. one reconstructed from a client's shares while debugging) with
+// the encoding of value + shift (see `encode_bits`), and returns the positions (least significant
+// first) at which it diverges. Each bit is a separate element of the vector, so a bug that corrupts
+// a single position only shows in the aggregate as an error of a multiple of 2^position, which is
+// hard to trace back from the aggregate alone. If value + shift can't be encoded in bits.len()
+// bits, no vector of that length encodes it, so every position is returned.
+pub fn check_bits(bits: &[u32], value: isize, shift: isize) -> Result<(), Vec<usize>> {
+    let expected_bits = match encode_bits(value, shift, bits.len()) {
+        Ok(expected_bits) => expected_bits,
+        Err(_) => return Err((0..bits.len()).collect()),
+    };
+    let positions: Vec<usize> = bits
+        .iter()
+        .zip(expected_bits.iter())
+        .enumerate()
+        .filter(|(_, (bit, expected_bit))| bit != expected_bit)
+        .map(|(position, _)| position)
+        .collect();
+    if positions.is_empty() {
+        Ok(())
+    } else {
+        Err(positions)
+    }
+}
+
+// Recovers the signed result of a study from raw_sum, the aggregate the servers computed in a field
+// with the given modulus: the sum of n_contributions contributions (the clients' values and the
+// selected noises), each shifted by shift_value (see `encode_bits`). The shifted contributions are
+// non-negative, so as long as their total is less than the modulus (which the study must ensure),
+// raw_sum is that total exactly. The total shift, however, can exceed it: when the noise is
+// negative enough, the result is negative. Subtracting the total shift in the field then wraps
+// around to modulus - |result| (and subtracting it from raw_sum as an unsigned integer
+// underflows), so this subtracts it as a signed integer instead.
+pub fn decode_sum(
+    raw_sum: u64,
+    modulus: u64,
+    shift_value: u64,
+    n_contributions: usize,
+) -> Result<i64, ParameterError> {
+    if raw_sum >= modulus {
+        return Err(ParameterError::ValueOutOfRange);
+    }
+    let total_shift = shift_value as u128 * n_contributions as u128;
+    // The total of the shifted contributions is at least the total shift, so it wouldn't fit either.
+    if total_shift >= modulus as u128 {
+        return Err(ParameterError::Overflow);
+    }
+    i64::try_from(raw_sum as i128 - total_shift as i128).map_err(|_| ParameterError::Overflow)
+}
+
+// The bound on the magnitude of the noise our analysis assumes: r/lambda * 6 ln 10, where r is the
+// granularity (see `get_granularity`) and lambda is r * epsilon / (l1_sensitivity + r). A sample of
+// `noise` exceeds it with probability about 10^-6. The sampler itself doesn't truncate, so this is
+// the bound encodings of the noise must accommodate.
+pub fn noise_bound(l1_sensitivity: f64, epsilon: f64) -> Result<f64, ParameterError> {
+    let granularity = get_granularity(l1_sensitivity, epsilon)?;
+    let lambda = epsilon_to_lambda(l1_sensitivity, epsilon)?;
+    Ok(6.0_f64 * 10.0_f64.ln() * granularity / lambda)
+}
+
+// Given our analysis, the minimum number of bits required is ceil(log_2(r/lambda * 6 ln 10)) (see
+// `noise_bound`): the number of bits of the magnitude of any noise within the bound, not counting
+// the sign. Since `noise` doesn't truncate, this isn't a hard limit on what it can return, but a
+// sample exceeds it with probability about 10^-6.
+pub fn min_bits(l1_sensitivity: f64, epsilon: f64) -> Result<usize, ParameterError> {
+    Ok(noise_bound(l1_sensitivity, epsilon)?.log2().ceil() as usize)
+}
+
+// Checks that noise within `noise_bound`, once shifted by `shift_value_for_dimension(dimension)`,
+// fits in dimension bits, i.e. that the shifted noise is in [0, 2^dimension). Since the noise is an
+// integer, this holds if ceil(bound) < shift_value. min_bits(l1_sensitivity, epsilon) + 1 bits (the
+// dimension clients use) suffice unless the bound is within 1 below a power of 2, or epsilon is so
+// large that min_bits is at most 1.
+pub fn verify_dimension_sufficient(
+    dimension: usize,
+    l1_sensitivity: f64,
+    epsilon: f64,
+) -> Result<(), ParameterError> {
+    let shift_value = shift_value_for_dimension(dimension)?;
+    let max_noise = noise_bound(l1_sensitivity, epsilon)?.ceil();
+    if max_noise < shift_value as f64 {
+        Ok(())
+    } else {
+        Err(ParameterError::DimensionTooSmall)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn test_parameter_errors() {
+        // Each kind of invalid parameter is reported as such.
+        assert_eq!(
+            noise(1.0_f64, 0.0_f64),
+            Err(ParameterError::NonPositiveEpsilon)
+        );
+        assert_eq!(
+            noise(1.0_f64, -0.1_f64),
+            Err(ParameterError::NonPositiveEpsilon)
+        );
+        assert_eq!(
+            noise(1.0_f64, f64::NAN),
+            Err(ParameterError::NonPositiveEpsilon)
+        );
+        assert_eq!(
+            noise(1.0_f64, 1e-310_f64),
+            Err(ParameterError::EpsilonTooSmall)
+        );
+        assert_eq!(
+            noise(-1.0_f64, 0.1_f64),
+            Err(ParameterError::SensitivityOutOfRange)
+        );
+        assert_eq!(
+            noise(f64::INFINITY, 0.1_f64),
+            Err(ParameterError::SensitivityOutOfRange)
+        );
+        assert_eq!(
+            sample_geometric(&mut rand::thread_rng(), 0.0_f64),
+            Err(ParameterError::LambdaTooSmall)
+        );
+        assert_eq!(
+            lambda_to_epsilon(1.0_f64, 0.1_f64, 0.0_f64),
+            Err(ParameterError::GranularityOutOfRange)
+        );
+        assert_eq!(
+            DpResult::new(0, 1.0_f64, 0.1_f64, 1, 1.5_f64),
+            Err(ParameterError::ConfidenceOutOfRange)
+        );
+        assert_eq!(
+            shift_value_for_dimension(0),
+            Err(ParameterError::DimensionOutOfRange)
+        );
+        assert_eq!(
+            verify_dimension_sufficient(2, 1.0_f64, 0.1_f64),
+            Err(ParameterError::DimensionTooSmall)
+        );
+        assert_eq!(
+            ParameterError::NonPositiveEpsilon.to_string(),
+            "epsilon must be positive"
+        );
+    }
+
+    #[test]
+    fn test_epsilon_lambda_conversion() {
+        for l1_sensitivity in [1.0_f64, 2.0_f64, 10.0_f64] {
+            for epsilon in [0.025_f64, 0.1_f64, 0.8_f64, 3.0_f64] {
+                let lambda = epsilon_to_lambda(l1_sensitivity, epsilon).unwrap();
+                let granularity = get_granularity(l1_sensitivity, epsilon).unwrap();
+                let recovered_epsilon =
+                    lambda_to_epsilon(l1_sensitivity, lambda, granularity).unwrap();
+                assert!((recovered_epsilon - epsilon).abs() <= 1e-12_f64 * epsilon);
+            }
+        }
+        assert!(lambda_to_epsilon(1.0_f64, 0.0_f64, 1.0_f64).is_err());
+        assert!(lambda_to_epsilon(1.0_f64, 1.0_f64, -1.0_f64).is_err());
+    }
+
+    #[test]
+    fn test_achieved_epsilon() {
+        // These are the parameters used by the comparison example.
+        for epsilon in [0.025_f64, 0.05_f64, 0.1_f64, 0.2_f64, 0.4_f64, 0.8_f64] {
+            let achieved = achieved_epsilon(1.0_f64, epsilon).unwrap();
+            let granularity = get_granularity(1.0_f64, epsilon).unwrap();
+            assert!(achieved < epsilon);
+            assert!((achieved - epsilon / (1.0_f64 + granularity)).abs() <= 1e-15_f64);
+            // The difference is tiny, as the granularity is on the order of 2^-40 / epsilon.
+            assert!(epsilon - achieved < 1e-10_f64 * epsilon);
+        }
+    }
+
+    #[test]
+    fn test_negligible_noise() {
+        let epsilon = 100.0_f64;
+        assert!(is_noise_negligible(1.0_f64, epsilon).unwrap());
+        assert!(noise(1.0_f64, epsilon).is_ok());
+        assert!(min_bits(1.0_f64, epsilon).is_ok());
+
+        let mut rng = seeded_rng(16);
+        for epsilon in [0.1_f64, 1.0_f64] {
+            assert!(!is_noise_negligible(1.0_f64, epsilon).unwrap());
+            // The probability should match the empirical frequency of zero noise.
+            let n_samples = SAMPLE_COUNT;
+            let zeros = (0..n_samples)
+                .filter(|_| noise_with_rng(&mut rng, 1.0_f64, epsilon).unwrap() == 0)
+                .count();
+            let expected = zero_noise_probability(1.0_f64, epsilon).unwrap();
+            assert!(
+                (zeros as f64 / n_samples as f64 - expected).abs()
+                    < frequency_tolerance(expected, n_samples)
+            );
+        }
+    }
+
+    #[test]
+    fn test_dp_result() {
+        let result = DpResult::new(1000, 1.0_f64, 0.1_f64, 1, 0.95_f64).unwrap();
+        assert_eq!(result.value, 1000);
+        assert_eq!(result.epsilon, 0.1_f64);
+        assert_eq!(result.delta, 0.0_f64);
+        assert_eq!(result.confidence, 0.95_f64);
+        // ln(1 / 0.05) / 0.1 = 29.957...
+        let noise_bound = 20.0_f64.ln() / 0.1_f64;
+        assert!((result.confidence_interval.0 - (1000.0_f64 - noise_bound)).abs() < 1e-9_f64);
+        assert!((result.confidence_interval.1 - (1000.0_f64 + noise_bound)).abs() < 1e-9_f64);
+        assert_eq!(
+            result.to_string(),
+            "1000 (epsilon = 0.1, delta = 0, 95% confidence interval [970.0, 1030.0])"
+        );
+
+        // With several noises, the interval is wider than the sum of each noise's interval.
+        let result = DpResult::new(1000, 1.0_f64, 0.1_f64, 4, 0.95_f64).unwrap();
+        let noise_bound = 4.0_f64 * 80.0_f64.ln() / 0.1_f64;
+        assert!((result.confidence_interval.1 - (1000.0_f64 + noise_bound)).abs() < 1e-9_f64);
+
+        // Empirically, the exact value should be in the interval (at least) 95% of the time.
+        let exact_value = 1000;
+        let n_samples = 2000;
+        let mut rng = seeded_rng(17);
+        let covered = (0..n_samples)
+            .filter(|_| {
+                let value = exact_value
+                    + (0..4)
+                        .map(|_| noise_with_rng(&mut rng, 1.0_f64, 0.1_f64).unwrap())
+                        .sum::<i64>();
+                let result = DpResult::new(value, 1.0_f64, 0.1_f64, 4, 0.95_f64).unwrap();
+                result.confidence_interval.0 <= exact_value as f64
+                    && exact_value as f64 <= result.confidence_interval.1
+            })
+            .count();
+        assert!(covered as f64 >= 0.95_f64 * n_samples as f64);
+
+        assert!(DpResult::new(1000, 1.0_f64, 0.1_f64, 0, 0.95_f64).is_err());
+        assert!(DpResult::new(1000, 1.0_f64, 0.1_f64, 1, 1.0_f64).is_err());
+    }
+
+    #[test]
+    fn test_dp_mean() {
+        let result = DpResult::new(1000, 1.0_f64, 0.1_f64, 1, 0.95_f64).unwrap();
+        let mean = result.mean(100).unwrap();
+        assert_eq!(mean.value, 10.0_f64);
+        assert_eq!(mean.count, 100);
+        assert_eq!(mean.epsilon, result.epsilon);
+        assert_eq!(mean.confidence, result.confidence);
+        let noise_bound = 20.0_f64.ln() / 0.1_f64 / 100.0_f64;
+        assert!((mean.confidence_interval.0 - (10.0_f64 - noise_bound)).abs() < 1e-9_f64);
+        assert!((mean.confidence_interval.1 - (10.0_f64 + noise_bound)).abs() < 1e-9_f64);
+        assert!(result.mean(0).is_err());
+
+        // The noised mean should recover the true mean within the (scaled) noise bound.
+        let values: Vec<i64> = (0..500).map(|i| i % 7).collect();
+        let count = values.len();
+        let exact_sum: i64 = values.iter().sum();
+        let true_mean = exact_sum as f64 / count as f64;
+        let n_noises = 4;
+        let n_samples = 2000;
+        let mut rng = seeded_rng(18);
+        let covered = (0..n_samples)
+            .filter(|_| {
+                let value = exact_sum
+                    + (0..n_noises)
+                        .map(|_| noise_with_rng(&mut rng, 1.0_f64, 0.1_f64).unwrap())
+                        .sum::<i64>();
+                let mean = DpResult::new(value, 1.0_f64, 0.1_f64, n_noises, 0.95_f64)
+                    .unwrap()
+                    .mean(count)
+                    .unwrap();
+                mean.confidence_interval.0 <= true_mean && true_mean <= mean.confidence_interval.1
+            })
+            .count();
+        assert!(covered as f64 >= 0.95_f64 * n_samples as f64);
+    }
+
+    #[test]
+    fn test_shift_value_for_dimension() {
+        assert!(shift_value_for_dimension(0).is_err());
+        assert_eq!(shift_value_for_dimension(1).unwrap(), 1);
+        assert_eq!(shift_value_for_dimension(2).unwrap(), 2);
+        assert_eq!(shift_value_for_dimension(6).unwrap(), 32);
+        assert_eq!(shift_value_for_dimension(MAX_DIMENSION).unwrap(), 1 << 63);
+        assert!(shift_value_for_dimension(MAX_DIMENSION + 1).is_err());
+        assert!(shift_value_for_dimension(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_correlated_noise() {
+        let epsilon = 1.0_f64;
+        let l1_sensitivities = vec![2.0_f64, 6.0_f64];
+        assert!(CorrelatedNoise::new(vec![], epsilon).is_err());
+        assert!(CorrelatedNoise::new(vec![1.0_f64, -1.0_f64], epsilon).is_err());
+        let correlated_noise = CorrelatedNoise::new(l1_sensitivities.clone(), epsilon).unwrap();
+        let n_samples = SAMPLE_COUNT;
+        let mut samples = vec![Vec::with_capacity(n_samples); l1_sensitivities.len()];
+        let mut rng = seeded_rng(19);
+        for _ in 0..n_samples {
+            let noises = correlated_noise.sample_with_rng(&mut rng).unwrap();
+            assert_eq!(noises.len(), l1_sensitivities.len());
+            for (query_samples, noise) in samples.iter_mut().zip(noises) {
+                query_samples.push(noise);
+            }
+        }
+        // Each query's marginal should look like Laplace noise with scale l1_sensitivity / epsilon,
+        // which has mean 0, variance 2 * (l1_sensitivity / epsilon)^2, and kurtosis 6.
+        let mut moments = Vec::with_capacity(l1_sensitivities.len());
+        for (query_samples, l1_sensitivity) in samples.iter().zip(l1_sensitivities.iter()) {
+            let (mean, variance) = mean_and_variance(query_samples);
+            let expected_variance = 2.0_f64 * (l1_sensitivity / epsilon).powi(2);
+            assert!(mean.abs() < mean_tolerance(expected_variance.sqrt(), n_samples));
+            assert!(
+                (variance - expected_variance).abs()
+                    < variance_tolerance(expected_variance, 6.0_f64, n_samples)
+            );
+            moments.push((mean, variance));
+        }
+        // ... but the queries should be (nearly, given rounding) perfectly correlated.
+        let covariance = samples[0]
+            .iter()
+            .zip(samples[1].iter())
+            .map(|(x, y)| (*x as f64 - moments[0].0) * (*y as f64 - moments[1].0))
+            .sum::<f64>()
+            / n_samples as f64;
+        let correlation = covariance / (moments[0].1 * moments[1].1).sqrt();
+        assert!(correlation > 0.95_f64);
+    }
+
+    #[test]
+    fn test_noise_from_distribution() {
+        let dist = rand::distributions::Uniform::new(2.0_f64, 3.0_f64);
+        let mut rng = seeded_rng(5);
+        let n_samples = SAMPLE_COUNT;
+        let samples: Vec<f64> = (0..n_samples)
+            .map(|_| noise_from_distribution(&dist, &mut rng))
+            .collect();
+        assert!(samples
+            .iter()
+            .all(|sample| (2.0_f64..3.0_f64).contains(sample)));
+        // The uniform distribution on [2, 3) has mean 2.5 and standard deviation 1 / sqrt(12).
+        let mean = samples.iter().sum::<f64>() / n_samples as f64;
+        assert!((mean - 2.5_f64).abs() < mean_tolerance(1.0_f64 / 12.0_f64.sqrt(), n_samples));
+    }
+
+    #[test]
+    fn test_noise_with_rng() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        // The same seed gives the same stream of noise, and a different seed a different one.
+        let stream = |seed: u64| -> Vec<i64> {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..100)
+                .map(|_| noise_with_rng(&mut rng, 1.0_f64, 0.1_f64).unwrap())
+                .collect()
+        };
+        assert_eq!(stream(7), stream(7));
+        assert_ne!(stream(7), stream(8));
+
+        // The seeded noise has the same distribution as `noise`: Laplace with scale
+        // l1_sensitivity / epsilon has variance 2 * (l1_sensitivity / epsilon)^2.
+        let mut rng = seeded_rng(6);
+        let n_samples = SAMPLE_COUNT;
+        let samples: Vec<i64> = (0..n_samples)
+            .map(|_| noise_with_rng(&mut rng, 1.0_f64, 0.5_f64).unwrap())
+            .collect();
+        let (mean, _) = mean_and_variance(&samples);
+        assert!(mean.abs() < mean_tolerance(2.0_f64.sqrt() * 2.0_f64, n_samples));
+        assert!(noise_with_rng(&mut rng, 1.0_f64, -1.0_f64).is_err());
+    }
+
+    #[test]
+    fn test_encode_bits() {
+        let dimension = 8;
+        let shift = shift_value_for_dimension(dimension).unwrap() as isize;
+        assert_eq!(
+            encode_bits(5, 0, dimension).unwrap(),
+            vec![1, 0, 1, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            encode_bits(-3, shift, dimension).unwrap(),
+            vec![1, 0, 1, 1, 1, 1, 1, 0]
+        );
+        for value in -shift..shift {
+            let bits = encode_bits(value, shift, dimension).unwrap();
+            assert_eq!(bits.len(), dimension);
+            assert_eq!(decode_bits(&bits, shift).unwrap(), value);
+        }
+        // Values whose shifted value doesn't fit.
+        assert_eq!(
+            encode_bits(-shift - 1, shift, dimension),
+            Err(ParameterError::ValueOutOfRange)
+        );
+        assert_eq!(
+            encode_bits(shift, shift, dimension),
+            Err(ParameterError::ValueOutOfRange)
+        );
+        assert_eq!(
+            encode_bits(isize::MAX, 1, dimension),
+            Err(ParameterError::Overflow)
+        );
+        assert_eq!(
+            encode_bits(0, 0, 0),
+            Err(ParameterError::DimensionOutOfRange)
+        );
+        assert_eq!(
+            encode_bits(0, 0, MAX_DIMENSION + 1),
+            Err(ParameterError::DimensionOutOfRange)
+        );
+        // The full width of the largest dimension.
+        let bits = encode_bits(isize::MAX, 0, MAX_DIMENSION).unwrap();
+        assert_eq!(decode_bits(&bits, 0).unwrap(), isize::MAX);
+
+        assert_eq!(
+            decode_bits(&[1, 2], 0),
+            Err(ParameterError::ValueOutOfRange)
+        );
+        assert_eq!(
+            decode_bits(&[], 0),
+            Err(ParameterError::DimensionOutOfRange)
+        );
+        assert_eq!(decode_bits(&[1; 64], 0), Err(ParameterError::Overflow));
+    }
+
+    #[test]
+    fn test_check_bits() {
+        let dimension = 8;
+        let shift = shift_value_for_dimension(dimension).unwrap() as isize;
+        let value = -37;
+        let bits = encode_bits(value, shift, dimension).unwrap();
+        assert_eq!(check_bits(&bits, value, shift), Ok(()));
+        assert_eq!(check_bits(&bits, value + 1, shift), Err(vec![0, 1, 2]));
+
+        // Split the bits into additive shares (here modulo 2^32), as a client splits its vector
+        // between the servers, and corrupt a single position of one of them.
+        let mut rng = seeded_rng(24);
+        let share1: Vec<u32> = (0..dimension).map(|_| rng.gen::<u32>()).collect();
+        let mut share2: Vec<u32> = bits
+            .iter()
+            .zip(share1.iter())
+            .map(|(bit, share)| bit.wrapping_sub(*share))
+            .collect();
+        let reconstruct = |share2: &[u32]| -> Vec<u32> {
+            share1
+                .iter()
+                .zip(share2.iter())
+                .map(|(share1, share2)| share1.wrapping_add(*share2))
+                .collect()
+        };
+        assert_eq!(check_bits(&reconstruct(&share2), value, shift), Ok(()));
+        for position in 0..dimension {
+            share2[position] ^= 1;
+            assert_eq!(
+                check_bits(&reconstruct(&share2), value, shift),
+                Err(vec![position])
+            );
+            share2[position] ^= 1;
+        }
+
+        // A value that doesn't fit in the vector can't match any of its positions.
+        assert_eq!(
+            check_bits(&bits, shift, shift),
+            Err((0..dimension).collect())
+        );
+    }
+
+    #[test]
+    fn test_decode_sum() {
+        // The modulus of prio's Field32.
+        let modulus = 4293918721_u64;
+        let shift = shift_value_for_dimension(8).unwrap();
+        // Three contributions of 30, -100, and -50: the raw sum is less than the total shift.
+        let contributions = [30_isize, -100_isize, -50_isize];
+        let raw_sum: u64 = contributions
+            .iter()
+            .map(|contribution| {
+                decode_bits(&encode_bits(*contribution, shift as isize, 8).unwrap(), 0).unwrap()
+                    as u64
+            })
+            .sum();
+        assert_eq!(raw_sum, 3 * shift - 120);
+        assert!(raw_sum.checked_sub(3 * shift).is_none());
+        assert_eq!(decode_sum(raw_sum, modulus, shift, 3).unwrap(), -120);
+        // The field subtraction wraps around instead.
+        assert_eq!((raw_sum + modulus - 3 * shift) % modulus, modulus - 120);
+        assert_eq!(decode_sum(3 * shift + 120, modulus, shift, 3).unwrap(), 120);
+        assert_eq!(decode_sum(0, modulus, 0, 0).unwrap(), 0);
+
+        assert_eq!(
+            decode_sum(modulus, modulus, shift, 3),
+            Err(ParameterError::ValueOutOfRange)
+        );
+        assert_eq!(
+            decode_sum(0, modulus, shift, modulus as usize),
+            Err(ParameterError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_noise_parameters() {
+        let cases = [
+            (1.0_f64, 0.0_f64, ParameterError::NonPositiveEpsilon),
+            (1.0_f64, -0.1_f64, ParameterError::NonPositiveEpsilon),
+            (1.0_f64, f64::NAN, ParameterError::NonPositiveEpsilon),
+            (
+                1.0_f64,
+                f64::NEG_INFINITY,
+                ParameterError::NonPositiveEpsilon,
+            ),
+            (1.0_f64, f64::INFINITY, ParameterError::EpsilonTooLarge),
+            (0.0_f64, 0.1_f64, ParameterError::SensitivityOutOfRange),
+            (-1.0_f64, 0.1_f64, ParameterError::SensitivityOutOfRange),
+            (f64::NAN, 0.1_f64, ParameterError::SensitivityOutOfRange),
+            (
+                f64::INFINITY,
+                0.1_f64,
+                ParameterError::SensitivityOutOfRange,
+            ),
+        ];
+        let mut rng = seeded_rng(7);
+        for (l1_sensitivity, epsilon, error) in cases.iter() {
+            assert_eq!(noise(*l1_sensitivity, *epsilon), Err(*error));
+            assert_eq!(
+                noise_with_rng(&mut rng, *l1_sensitivity, *epsilon),
+                Err(*error)
+            );
+            assert_eq!(
+                noise_with_granularity(*l1_sensitivity, *epsilon, GRANULARITY_PARAM),
+                Err(*error)
+            );
+        }
+        assert!(noise(1.0_f64, 0.1_f64).is_ok());
+    }
+
+    #[test]
+    fn test_noise_with_granularity() {
+        // For an l1 sensitivity of 1 and epsilon 0.1, l1_sensitivity / epsilon rounds up to 16.
+        assert_eq!(
+            get_granularity_with_param(1.0_f64, 0.1_f64, GRANULARITY_PARAM).unwrap(),
+            get_granularity(1.0_f64, 0.1_f64).unwrap()
+        );
+        assert_eq!(
+            get_granularity_with_param(1.0_f64, 0.1_f64, 4.0_f64).unwrap(),
+            4.0_f64
+        );
+        // A parameter that gives a granularity above 1 would make all of the noise a multiple of
+        // the granularity, so it's rejected. The smallest accepted parameter is the one that gives
+        // a granularity of exactly 1.
+        for granularity_param in [1.0_f64, 4.0_f64, 8.0_f64].iter() {
+            assert_eq!(
+                noise_with_granularity(1.0_f64, 0.1_f64, *granularity_param),
+                Err(ParameterError::GranularityOutOfRange)
+            );
+        }
+        assert!(noise_with_granularity(1.0_f64, 0.1_f64, 16.0_f64).is_ok());
+
+        // With the default parameter, the noise has the same distribution as `noise`.
+        let n_samples = SAMPLE_COUNT;
+        let mut rng = seeded_rng(21);
+        let samples: Vec<i64> = (0..n_samples)
+            .map(|_| {
+                noise_with_granularity_and_rng(&mut rng, 1.0_f64, 0.5_f64, GRANULARITY_PARAM)
+                    .unwrap()
+            })
+            .collect();
+        let (mean, _) = mean_and_variance(&samples);
+        assert!(mean.abs() < mean_tolerance(2.0_f64.sqrt() * 2.0_f64, n_samples));
+
+        for granularity_param in [0.0_f64, -2.0_f64, 0.5_f64, 3.0_f64, f64::NAN].iter() {
+            assert_eq!(
+                noise_with_granularity(1.0_f64, 0.1_f64, *granularity_param),
+                Err(ParameterError::GranularityOutOfRange)
+            );
+        }
+        assert_eq!(
+            noise_with_granularity(1.0_f64, -1.0_f64, GRANULARITY_PARAM),
+            Err(ParameterError::NonPositiveEpsilon)
+        );
+    }
+
+    #[cfg(feature = "noise-dump")]
+    #[test]
+    fn test_dump_noise_samples() {
+        let (l1_sensitivity, epsilon) = (1.0_f64, 0.5_f64);
+        let n_samples = 1000;
+        let path = std::env::temp_dir().join(format!("dprio-noise-{}.txt", std::process::id()));
+        dump_noise_samples(&path, l1_sensitivity, epsilon, n_samples).unwrap();
+        let dumped = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut lines = dumped.lines();
+        let granularity: f64 = lines.next().unwrap()["granularity ".len()..]
+            .parse()
+            .unwrap();
+        assert_eq!(
+            granularity,
+            get_granularity(l1_sensitivity, epsilon).unwrap()
+        );
+        let lambda: f64 = lines.next().unwrap()["lambda ".len()..].parse().unwrap();
+        assert_eq!(lambda, epsilon_to_lambda(l1_sensitivity, epsilon).unwrap());
+        let samples: Vec<i64> = lines.map(|line| line.parse().unwrap()).collect();
+        assert_eq!(samples.len(), n_samples);
+        // Each sample exceeds this bound in magnitude with probability 10^-12 (see `min_bits`).
+        let bound = 12.0_f64 * 10.0_f64.ln() * l1_sensitivity / epsilon;
+        assert!(samples
+            .iter()
+            .all(|sample| (*sample as f64 * granularity).abs() <= bound));
+        assert!(samples.iter().any(|sample| *sample != 0));
+    }
+
+    #[test]
+    fn test_sample_geometric() {
+        let mut rng = seeded_rng(8);
+        assert!(sample_geometric(&mut rng, 2.0_f64.powi(-60)).is_err());
+        // The binary search has to converge to the right distribution for both large and small
+        // lambda (with small lambda, the samples span many orders of magnitude).
+        for lambda in [2.0_f64, 0.5_f64, 0.01_f64, 1e-6_f64].iter() {
+            let p = -libm::expm1(-lambda);
+            let n_samples = SAMPLE_COUNT;
+            let samples: Vec<i64> = (0..n_samples)
+                .map(|_| sample_geometric(&mut rng, *lambda).unwrap())
+                .collect();
+            assert!(samples.iter().all(|sample| *sample >= 1));
+            let (mean, _) = mean_and_variance(&samples);
+            let std_dev = (1.0_f64 - p).sqrt() / p;
+            assert!((mean - 1.0_f64 / p).abs() < mean_tolerance(std_dev, n_samples));
+            // With lambda = 1e-6, a 1 is expected in only a few percent of runs, too rarely for
+            // the normal approximation behind frequency_tolerance.
+            if p * n_samples as f64 >= 10.0_f64 {
+                let ones = samples.iter().filter(|sample| **sample == 1).count() as f64;
+                assert!((ones / n_samples as f64 - p).abs() < frequency_tolerance(p, n_samples));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_two_sided_geometric() {
+        let mut rng = seeded_rng(9);
+        // As for sample_geometric, both large and small lambda (where the binary search has to
+        // cover a wide range) must give the right distribution.
+        for lambda in [2.0_f64, 0.5_f64, 0.01_f64, 1e-4_f64].iter() {
+            // The two-sided geometric distribution with q = e^-lambda has
+            // P(X = k) = (1 - q) / (1 + q) * q^|k|.
+            let q = libm::exp(-lambda);
+            let one_minus_q = -libm::expm1(-lambda);
+            let n_samples = SAMPLE_COUNT;
+            let samples = two_sided_geometric_samples(&mut rng, *lambda, n_samples).unwrap();
+
+            // Its mean is 0, its variance is 2q / (1 - q)^2, and its fourth moment is
+            // 2 (1 - q) / (1 + q) * sum_k k^4 q^k, with
+            // sum_k k^4 q^k = q (1 + 11q + 11q^2 + q^3) / (1 - q)^5.
+            let expected_variance = 2.0_f64 * q / one_minus_q.powi(2);
+            let fourth_moment = 2.0_f64 * one_minus_q / (1.0_f64 + q)
+                * q
+                * (1.0_f64 + 11.0_f64 * q + 11.0_f64 * q * q + q * q * q)
+                / one_minus_q.powi(5);
+            let kurtosis = fourth_moment / expected_variance.powi(2);
+            let (mean, variance) = mean_and_variance(&samples);
+            assert!(mean.abs() < mean_tolerance(expected_variance.sqrt(), n_samples));
+            assert!(
+                (variance - expected_variance).abs()
+                    < variance_tolerance(expected_variance, kurtosis, n_samples)
+            );
+
+            // Bin the samples into 0, the positive values, and the tail beyond the scale 1 /
+            // lambda (on either side), whose probabilities are (1 - q) / (1 + q), q / (1 + q),
+            // and 2 q^k / (1 + q) for a tail starting at k.
+            let tail_start = (1.0_f64 / lambda).ceil() as i64;
+            let bins = [
+                (
+                    samples.iter().filter(|sample| **sample == 0).count(),
+                    one_minus_q / (1.0_f64 + q),
+                ),
+                (
+                    samples.iter().filter(|sample| **sample > 0).count(),
+                    q / (1.0_f64 + q),
+                ),
+                (
+                    samples
+                        .iter()
+                        .filter(|sample| sample.abs() >= tail_start)
+                        .count(),
+                    2.0_f64 * libm::exp(-lambda * tail_start as f64) / (1.0_f64 + q),
+                ),
+            ];
+            for (count, probability) in bins.iter() {
+                // With small lambda, a 0 is too rare for the normal approximation behind
+                // frequency_tolerance (see test_sample_geometric).
+                if probability * n_samples as f64 >= 10.0_f64 {
+                    let frequency = *count as f64 / n_samples as f64;
+                    assert!(
+                        (frequency - probability).abs()
+                            < frequency_tolerance(*probability, n_samples)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_geometric_split_probability() {
+        // For small x, 1 - e^(-x) = x (1 - x/2 + x^2/6 - x^3/24 + ...), and four terms of the
+        // series are accurate to well beyond f64 precision for x <= 10^-6.
+        let one_minus_exp_neg =
+            |x: f64| x * (1.0_f64 - x / 2.0_f64 + x * x / 6.0_f64 - x * x * x / 24.0_f64);
+        let cases = [
+            (2.0_f64.powi(-58), 1, 2),
+            (2.0_f64.powi(-58), 3, 7),
+            (2.0_f64.powi(-40), 1 << 20, 1 << 21),
+            (1e-9_f64, 1000, 3000),
+            (1e-12_f64, 123_456, 1_000_000),
+        ];
+        for (lambda, d_mid, d_right) in cases.iter() {
+            let reference = one_minus_exp_neg(lambda * *d_mid as f64)
+                / one_minus_exp_neg(lambda * *d_right as f64);
+            let q = geometric_split_probability(*lambda, *d_mid, *d_right);
+            assert!(((q - reference) / reference).abs() < 1e-12_f64);
+        }
+        // Computed naively, 1 - e^(-x) is 0 for x this small.
+        let lambda = 2.0_f64.powi(-58);
+        assert_eq!(1.0_f64 - (-lambda).exp(), 0.0_f64);
+        // For large x, it's within rounding error of 1.
+        assert!((geometric_split_probability(1.0_f64, 40, 80) - 1.0_f64).abs() < 1e-15_f64);
+        assert!(
+            (geometric_split_probability(1.0_f64, 1, 2) - 1.0_f64 / (1.0_f64 + (-1.0_f64).exp()))
+                .abs()
+                < 1e-15_f64
+        );
+    }
+
+    #[test]
+    fn test_power_of_two() {
+        let cases = [
+            // (x, up, down, nearest)
+            (5.0_f64, 8.0_f64, 4.0_f64, 4.0_f64),
+            (7.0_f64, 8.0_f64, 4.0_f64, 8.0_f64),
+            (6.0_f64, 8.0_f64, 4.0_f64, 8.0_f64),
+            (1.5_f64, 2.0_f64, 1.0_f64, 2.0_f64),
+            (1000.0_f64, 1024.0_f64, 512.0_f64, 1024.0_f64),
+            (0.3_f64, 1.0_f64, 1.0_f64, 1.0_f64),
+            (16.0_f64, 16.0_f64, 16.0_f64, 16.0_f64),
+        ];
+        for (x, up, down, nearest) in cases.iter() {
+            assert_eq!(power_of_two(*x, RoundingMode::Up).unwrap(), *up);
+            assert_eq!(power_of_two(*x, RoundingMode::Down).unwrap(), *down);
+            assert_eq!(power_of_two(*x, RoundingMode::Nearest).unwrap(), *nearest);
+            assert_eq!(ceil_power_of_two(*x).unwrap(), *up);
+        }
+        for x in [-1.0_f64, f64::NAN, f64::INFINITY, (1024.0_f64).exp2()].iter() {
+            assert!(power_of_two(*x, RoundingMode::Down).is_err());
+        }
+        // Every exact power of 2 in range, and the values on either side of it.
+        for exponent in 0..=1023 {
+            let x = (exponent as f64).exp2();
+            assert_eq!(ceil_power_of_two(x).unwrap(), x);
+            assert_eq!(power_of_two(x, RoundingMode::Down).unwrap(), x);
+            let below = f64::from_bits(x.to_bits() - 1);
+            let expected_below = if exponent == 0 { 1.0_f64 } else { x };
+            assert_eq!(ceil_power_of_two(below).unwrap(), expected_below);
+            if exponent < 1023 {
+                let above = f64::from_bits(x.to_bits() + 1);
+                assert_eq!(ceil_power_of_two(above).unwrap(), 2.0_f64 * x);
+                assert_eq!(power_of_two(above, RoundingMode::Down).unwrap(), x);
+                assert_eq!(power_of_two(above, RoundingMode::Nearest).unwrap(), x);
+            } else {
+                assert!(ceil_power_of_two(f64::from_bits(x.to_bits() + 1)).is_err());
+            }
+        }
+        for x in [
+            0.0_f64,
+            f64::MIN_POSITIVE,
+            1e-300_f64,
+            5e-324_f64,
+            0.999_f64,
+        ]
+        .iter()
+        {
+            assert_eq!(ceil_power_of_two(*x).unwrap(), 1.0_f64);
+        }
+        assert_eq!(
+            get_granularity_with_rounding(1.0_f64, 0.1_f64, RoundingMode::Up).unwrap(),
+            get_granularity(1.0_f64, 0.1_f64).unwrap()
+        );
+        assert_eq!(
+            get_granularity_with_rounding(1.0_f64, 0.1_f64, RoundingMode::Down).unwrap() * 2.0_f64,
+            get_granularity(1.0_f64, 0.1_f64).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_min_bits() {
+        // The epsilons of the example, for a count. The bound is about 6 ln(10) / epsilon, e.g.
+        // 552.6 for epsilon = 0.025, which takes 10 bits.
+        let expected_bits = [
+            (0.025_f64, 10),
+            (0.05_f64, 9),
+            (0.1_f64, 8),
+            (0.2_f64, 7),
+            (0.4_f64, 6),
+            (0.8_f64, 5),
+        ];
+        for (epsilon, bits) in expected_bits.iter() {
+            assert_eq!(min_bits(1.0_f64, *epsilon).unwrap(), *bits);
+            let bound = noise_bound(1.0_f64, *epsilon).unwrap();
+            assert!(bound <= 2.0_f64.powi(*bits as i32));
+            assert!(bound > 2.0_f64.powi(*bits as i32 - 1));
+        }
+        // Doubling the sensitivity doubles the bound, which takes one more bit.
+        assert_eq!(min_bits(2.0_f64, 0.1_f64).unwrap(), 9);
+
+        // Samples of `noise` stay within the bound (each exceeds it with probability 10^-6).
+        let max_magnitude = 2.0_f64.powi(min_bits(1.0_f64, 0.8_f64).unwrap() as i32);
+        let mut rng = seeded_rng(20);
+        let exceeding = (0..SAMPLE_COUNT)
+            .filter(|_| {
+                noise_with_rng(&mut rng, 1.0_f64, 0.8_f64).unwrap().abs() as f64 > max_magnitude
+            })
+            .count();
+        assert!(exceeding <= 1);
+
+        assert!(min_bits(1.0_f64, 0.0_f64).is_err());
+        assert!(min_bits(1.0_f64, f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_verify_dimension_sufficient() {
+        // The epsilons of the example, for a count.
+        for epsilon in [0.025_f64, 0.05_f64, 0.1_f64, 0.2_f64, 0.4_f64, 0.8_f64].iter() {
+            let dimension = min_bits(1.0_f64, *epsilon).unwrap() + 1;
+            assert!(verify_dimension_sufficient(dimension, 1.0_f64, *epsilon).is_ok());
+            assert!(verify_dimension_sufficient(dimension - 1, 1.0_f64, *epsilon).is_err());
+            assert!(verify_dimension_sufficient(dimension + 1, 1.0_f64, *epsilon).is_ok());
+        }
+        // For epsilon = 10, the bound is about 1.38, so noise of 2 must fit, which takes more than
+        // min_bits + 1 = 2 bits.
+        let dimension = min_bits(1.0_f64, 10.0_f64).unwrap() + 1;
+        assert_eq!(dimension, 2);
+        assert!(verify_dimension_sufficient(dimension, 1.0_f64, 10.0_f64).is_err());
+        assert!(verify_dimension_sufficient(dimension + 1, 1.0_f64, 10.0_f64).is_ok());
+        assert!(verify_dimension_sufficient(0, 1.0_f64, 0.1_f64).is_err());
+        assert!(verify_dimension_sufficient(MAX_DIMENSION + 1, 1.0_f64, 0.1_f64).is_err());
+    }
+
+    #[test]
+    fn test_epsilon_for_target_error() {
+        for (target_error, l1_sensitivity, confidence) in [
+            (10.0_f64, 1.0_f64, 0.95_f64),
+            (100.0_f64, 1.0_f64, 0.99_f64),
+            (3.0_f64, 2.0_f64, 0.5_f64),
+        ]
+        .iter()
+        {
+            let epsilon =
+                epsilon_for_target_error(*target_error, *l1_sensitivity, *confidence).unwrap();
+            let result = DpResult::new(1000, *l1_sensitivity, epsilon, 1, *confidence).unwrap();
+            let half_width =
+                (result.confidence_interval.1 - result.confidence_interval.0) / 2.0_f64;
+            assert!((half_width - target_error).abs() < 1e-9_f64 * target_error);
+            // A looser target allows a smaller epsilon.
+            assert!(
+                epsilon_for_target_error(2.0_f64 * target_error, *l1_sensitivity, *confidence)
+                    .unwrap()
+                    < epsilon
+            );
+        }
+        assert!(epsilon_for_target_error(0.0_f64, 1.0_f64, 0.95_f64).is_err());
+        assert!(epsilon_for_target_error(10.0_f64, -1.0_f64, 0.95_f64).is_err());
+        assert!(epsilon_for_target_error(10.0_f64, 1.0_f64, 1.0_f64).is_err());
+        assert!(epsilon_for_target_error(f64::NAN, 1.0_f64, 0.95_f64).is_err());
+    }
 }
@@ -2,63 +2,401 @@ extern crate byteorder;
 extern crate rand;
 extern crate sha2;
 
-use byteorder::{NetworkEndian, WriteBytesExt};
-use rand::distributions::{Distribution, Uniform};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use events::{Event, EventSink, NoopSink};
+use rand::Rng;
 use sha2::{Digest, Sha256};
+use std::borrow::Borrow;
+use std::cmp;
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
 
+pub mod capacity;
+pub mod events;
+pub mod exact;
+pub mod gaussian;
 pub mod laplace;
+pub mod permutation;
+#[cfg(feature = "persistence")]
+pub mod persistence;
+#[cfg(feature = "signatures")]
+pub mod signing;
+#[cfg(test)]
+mod test_support;
+
+// A value identifying a deployment of DPrio (e.g. a particular study), which is included in the
+// hash of every commitment. Commitments made with one personalization never validate with
+// another, so commitments can't be replayed across deployments. Every party in a deployment must
+// be configured with the same personalization.
+pub type Personalization = [u8; 16];
+
+// The personalization of commitments that aren't given one.
+pub const DEFAULT_PERSONALIZATION: Personalization = [0; 16];
+
+// A random value hashed along with p and revealed together with it. Without it, when n is small,
+// an adversary who sees a closed commitment could hash every candidate value of p and so recover
+// the committed index before it's published.
+pub type Nonce = [u8; 16];
 
-pub struct Commitment {
+// A commitment is hashed with D, which can be any hash function implementing `Digest` (e.g.
+// Sha512_256, which is faster on 64-bit platforms). Every party in a deployment must use the same
+// one: a closed commitment made with one hash function never validates with another.
+pub struct Commitment<D = Sha256> {
     n: u64,
     p: u64,
+    nonce: Nonce,
+    personalization: Personalization,
+    digest: PhantomData<D>,
 }
 
 impl Commitment {
-    pub fn new(n: u64) -> Commitment {
-        let factor = u64::MAX / n;
-        let between = Uniform::new_inclusive(0, n * factor);
-        let mut rng = rand::thread_rng();
+    // A commitment for a selection from a corpus of size n, which must be positive.
+    pub fn new(n: u64) -> Result<Commitment, CommitmentError> {
+        Commitment::new_with_rng(n, &mut rand::thread_rng())
+    }
+
+    // Like `new`, but draws the published value and the nonce from rng, so that a seeded rng
+    // reproduces the commitment (e.g. in tests). Outside of tests, rng must be a cryptographically
+    // secure one, as the nonce is what hides the committed value.
+    pub fn new_with_rng<R: Rng>(n: u64, rng: &mut R) -> Result<Commitment, CommitmentError> {
+        Commitment::new_with_digest_and_rng(n, rng)
+    }
+
+    #[cfg(test)]
+    fn new_with_p(n: u64, p: u64) -> Commitment {
+        Commitment::new_with_p_and_digest(n, p, &mut rand::thread_rng())
+    }
+}
+
+impl<D: Digest> Commitment<D> {
+    // Like `new`, but hashed with D rather than Sha256.
+    pub fn new_with_digest(n: u64) -> Result<Commitment<D>, CommitmentError> {
+        Commitment::new_with_digest_and_rng(n, &mut rand::thread_rng())
+    }
+
+    // Like `new_with_rng`, but hashed with D rather than Sha256.
+    pub fn new_with_digest_and_rng<R: Rng>(
+        n: u64,
+        rng: &mut R,
+    ) -> Result<Commitment<D>, CommitmentError> {
+        if n == 0 {
+            return Err(CommitmentError::InvalidCorpusSize);
+        }
+        let p = sample_published_value(n, rng);
+        Ok(Commitment::new_with_p_and_digest(n, p, rng))
+    }
+
+    fn new_with_p_and_digest<R: Rng>(n: u64, p: u64, rng: &mut R) -> Commitment<D> {
         Commitment {
             n,
-            p: between.sample(&mut rng),
+            p,
+            nonce: rng.gen(),
+            personalization: DEFAULT_PERSONALIZATION,
+            digest: PhantomData,
         }
     }
 
-    fn new_with_p(n: u64, p: u64) -> Commitment {
-        Commitment { n, p }
+    pub fn with_personalization(mut self, personalization: Personalization) -> Commitment<D> {
+        self.personalization = personalization;
+        self
+    }
+
+    pub fn commit(&self) -> ClosedCommitment<D> {
+        self.commit_with_challenge(&[])
     }
 
-    pub fn commit(&self) -> ClosedCommitment {
-        let mut buf = Vec::with_capacity(std::mem::size_of::<u64>());
-        buf.write_u64::<NetworkEndian>(self.p).unwrap();
-        let hash = Sha256::digest(&buf);
-        ClosedCommitment::new(self.n, hash.to_vec())
+    // The second round of the interactive variant of the commitment. In the interactive variant,
+    // the participants commit as usual, then a fresh random challenge is broadcast, and each
+    // participant sends the closed commitment made with the challenge, which is validated with
+    // `ClosedCommitment::validate_with_challenge`. Since the challenge isn't known in advance, the
+    // closed commitments can't have been made (or precomputed) before the round started.
+    pub fn commit_with_challenge(&self, challenge: &[u8]) -> ClosedCommitment<D> {
+        ClosedCommitment::new_with_digest(
+            self.n,
+            commitment_hash::<D>(
+                &self.personalization,
+                &self.nonce,
+                challenge,
+                self.n,
+                self.p,
+            ),
+        )
+        .with_personalization(self.personalization)
     }
 
-    pub fn publish(&self) -> u64 {
-        self.p
+    // Returns the value to publish, along with the nonce needed to validate it.
+    pub fn publish(&self) -> (u64, Nonce) {
+        (self.p, self.nonce)
+    }
+
+    // Like `publish`, but first checks that the value being published opens the given closed
+    // commitment. This catches a caller publishing a value for a different commitment than the
+    // one it sent out.
+    pub fn publish_checked(
+        &self,
+        closed_commitment: &ClosedCommitment<D>,
+    ) -> Result<(u64, Nonce), CommitmentError> {
+        closed_commitment.validate(self.p, &self.nonce)?;
+        Ok(self.publish())
     }
 }
 
-pub struct ClosedCommitment {
+impl<D> Commitment<D> {
+    #[cfg(feature = "zeroize")]
+    fn zeroize_secrets(&mut self) {
+        use zeroize::Zeroize;
+
+        self.p.zeroize();
+        self.nonce.zeroize();
+    }
+}
+
+// With the "zeroize" feature, p and the nonce are wiped when a commitment is dropped, so that they
+// don't linger in freed memory of a long-running server. The values returned by `publish` are
+// copies, and wiping them is up to the caller.
+#[cfg(feature = "zeroize")]
+impl<D> Drop for Commitment<D> {
+    fn drop(&mut self) {
+        self.zeroize_secrets();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<D> zeroize::ZeroizeOnDrop for Commitment<D> {}
+
+// The largest value a participant publishes for a corpus of size n. The published values are
+// uniform in [0, n * factor), where n * factor is the largest multiple of n that fits in a u64.
+// The range contains exactly factor values of each residue modulo n, so the published values are
+// uniform modulo n, and hence so is the gathered index (as long as any participant is honest).
+// Since factor = floor(u64::MAX / n), n * factor can't overflow (even for n close to u64::MAX), and
+// since n * factor >= n >= 1, subtracting 1 can't either.
+fn max_published_value(n: u64) -> u64 {
+    let factor = u64::MAX / n;
+    n * factor - 1
+}
+
+// Samples a published value by rejection: a uniform u64 is drawn until it's at most
+// max_published_value(n). Reducing the u64 modulo n directly (or drawing from a range whose size
+// isn't a multiple of n) would favor some indices. At most n - 1 of the 2^64 values are rejected,
+// so this almost never loops.
+fn sample_published_value<R: Rng>(n: u64, rng: &mut R) -> u64 {
+    let max = max_published_value(n);
+    loop {
+        let p = rng.gen::<u64>();
+        if p <= max {
+            return p;
+        }
+    }
+}
+
+// Prefixes the preimage of every commitment hash, so that a closed commitment can't be mistaken
+// for (or replayed as) a hash made by another protocol, or by another version of this one.
+const COMMITMENT_DOMAIN_TAG: &[u8] = b"dprio-commitment-v1";
+
+// Everything but the challenge has a fixed length, so the encoding is unambiguous. Without a
+// challenge, this is the hash of the domain tag, the personalization, the nonce, n, and p. n is
+// included so that a commitment can't be opened against a corpus of a different size.
+fn commitment_hash<D: Digest>(
+    personalization: &Personalization,
+    nonce: &Nonce,
+    challenge: &[u8],
     n: u64,
+    p: u64,
+) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(
+        COMMITMENT_DOMAIN_TAG.len()
+            + personalization.len()
+            + nonce.len()
+            + challenge.len()
+            + 2 * std::mem::size_of::<u64>(),
+    );
+    buf.extend_from_slice(COMMITMENT_DOMAIN_TAG);
+    buf.extend_from_slice(personalization);
+    buf.extend_from_slice(nonce);
+    buf.write_u64::<NetworkEndian>(n).unwrap();
+    buf.extend_from_slice(challenge);
+    buf.write_u64::<NetworkEndian>(p).unwrap();
+    D::digest(&buf).to_vec()
+}
+
+// The number of values of p `verify_binding` tries.
+const BINDING_SEARCH_SPACE: u64 = 1 << 12;
+
+// A self-test of the commitment scheme: searches the first BINDING_SEARCH_SPACE values of p (with a
+// fixed nonce) for two that result in the same closed commitment, which would allow a participant
+// to open its commitment to either value. Returns true if there are none. This can't prove that
+// the scheme is binding, but it catches a broken `commit` (e.g. one that doesn't depend on p, or
+// only on some of its bits).
+pub fn verify_binding<D: Digest>() -> bool {
+    let mut hashes = HashSet::new();
+    (0..BINDING_SEARCH_SPACE).all(|p| {
+        hashes.insert(commitment_hash::<D>(
+            &DEFAULT_PERSONALIZATION,
+            &[0; 16],
+            &[],
+            u64::MAX,
+            p,
+        ))
+    })
+}
+
+// With the `serde` feature, closed commitments can be sent between servers in any serde format.
+// The hash is serialized as a sequence of bytes. Neither the hash function nor the
+// personalization is serialized, as the receiving party must use its own (as with `to_bytes`): a
+// deserialized commitment has the default personalization, so a deployment with its own should set
+// it with `with_personalization`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound = ""))]
+pub struct ClosedCommitment<D = Sha256> {
+    n: u64,
+    // The digest of the committed value (see `commitment_hash`), never the value itself. With the
+    // default Sha256 this is 32 bytes.
     hash: Vec<u8>,
+    // Skipped deserializes to DEFAULT_PERSONALIZATION, which is all zeros.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    personalization: Personalization,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    digest: PhantomData<D>,
 }
 
 impl ClosedCommitment {
     pub fn new(n: u64, hash: Vec<u8>) -> ClosedCommitment {
-        ClosedCommitment { n, hash }
+        ClosedCommitment::new_with_digest(n, hash)
+    }
+}
+
+impl<D: Digest> ClosedCommitment<D> {
+    // Like `new`, for a commitment hashed with D rather than Sha256.
+    pub fn new_with_digest(n: u64, hash: Vec<u8>) -> ClosedCommitment<D> {
+        ClosedCommitment {
+            n,
+            hash,
+            personalization: DEFAULT_PERSONALIZATION,
+            digest: PhantomData,
+        }
+    }
+
+    // The corpus size the commitment is bound to, so a received commitment can be checked against
+    // the expected pool size before it's validated.
+    pub fn corpus_size(&self) -> u64 {
+        self.n
+    }
+
+    // Sets the personalization `validate` expects the commitment to have been made with, which
+    // should be that of the validating party's deployment.
+    pub fn with_personalization(mut self, personalization: Personalization) -> ClosedCommitment<D> {
+        self.personalization = personalization;
+        self
     }
 
-    pub fn validate(&self, p: u64) -> Result<OpenedCommitment, CommitmentError> {
-        let commitment = Commitment::new_with_p(self.n, p);
-        let hash = commitment.commit().hash;
+    // Validates a published value and its nonce against the commitment. The published value
+    // typically comes from the network, so it is checked to be one a participant could have
+    // published before it's used at all.
+    pub fn validate(&self, p: u64, nonce: &Nonce) -> Result<OpenedCommitment, CommitmentError> {
+        self.validate_with_challenge(p, nonce, &[])
+    }
+
+    // Validates a published value against a closed commitment made with
+    // `Commitment::commit_with_challenge` and the same challenge.
+    pub fn validate_with_challenge(
+        &self,
+        p: u64,
+        nonce: &Nonce,
+        challenge: &[u8],
+    ) -> Result<OpenedCommitment, CommitmentError> {
+        if self.n == 0 {
+            return Err(CommitmentError::EmptyCorpus);
+        }
+        if p > max_published_value(self.n) {
+            return Err(CommitmentError::PublishedValueOutOfRange);
+        }
+        let hash = commitment_hash::<D>(&self.personalization, nonce, challenge, self.n, p);
         if hash == self.hash {
             Ok(OpenedCommitment::new(self.n, p))
         } else {
             Err(CommitmentError::HashMismatch)
         }
     }
+
+    // The wire encoding of a closed commitment, for consumers that don't use serde: n, the length
+    // of the hash (both in network byte order), and the hash. The personalization isn't sent, as
+    // the receiving party must use its own (see `with_personalization`). This layout is stable.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(
+            std::mem::size_of::<u64>() + std::mem::size_of::<u32>() + self.hash.len(),
+        );
+        buf.write_u64::<NetworkEndian>(self.n).unwrap();
+        buf.write_u32::<NetworkEndian>(self.hash.len() as u32)
+            .unwrap();
+        buf.extend_from_slice(&self.hash);
+        buf
+    }
+}
+
+impl<D: Digest> ClosedCommitment<D> {
+    // The inverse of `to_bytes`. All of bytes must be consumed, so truncated or oversized input is
+    // rejected.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<ClosedCommitment<D>, CommitmentError> {
+        let n = bytes
+            .read_u64::<NetworkEndian>()
+            .map_err(|_| CommitmentError::MalformedBytes)?;
+        let hash_len = bytes
+            .read_u32::<NetworkEndian>()
+            .map_err(|_| CommitmentError::MalformedBytes)? as usize;
+        if bytes.len() != hash_len {
+            return Err(CommitmentError::MalformedBytes);
+        }
+        Ok(ClosedCommitment::new_with_digest(n, bytes.to_vec()))
+    }
+}
+
+// Encodes the closed commitments of a round as a single blob, so that a server can broadcast them
+// in one message: the number of commitments, followed by each commitment's encoding (see
+// `ClosedCommitment::to_bytes`) prefixed with its length, all in network byte order. Both are
+// encoded in 32 bits, so a round that doesn't fit is rejected rather than truncated.
+pub fn serialize_round<D: Digest>(
+    closed_commitments: &[ClosedCommitment<D>],
+) -> Result<Vec<u8>, CommitmentError> {
+    let mut buf = Vec::new();
+    let count =
+        u32::try_from(closed_commitments.len()).map_err(|_| CommitmentError::RoundTooLarge)?;
+    buf.write_u32::<NetworkEndian>(count).unwrap();
+    for closed_commitment in closed_commitments {
+        let encoded = closed_commitment.to_bytes();
+        let len = u32::try_from(encoded.len()).map_err(|_| CommitmentError::RoundTooLarge)?;
+        buf.write_u32::<NetworkEndian>(len).unwrap();
+        buf.extend_from_slice(&encoded);
+    }
+    Ok(buf)
+}
+
+// The inverse of `serialize_round`. The blob typically comes from the network, so any truncation
+// or trailing data is rejected. The commitments get the default personalization, so a deployment
+// with its own should set it on each of them.
+pub fn deserialize_round<D: Digest>(
+    mut bytes: &[u8],
+) -> Result<Vec<ClosedCommitment<D>>, CommitmentError> {
+    let count = bytes
+        .read_u32::<NetworkEndian>()
+        .map_err(|_| CommitmentError::MalformedBytes)?;
+    let mut closed_commitments = Vec::new();
+    for _ in 0..count {
+        let len = bytes
+            .read_u32::<NetworkEndian>()
+            .map_err(|_| CommitmentError::MalformedBytes)? as usize;
+        if bytes.len() < len {
+            return Err(CommitmentError::MalformedBytes);
+        }
+        let (encoded, rest) = bytes.split_at(len);
+        closed_commitments.push(ClosedCommitment::from_bytes(encoded)?);
+        bytes = rest;
+    }
+    if !bytes.is_empty() {
+        return Err(CommitmentError::MalformedBytes);
+    }
+    Ok(closed_commitments)
 }
 
 pub struct OpenedCommitment {
@@ -71,17 +409,41 @@ impl OpenedCommitment {
         OpenedCommitment { n, p }
     }
 
-    // TODO: how to make this anything that can iterate over OpenedCommitments?
-    pub fn gather(opened_commitments: &[OpenedCommitment]) -> Result<u64, CommitmentError> {
+    pub fn corpus_size(&self) -> u64 {
+        self.n
+    }
+
+    // Checks that p is a value a participant could have published for n (see
+    // `max_published_value`), as `ClosedCommitment::validate` does. Reducing an out-of-range p
+    // modulo n would bias the selected index.
+    pub fn validate_range(&self) -> Result<(), CommitmentError> {
+        if self.n == 0 {
+            return Err(CommitmentError::EmptyCorpus);
+        }
+        if self.p > max_published_value(self.n) {
+            return Err(CommitmentError::PublishedValueOutOfRange);
+        }
+        Ok(())
+    }
+
+    // Accepts anything that iterates over OpenedCommitments, by value or by reference, e.g. a slice
+    // or the receiving end of a channel, so they don't have to be collected first.
+    pub fn gather<I>(opened_commitments: I) -> Result<u64, CommitmentError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<OpenedCommitment>,
+    {
         let mut sum: u128 = 0;
         let mut n: Option<u64> = None;
         for opened_commitment in opened_commitments {
+            let opened_commitment = opened_commitment.borrow();
             if n.is_none() {
                 n.replace(opened_commitment.n);
             }
             if *n.as_ref().unwrap() != opened_commitment.n {
                 return Err(CommitmentError::CorpusSizeMismatch);
             }
+            opened_commitment.validate_range()?;
             sum += opened_commitment.p as u128;
         }
         let n = match n {
@@ -92,6 +454,632 @@ impl OpenedCommitment {
         };
         Ok((sum % n as u128) as u64)
     }
+
+    // Like `gather`, for deployments with more than two servers, some of which may drop out before
+    // revealing: sums whichever commitments were opened, provided there are at least min_parties
+    // of them. min_parties should be large enough that at least one of the opened commitments
+    // comes from an honest server; it's never allowed to be less than `min_honest_servers()`.
+    pub fn gather_threshold(
+        opened_commitments: &[OpenedCommitment],
+        min_parties: usize,
+    ) -> Result<u64, CommitmentError> {
+        if opened_commitments.len() < cmp::max(min_parties, min_honest_servers()) {
+            return Err(CommitmentError::InsufficientParties);
+        }
+        OpenedCommitment::gather(opened_commitments)
+    }
+
+    // Like `gather`, but also returns a transcript of each party's contribution and the selected
+    // index, from which an auditor can re-verify the selection (see `GatherTranscript::verify`).
+    pub fn gather_with_transcript(
+        opened_commitments: &[OpenedCommitment],
+    ) -> Result<(u64, GatherTranscript), CommitmentError> {
+        let index = OpenedCommitment::gather(opened_commitments)?;
+        let transcript = GatherTranscript {
+            contributions: opened_commitments
+                .iter()
+                .map(|opened_commitment| (opened_commitment.n, opened_commitment.p))
+                .collect(),
+            index,
+        };
+        Ok((index, transcript))
+    }
+
+    // Like `gather`, but for contributions that may be negative (e.g. in a variant of the scheme
+    // where a participant's contribution is signed). The contributions are accumulated as i128
+    // and reduced with the Euclidean modulo, so the result is always in [0, n).
+    pub fn gather_signed(n: u64, contributions: &[i64]) -> Result<u64, CommitmentError> {
+        // A corpus of size 0 is empty too.
+        if n == 0 || contributions.is_empty() {
+            return Err(CommitmentError::EmptyCorpus);
+        }
+        let sum: i128 = contributions
+            .iter()
+            .map(|contribution| *contribution as i128)
+            .sum();
+        Ok(sum.rem_euclid(n as i128) as u64)
+    }
+}
+
+// The (n, p) pair of each opened commitment, in the order they were gathered, and the selected
+// index, i.e. the sum of the p's modulo n.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GatherTranscript {
+    contributions: Vec<(u64, u64)>,
+    index: u64,
+}
+
+impl GatherTranscript {
+    pub fn contributions(&self) -> &[(u64, u64)] {
+        &self.contributions
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    // Re-verifies the transcript against the closed commitments the parties sent, in the same
+    // order as the contributions, and returns the selected index. The p's are only bound to the
+    // closed commitments by their nonces, so the auditor needs the published nonces too: each
+    // (p, nonce) is validated against its closed commitment, and the index is gathered again
+    // from the result.
+    pub fn verify<D: Digest>(
+        &self,
+        closed_commitments: &[ClosedCommitment<D>],
+        nonces: &[Nonce],
+    ) -> Result<u64, CommitmentError> {
+        if closed_commitments.len() != self.contributions.len()
+            || nonces.len() != self.contributions.len()
+        {
+            return Err(CommitmentError::OpeningCountMismatch);
+        }
+        let mut opened_commitments = Vec::with_capacity(self.contributions.len());
+        for ((closed_commitment, nonce), (n, p)) in closed_commitments
+            .iter()
+            .zip(nonces)
+            .zip(&self.contributions)
+        {
+            if closed_commitment.corpus_size() != *n {
+                return Err(CommitmentError::CorpusSizeMismatch);
+            }
+            opened_commitments.push(closed_commitment.validate(*p, nonce)?);
+        }
+        if OpenedCommitment::gather(&opened_commitments)? != self.index {
+            return Err(CommitmentError::IndexDisagreement);
+        }
+        Ok(self.index)
+    }
+}
+
+// Each server independently validates the published values and gathers the selected index from
+// its own view of the opened commitments. Before removing the selected noise from their corpora,
+// the servers must confirm they arrived at the same index, as otherwise their corpora would no
+// longer correspond to each other. This gathers the index from each server's view and returns it
+// if they agree.
+pub fn agree_on_index(
+    server1_opened_commitments: &[OpenedCommitment],
+    server2_opened_commitments: &[OpenedCommitment],
+) -> Result<u64, CommitmentError> {
+    let server1_index = OpenedCommitment::gather(server1_opened_commitments)?;
+    let server2_index = OpenedCommitment::gather(server2_opened_commitments)?;
+    if server1_index != server2_index {
+        return Err(CommitmentError::IndexDisagreement);
+    }
+    Ok(server1_index)
+}
+
+// The number of participants a RevealRound accepts unless configured otherwise.
+pub const DEFAULT_MAX_PARTICIPANTS: usize = 16;
+
+// The number of participants in a round of noise selection that must be honest for the selected
+// index to be uniformly random. See `security_assumptions`.
+pub fn min_honest_servers() -> usize {
+    1
+}
+
+// Describes the threat model of the noise selection, for deployment planning.
+pub fn security_assumptions() -> &'static str {
+    "Each participant in a round of noise selection commits to a value that is uniform modulo n \
+     before any value is published, and the selected index is the sum of the published values \
+     modulo n. As long as at least one participant is honest (its value is uniform and \
+     independent of the others' values, and it doesn't collude with them), the selected index is \
+     uniform, no matter how the other participants choose their values. A participant that \
+     doesn't publish its value can abort the round, but not bias it. The commitments must be \
+     hiding and binding, and every participant must validate every published value against its \
+     commitment before gathering."
+}
+
+// Collects the closed commitments of the participants in one round of noise selection. Once every
+// participant has published its value, `finalize` validates the published values against the
+// commitments and gathers the selected index. Since the commitments may come from untrusted
+// parties, the number of participants is capped to bound the memory a round can use.
+pub struct RevealRound {
+    n: u64,
+    max_participants: usize,
+    min_honest: usize,
+    // The challenge the closed commitments were made with (see `with_challenge`), empty if none.
+    challenge: Vec<u8>,
+    closed_commitments: Vec<ClosedCommitment>,
+    event_sink: Box<dyn EventSink>,
+}
+
+impl RevealRound {
+    pub fn new(n: u64) -> RevealRound {
+        RevealRound {
+            n,
+            max_participants: DEFAULT_MAX_PARTICIPANTS,
+            min_honest: min_honest_servers(),
+            challenge: Vec::new(),
+            closed_commitments: Vec::new(),
+            event_sink: Box::new(NoopSink),
+        }
+    }
+
+    pub fn with_max_participants(mut self, max_participants: usize) -> RevealRound {
+        self.max_participants = max_participants;
+        self
+    }
+
+    // Sets the number of participants assumed to be honest, which `finalize` requires the round to
+    // have at least. It should never be less than `min_honest_servers()`, but a deployment may
+    // require more (e.g. one honest participant per organization).
+    pub fn with_min_honest(mut self, min_honest: usize) -> RevealRound {
+        self.min_honest = min_honest;
+        self
+    }
+
+    // Makes the round expect closed commitments made with `Commitment::commit_with_challenge` and
+    // this challenge.
+    //
+    // Since the challenge is fresh for every round, two participants submitting the same closed
+    // commitment can't be a replay of an earlier round: either one participant copied the other's
+    // commitment (and will copy its published value), or the hashes collided. Both are resolved the
+    // same way, so that every server arrives at the same index without assuming that collisions
+    // are impossible: the validated openings are put in a canonical order (by hash, then published
+    // value, then nonce) and only the first opening of each hash counts towards the index. The
+    // participants sharing a hash therefore count as a single participant for the minimum number
+    // of honest participants. (Counting a copied value twice would also let a copier bias the
+    // index, e.g. 2p mod n isn't uniform when n is even.)
+    pub fn with_challenge(mut self, challenge: &[u8]) -> RevealRound {
+        self.challenge = challenge.to_vec();
+        self
+    }
+
+    pub fn with_event_sink(mut self, event_sink: Box<dyn EventSink>) -> RevealRound {
+        self.event_sink = event_sink;
+        self
+    }
+
+    // Adds a participant's closed commitment to the round. Returns the participant's index, which
+    // is the position of its published value in the values given to `finalize`.
+    pub fn submit(
+        &mut self,
+        closed_commitment: ClosedCommitment,
+    ) -> Result<usize, CommitmentError> {
+        if closed_commitment.n != self.n {
+            self.event_sink.record(Event::CommitmentRejected);
+            return Err(CommitmentError::CorpusSizeMismatch);
+        }
+        if self.closed_commitments.len() >= self.max_participants {
+            self.event_sink.record(Event::CommitmentRejected);
+            return Err(CommitmentError::TooManyParticipants);
+        }
+        self.event_sink.record(Event::CommitmentReceived);
+        self.closed_commitments.push(closed_commitment);
+        Ok(self.closed_commitments.len() - 1)
+    }
+
+    // Validates the published values (with their nonces) and gathers the selected index. The
+    // hashes of the consumed commitments are recorded in `seen_commitments`, so that a commitment
+    // opened in one round is rejected if it is replayed in a later round sharing the same set.
+    // Without a challenge, a commitment submitted twice within the round is rejected as a replay
+    // too (see `with_challenge` for how colliding commitments are handled with one).
+    pub fn finalize(
+        mut self,
+        published_values: &[(u64, Nonce)],
+        seen_commitments: &mut SeenCommitments,
+    ) -> Result<u64, CommitmentError> {
+        if self.closed_commitments.len() < cmp::max(self.min_honest, min_honest_servers()) {
+            return Err(CommitmentError::InsufficientParties);
+        }
+        if published_values.len() != self.closed_commitments.len() {
+            return Err(CommitmentError::OpeningCountMismatch);
+        }
+        let mut round_hashes = HashSet::new();
+        for closed_commitment in &self.closed_commitments {
+            if seen_commitments.hashes.contains(&closed_commitment.hash)
+                || (!round_hashes.insert(&closed_commitment.hash) && self.challenge.is_empty())
+            {
+                self.event_sink.record(Event::CommitmentRejected);
+                return Err(CommitmentError::ReplayedCommitment);
+            }
+        }
+        let mut openings = Vec::with_capacity(self.closed_commitments.len());
+        for (closed_commitment, (p, nonce)) in
+            self.closed_commitments.iter().zip(published_values.iter())
+        {
+            match closed_commitment.validate_with_challenge(*p, nonce, &self.challenge) {
+                Ok(_) => {
+                    self.event_sink.record(Event::OpeningValidated);
+                    openings.push((&closed_commitment.hash, *p, *nonce));
+                }
+                Err(e) => {
+                    self.event_sink.record(Event::OpeningRejected);
+                    return Err(e);
+                }
+            }
+        }
+        // Without a challenge, the hashes are distinct, so this doesn't remove anything.
+        openings.sort();
+        openings.dedup_by(|opening, first_opening| opening.0 == first_opening.0);
+        if openings.len() < cmp::max(self.min_honest, min_honest_servers()) {
+            return Err(CommitmentError::InsufficientParties);
+        }
+        let index = OpenedCommitment::gather(
+            openings
+                .iter()
+                .map(|(_, p, _)| OpenedCommitment::new(self.n, *p)),
+        )?;
+        self.event_sink.record(Event::IndexSelected);
+        for closed_commitment in self.closed_commitments {
+            seen_commitments.hashes.insert(closed_commitment.hash);
+        }
+        Ok(index)
+    }
+}
+
+// The hashes of the commitments consumed by previous reveal rounds. Only the hashes of rounds that
+// finalized successfully are recorded.
+#[derive(Default)]
+pub struct SeenCommitments {
+    hashes: HashSet<Vec<u8>>,
+}
+
+impl SeenCommitments {
+    pub fn new() -> SeenCommitments {
+        SeenCommitments::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
+
+// A server's share of one participant's noise, as encoded for the aggregation.
+pub type NoiseShare = Vec<u8>;
+
+// A server's corpus of noise shares to select from. The servers hold parallel corpora (the share
+// at each index in one server's corpus corresponds to the share at that index in the other's) and
+// must remove the same index from each to keep them in correspondence. Removing a share moves the
+// last (non-padding) share into its place.
+pub struct NoiseCorpus {
+    // The real shares are at the beginning, followed by any padding (see `pad_corpus`).
+    noise_shares: Vec<NoiseShare>,
+    n_real: usize,
+}
+
+impl NoiseCorpus {
+    pub fn new(noise_shares: Vec<NoiseShare>) -> NoiseCorpus {
+        let n_real = noise_shares.len();
+        NoiseCorpus {
+            noise_shares,
+            n_real,
+        }
+    }
+
+    // The number of shares in the corpus, including any padding.
+    pub fn len(&self) -> usize {
+        self.noise_shares.len()
+    }
+
+    // The number of real shares in the corpus, i.e. those that can be selected.
+    pub fn real_len(&self) -> usize {
+        self.n_real
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.noise_shares.is_empty()
+    }
+
+    // The corpus size to commit to when selecting the next noise share. Every closed commitment
+    // carries this size in the clear (see `ClosedCommitment::corpus_size`), so it includes the
+    // padding, and the commitments don't reveal how many of the shares are real (see
+    // `pad_corpus`).
+    pub fn commit_size(&self) -> u64 {
+        self.noise_shares.len() as u64
+    }
+
+    // Removes the real share at index. An index of the padding fails with DummySelected and
+    // removes nothing, as dummies must never be aggregated: the selection must be redrawn with new
+    // commitments (see `select_noise_shares`).
+    pub fn remove_selected(&mut self, index: u64) -> Result<NoiseShare, CommitmentError> {
+        if index >= self.commit_size() {
+            return Err(CommitmentError::IndexOutOfRange);
+        }
+        if index >= self.n_real as u64 {
+            return Err(CommitmentError::DummySelected);
+        }
+        let last_real = self.n_real - 1;
+        self.noise_shares.swap(index as usize, last_real);
+        self.n_real -= 1;
+        // This moves the last share, which is padding if there is any, into the place of the last
+        // real share, so the padding remains after the real shares.
+        Ok(self.noise_shares.swap_remove(last_real))
+    }
+}
+
+// Binds a corpus to the corpus size the commitments of a selection are made for. A selected index
+// is only meaningful for the corpus the commitments were made for, so if the corpus changed in any
+// other way since (e.g. a share was removed without going through this context, the index was
+// applied to another server's corpus, or an index was applied twice), removing the selected share
+// would remove the wrong one. The context takes ownership of the corpus, so the only way to remove
+// a share is through the context, and each selection must start with `begin_selection`, which
+// records the size to commit to. `remove_selected` then only accepts an index if the corpus still
+// has the size that was recorded, and ends the selection, so each index is applied at most once.
+// The size includes any padding (see `commit_size`).
+pub struct SelectionContext {
+    corpus: NoiseCorpus,
+    // The corpus size the commitments of the pending selection are made for, if one was begun.
+    pending_n: Option<u64>,
+}
+
+impl SelectionContext {
+    pub fn new(corpus: NoiseCorpus) -> SelectionContext {
+        SelectionContext {
+            corpus,
+            pending_n: None,
+        }
+    }
+
+    // The corpus size to commit to when selecting the next noise share.
+    pub fn n(&self) -> u64 {
+        self.corpus.commit_size()
+    }
+
+    // Begins selecting the next noise share, returning the corpus size to commit to. Beginning a
+    // selection again abandons the pending one.
+    pub fn begin_selection(&mut self) -> u64 {
+        let n = self.n();
+        self.pending_n = Some(n);
+        n
+    }
+
+    pub fn corpus(&self) -> &NoiseCorpus {
+        &self.corpus
+    }
+
+    pub fn into_corpus(self) -> NoiseCorpus {
+        self.corpus
+    }
+
+    // Removes the share at the index selected for the pending selection, and ends the selection.
+    // If no selection is pending (e.g. because the index was already applied), or the corpus no
+    // longer has the size the commitments were made for, this fails with CorpusSizeMismatch. An
+    // index outside of the corpus fails with IndexOutOfRange, and leaves the selection pending. An
+    // index of the padding fails with DummySelected, and ends the selection, which must be
+    // redrawn.
+    pub fn remove_selected(&mut self, index: u64) -> Result<NoiseShare, CommitmentError> {
+        if self.pending_n != Some(self.corpus.commit_size()) {
+            return Err(CommitmentError::CorpusSizeMismatch);
+        }
+        match self.corpus.remove_selected(index) {
+            Err(CommitmentError::IndexOutOfRange) => Err(CommitmentError::IndexOutOfRange),
+            result => {
+                self.pending_n = None;
+                result
+            }
+        }
+    }
+}
+
+// Runs a full round of noise selection among servers with corpora of the given sizes (in a single
+// process, e.g. for simulations and tests): each server commits to a value, all of the
+// commitments are closed before any value is published, and then the published values are
+// validated against the commitments and the index is gathered. Returns the selected index. In a
+// single process every server would see the same published values, so they're validated and
+// gathered once; servers that receive the values separately should each validate their own copy
+// and compare the results with `agree_on_index`. The servers' corpora must be of the same size,
+// or this fails with CorpusSizeMismatch, and there must be at least one server, or this fails
+// with InsufficientParties.
+pub fn select_noise_index(server_corpus_sizes: &[u64]) -> Result<u64, CommitmentError> {
+    select_noise_index_with_rng(server_corpus_sizes, &mut rand::thread_rng())
+}
+
+// Like `select_noise_index`, with the commitments made with `Commitment::new_with_rng`.
+pub fn select_noise_index_with_rng<R: Rng>(
+    server_corpus_sizes: &[u64],
+    rng: &mut R,
+) -> Result<u64, CommitmentError> {
+    if server_corpus_sizes.is_empty() {
+        return Err(CommitmentError::InsufficientParties);
+    }
+    let commitments = server_corpus_sizes
+        .iter()
+        .map(|n| Commitment::new_with_rng(*n, rng))
+        .collect::<Result<Vec<Commitment>, CommitmentError>>()?;
+    let closed_commitments: Vec<ClosedCommitment> =
+        commitments.iter().map(|c| c.commit()).collect();
+    let published_values: Vec<(u64, Nonce)> = commitments.iter().map(|c| c.publish()).collect();
+    let opened_commitments = closed_commitments
+        .iter()
+        .zip(published_values.iter())
+        .map(|(closed_commitment, (p, nonce))| closed_commitment.validate(*p, nonce))
+        .collect::<Result<Vec<OpenedCommitment>, CommitmentError>>()?;
+    OpenedCommitment::gather(&opened_commitments)
+}
+
+// Selects the next noise share from each server's corpus with `select_noise_index`, and removes
+// it, returning the selected shares in the order of the servers. contexts holds each server's
+// selection context, in the order of the servers. Without any contexts, there are no servers to
+// select an index, so this fails with InsufficientParties (from `select_noise_index`).
+// The commitments are made for the padded size of the corpora (see `pad_corpus`), so if the index
+// is that of a dummy, the selection is redrawn with new commitments until it's that of a real
+// share. Every index is equally likely in each round, so every real share is equally likely to be
+// selected in the end. The corpora must have the same number of real shares, or this fails with
+// CorpusSizeMismatch, and if only padding is left, there's nothing to select, so this fails with
+// EmptyCorpus.
+pub fn select_noise_shares(
+    contexts: &mut [SelectionContext],
+) -> Result<Vec<NoiseShare>, CommitmentError> {
+    select_noise_shares_with_rng(contexts, &mut rand::thread_rng())
+}
+
+// Like `select_noise_shares`, with the index selected by `select_noise_index_with_rng`.
+pub fn select_noise_shares_with_rng<R: Rng>(
+    contexts: &mut [SelectionContext],
+    rng: &mut R,
+) -> Result<Vec<NoiseShare>, CommitmentError> {
+    if let Some(context) = contexts.first() {
+        let real_len = context.corpus().real_len();
+        if contexts
+            .iter()
+            .any(|context| context.corpus().real_len() != real_len)
+        {
+            return Err(CommitmentError::CorpusSizeMismatch);
+        }
+        if real_len == 0 && !context.corpus().is_empty() {
+            return Err(CommitmentError::EmptyCorpus);
+        }
+    }
+    loop {
+        let corpus_sizes: Vec<u64> = contexts
+            .iter_mut()
+            .map(|context| context.begin_selection())
+            .collect();
+        let index = select_noise_index_with_rng(&corpus_sizes, rng)?;
+        // Every corpus has the same number of real shares, so the index is that of a dummy in all
+        // of them or in none.
+        match contexts
+            .iter_mut()
+            .map(|context| context.remove_selected(index))
+            .collect()
+        {
+            Err(CommitmentError::DummySelected) => continue,
+            result => return result,
+        }
+    }
+}
+
+// The two servers' parallel corpora of candidate noise shares, from which noise is drawn with the
+// commitment protocol. The pool owns both corpora (through their selection contexts), so the same
+// share is always removed from each, and the corpora can't fall out of correspondence.
+pub struct NoisePool {
+    contexts: [SelectionContext; 2],
+}
+
+impl NoisePool {
+    // The shares at each index of noise_for_server1 and noise_for_server2 must come from the same
+    // participant, so they must be of the same length.
+    pub fn new(
+        noise_for_server1: Vec<NoiseShare>,
+        noise_for_server2: Vec<NoiseShare>,
+    ) -> Result<NoisePool, CommitmentError> {
+        if noise_for_server1.len() != noise_for_server2.len() {
+            return Err(CommitmentError::CorpusSizeMismatch);
+        }
+        Ok(NoisePool {
+            contexts: [
+                SelectionContext::new(NoiseCorpus::new(noise_for_server1)),
+                SelectionContext::new(NoiseCorpus::new(noise_for_server2)),
+            ],
+        })
+    }
+
+    // The number of candidates left to draw.
+    pub fn len(&self) -> usize {
+        self.contexts[0].corpus().real_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Selects a candidate with `select_noise_index` and removes it from the pool, returning its
+    // shares for server 1 and server 2, or None if the pool is empty.
+    pub fn draw(&mut self) -> Option<(NoiseShare, NoiseShare)> {
+        self.draw_with_rng(&mut rand::thread_rng())
+    }
+
+    // Like `draw`, with the candidate selected by `select_noise_shares_with_rng`.
+    pub fn draw_with_rng<R: Rng>(&mut self, rng: &mut R) -> Option<(NoiseShare, NoiseShare)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut noise_shares = select_noise_shares_with_rng(&mut self.contexts, rng)
+            .expect("the corpora should be parallel");
+        let share_for_server2 = noise_shares.pop().unwrap();
+        let share_for_server1 = noise_shares.pop().unwrap();
+        Some((share_for_server1, share_for_server2))
+    }
+}
+
+// Pads the corpus with dummy shares until it has `target_len` shares (if it has fewer).
+// If the number of noise shares varied from round to round, an observer who can see the size of
+// the corpus (e.g. the amount of data a server stores or transfers, or the corpus size every
+// closed commitment carries) could learn how many clients contributed noise. The commitments of a
+// selection are made for the padded size (see `commit_size`), so padding every corpus to the same
+// size hides this. Dummies are never selected: if the gathered index is that of a dummy, the
+// selection is redrawn (see `select_noise_shares`), so every real share remains equally likely to
+// be selected. This takes target_len / (number of real shares) rounds of commitments per selected
+// share on average, and an observer who counts the rounds over many selections can still estimate
+// the fraction of real shares, so deployments for which that is sensitive should keep the number
+// of rounds confidential between the servers. `make_dummy_share` should return shares that are
+// indistinguishable from real ones and that encode zero noise, so that even if a dummy were
+// aggregated by mistake, it wouldn't corrupt the result.
+pub fn pad_corpus<R, F>(
+    corpus: &mut NoiseCorpus,
+    target_len: usize,
+    mut make_dummy_share: F,
+    rng: &mut R,
+) where
+    R: Rng,
+    F: FnMut(&mut R) -> NoiseShare,
+{
+    while corpus.noise_shares.len() < target_len {
+        corpus.noise_shares.push(make_dummy_share(rng));
+    }
+}
+
+// Randomly retains `target_len` of the corpus's real shares (if it has more) and discards the rest,
+// so that the servers don't have to hold and select from far more noise than they need. Every
+// subset of `target_len` shares is equally likely to be retained, so a share selected uniformly
+// from the retained shares is uniform over all of the original shares as well. Padding is kept.
+// The servers' corpora must remain parallel, so both servers must downsample with identically
+// seeded RNGs (e.g. seeded with a value they agreed on, like a selected index).
+pub fn downsample_corpus<R: Rng>(corpus: &mut NoiseCorpus, target_len: usize, rng: &mut R) {
+    if corpus.n_real <= target_len {
+        return;
+    }
+    // A partial Fisher-Yates shuffle moves a uniformly random subset of the real shares to the
+    // front.
+    for i in 0..target_len {
+        let j = rng.gen_range(i, corpus.n_real);
+        corpus.noise_shares.swap(i, j);
+    }
+    corpus.noise_shares.drain(target_len..corpus.n_real);
+    corpus.n_real = target_len;
+}
+
+// Returns the probability that each value in a corpus of noise values is the one selected (and so
+// injected into the aggregate), in increasing order of value. Every index is selected with
+// probability 1 / n, so a value's probability is the fraction of the corpus it makes up. If the
+// corpus values were sampled from a distribution (e.g. Laplace), this is the empirical
+// distribution of the injected noise, which should converge to that distribution as the corpus
+// grows.
+pub fn selected_value_distribution(corpus_values: &[i64]) -> Vec<(i64, f64)> {
+    let mut counts = BTreeMap::new();
+    for value in corpus_values {
+        *counts.entry(*value).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(value, count)| (value, count as f64 / corpus_values.len() as f64))
+        .collect()
 }
 
 #[derive(Debug)]
@@ -99,27 +1087,155 @@ pub enum CommitmentError {
     HashMismatch,
     CorpusSizeMismatch,
     EmptyCorpus,
+    InvalidSignature,
+    TooManyParticipants,
+    OpeningCountMismatch,
+    IndexDisagreement,
+    IndexOutOfRange,
+    DummySelected,
+    ReplayedCommitment,
+    PublishedValueOutOfRange,
+    InsufficientParties,
+    InvalidCorpusSize,
+    MalformedBytes,
+    RoundTooLarge,
 }
 
-#[derive(Debug)]
-pub struct ParameterError;
+impl fmt::Display for CommitmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            CommitmentError::HashMismatch => {
+                "the published value and nonce don't match the closed commitment (wrong value, \
+                 nonce, challenge, or personalization)"
+            }
+            CommitmentError::CorpusSizeMismatch => {
+                "the commitments were made for different corpus sizes"
+            }
+            CommitmentError::EmptyCorpus => {
+                "there is nothing to select from (empty corpus or no opened commitments)"
+            }
+            CommitmentError::InvalidSignature => {
+                "the signature of the closed commitment is invalid"
+            }
+            CommitmentError::TooManyParticipants => {
+                "the round already has its maximum number of participants"
+            }
+            CommitmentError::OpeningCountMismatch => {
+                "the number of published values doesn't match the number of closed commitments"
+            }
+            CommitmentError::IndexDisagreement => "the servers selected different indices",
+            CommitmentError::IndexOutOfRange => "the selected index is outside of the corpus",
+            CommitmentError::DummySelected => {
+                "the selected index is that of a padding share, so the selection must be redrawn"
+            }
+            CommitmentError::ReplayedCommitment => {
+                "a closed commitment was already used in this round or a previous one"
+            }
+            CommitmentError::PublishedValueOutOfRange => {
+                "the published value is outside of the range a participant could have sampled"
+            }
+            CommitmentError::InsufficientParties => {
+                "too few participants for the minimum number of honest participants"
+            }
+            CommitmentError::InvalidCorpusSize => "the corpus size must be positive",
+            CommitmentError::MalformedBytes => "the encoded commitments are truncated or malformed",
+            CommitmentError::RoundTooLarge => "the round has too many commitments to encode",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for CommitmentError {}
+
+// Why the parameters of a mechanism (or of a computation about one) were rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParameterError {
+    // Epsilon is zero, negative, or NaN.
+    NonPositiveEpsilon,
+    // Epsilon is so small relative to the sensitivity that the noise can't be represented.
+    EpsilonTooSmall,
+    // Epsilon is infinite.
+    EpsilonTooLarge,
+    // Delta isn't in (0, 1).
+    DeltaOutOfRange,
+    // The sensitivity is negative, infinite, or NaN (or zero, where that's meaningless).
+    SensitivityOutOfRange,
+    // Lambda is too small (or not positive) for the geometric sampler.
+    LambdaTooSmall,
+    // The granularity isn't positive.
+    GranularityOutOfRange,
+    // The confidence isn't in (0, 1).
+    ConfidenceOutOfRange,
+    // The target error isn't a positive finite number.
+    TargetErrorOutOfRange,
+    // No queries were given.
+    NoQueries,
+    // The number of noises is 0.
+    NoNoise,
+    // A mean over no contributions was requested.
+    ZeroCount,
+    // The dimension is 0 or larger than `laplace::MAX_DIMENSION`.
+    DimensionOutOfRange,
+    // The dimension is too small to encode the noise.
+    DimensionTooSmall,
+    // A value is outside the range a function accepts.
+    ValueOutOfRange,
+    // The numerator or the denominator of a rational parameter is 0.
+    InvalidFraction,
+    // An intermediate value of the computation overflowed.
+    Overflow,
+}
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ParameterError::NonPositiveEpsilon => "epsilon must be positive",
+            ParameterError::EpsilonTooSmall => "epsilon is too small for the sensitivity",
+            ParameterError::EpsilonTooLarge => "epsilon must be finite",
+            ParameterError::DeltaOutOfRange => "delta must be in (0, 1)",
+            ParameterError::SensitivityOutOfRange => "the sensitivity must be positive and finite",
+            ParameterError::LambdaTooSmall => "lambda is too small for the geometric sampler",
+            ParameterError::GranularityOutOfRange => {
+                "the granularity must be positive (and at most 1 for noise)"
+            }
+            ParameterError::ConfidenceOutOfRange => "the confidence must be in (0, 1)",
+            ParameterError::TargetErrorOutOfRange => "the target error must be positive and finite",
+            ParameterError::NoQueries => "at least one query is required",
+            ParameterError::NoNoise => "at least one noise is required",
+            ParameterError::ZeroCount => "the mean of no contributions is undefined",
+            ParameterError::DimensionOutOfRange => "the dimension isn't supported",
+            ParameterError::DimensionTooSmall => "the dimension is too small to encode the noise",
+            ParameterError::ValueOutOfRange => "the value is out of range",
+            ParameterError::InvalidFraction => "the numerator and denominator must be positive",
+            ParameterError::Overflow => "the parameters are too large to compute with",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for ParameterError {}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::*;
 
     #[test]
     fn test_basic_commitment() {
         let n = 162_564_322;
-        let commitments = vec![Commitment::new(n), Commitment::new(n), Commitment::new(n)];
+        let commitments = vec![
+            Commitment::new(n).unwrap(),
+            Commitment::new(n).unwrap(),
+            Commitment::new(n).unwrap(),
+        ];
         let closed_commitments: Vec<ClosedCommitment> =
             commitments.iter().map(|c| c.commit()).collect();
-        let published_values: Vec<u64> = commitments.iter().map(|c| c.publish()).collect();
+        let published_values: Vec<(u64, Nonce)> = commitments.iter().map(|c| c.publish()).collect();
         let opened_commitments: Vec<OpenedCommitment> = closed_commitments
             .iter()
             .zip(published_values.iter())
-            .map(|(closed_commitment, p)| {
-                let result = closed_commitment.validate(*p);
+            .map(|(closed_commitment, (p, nonce))| {
+                let result = closed_commitment.validate(*p, nonce);
                 assert!(result.is_ok());
                 result.unwrap()
             })
@@ -129,4 +1245,1393 @@ mod tests {
         let index = result.unwrap();
         assert!(index < n);
     }
+
+    // Simulates a round in which the second server doesn't follow the protocol: after committing, it
+    // waits to see the first server's published value and then publishes a value chosen to steer
+    // the gathered index to `target_index`. Returns the result of validating that value against
+    // the adversarial server's commitment.
+    fn run_round_with_adversary(
+        n: u64,
+        target_index: u64,
+    ) -> Result<OpenedCommitment, CommitmentError> {
+        let honest_commitment = Commitment::new(n).unwrap();
+        let adversarial_commitment = Commitment::new(n).unwrap();
+        let honest_closed_commitment = honest_commitment.commit();
+        let adversarial_closed_commitment = adversarial_commitment.commit();
+        let (honest_published_value, honest_nonce) = honest_commitment.publish();
+        assert!(honest_closed_commitment
+            .validate(honest_published_value, &honest_nonce)
+            .is_ok());
+        let steering_value = (target_index + n - honest_published_value % n) % n;
+        // Without the commitment, this would be a winning move for the adversary.
+        assert_eq!(
+            OpenedCommitment::gather(&[
+                OpenedCommitment::new(n, honest_published_value),
+                OpenedCommitment::new(n, steering_value),
+            ])
+            .unwrap(),
+            target_index
+        );
+        let (_, adversarial_nonce) = adversarial_commitment.publish();
+        adversarial_closed_commitment.validate(steering_value, &adversarial_nonce)
+    }
+
+    #[test]
+    fn test_adversarial_reveal_fails_validation() {
+        for target_index in [0, 1, 500, 999] {
+            assert!(matches!(
+                run_round_with_adversary(1000, target_index),
+                Err(CommitmentError::HashMismatch)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_agree_on_index() {
+        let n = 1000;
+        let commitments = [Commitment::new(n).unwrap(), Commitment::new(n).unwrap()];
+        let closed_commitments: Vec<ClosedCommitment> =
+            commitments.iter().map(|c| c.commit()).collect();
+        let open = |published_values: &[(u64, Nonce)]| -> Vec<OpenedCommitment> {
+            closed_commitments
+                .iter()
+                .zip(published_values.iter())
+                .map(|(closed_commitment, (p, nonce))| {
+                    closed_commitment.validate(*p, nonce).unwrap()
+                })
+                .collect()
+        };
+        let published_values: Vec<(u64, Nonce)> = commitments.iter().map(|c| c.publish()).collect();
+        let index = agree_on_index(&open(&published_values), &open(&published_values)).unwrap();
+        assert!(index < n);
+
+        // Simulate the second server receiving a different (but validly committed) value from the
+        // first server than the first server used itself.
+        let desynced_commitment = Commitment::new_with_p(n, published_values[0].0 + 1);
+        let (desynced_p, desynced_nonce) = desynced_commitment.publish();
+        let desynced_opened_commitments = vec![
+            desynced_commitment
+                .commit()
+                .validate(desynced_p, &desynced_nonce)
+                .unwrap(),
+            closed_commitments[1]
+                .validate(published_values[1].0, &published_values[1].1)
+                .unwrap(),
+        ];
+        assert!(matches!(
+            agree_on_index(&open(&published_values), &desynced_opened_commitments),
+            Err(CommitmentError::IndexDisagreement)
+        ));
+    }
+
+    #[test]
+    fn test_noise_corpus() {
+        let mut corpus = NoiseCorpus::new(vec![vec![0], vec![1], vec![2], vec![3]]);
+        assert_eq!(corpus.len(), 4);
+        assert_eq!(corpus.commit_size(), 4);
+        assert_eq!(corpus.remove_selected(1).unwrap(), vec![1]);
+        assert_eq!(corpus.len(), 3);
+        assert_eq!(corpus.commit_size(), 3);
+        // The last share took the place of the removed one.
+        assert_eq!(corpus.remove_selected(1).unwrap(), vec![3]);
+        assert!(matches!(
+            corpus.remove_selected(2),
+            Err(CommitmentError::IndexOutOfRange)
+        ));
+        assert_eq!(corpus.len(), 2);
+        assert_eq!(corpus.remove_selected(0).unwrap(), vec![0]);
+        assert_eq!(corpus.remove_selected(0).unwrap(), vec![2]);
+        assert!(corpus.is_empty());
+        assert_eq!(corpus.commit_size(), 0);
+        assert!(matches!(
+            corpus.remove_selected(0),
+            Err(CommitmentError::IndexOutOfRange)
+        ));
+    }
+
+    #[test]
+    fn test_pad_corpus() {
+        let mut rng = seeded_rng(10);
+        let dummy_share = |rng: &mut rand::rngs::StdRng| vec![255, rng.gen::<u8>()];
+        let mut corpus = NoiseCorpus::new(vec![vec![0], vec![1], vec![2]]);
+        pad_corpus(&mut corpus, 8, dummy_share, &mut rng);
+        assert_eq!(corpus.len(), 8);
+        assert_eq!(corpus.real_len(), 3);
+        assert_eq!(corpus.commit_size(), 8);
+        // Padding to a smaller size does nothing.
+        pad_corpus(&mut corpus, 4, dummy_share, &mut rng);
+        assert_eq!(corpus.len(), 8);
+        assert_eq!(corpus.remove_selected(0).unwrap(), vec![0]);
+        // A dummy is never removed.
+        assert!(matches!(
+            corpus.remove_selected(2),
+            Err(CommitmentError::DummySelected)
+        ));
+        assert!(matches!(
+            corpus.remove_selected(7),
+            Err(CommitmentError::IndexOutOfRange)
+        ));
+        assert_eq!(corpus.remove_selected(0).unwrap(), vec![2]);
+        assert_eq!(corpus.remove_selected(0).unwrap(), vec![1]);
+        assert_eq!(corpus.real_len(), 0);
+        assert_eq!(corpus.commit_size(), 5);
+        assert_eq!(corpus.len(), 5);
+
+        // The distribution of the selected share should be uniform over the real shares, as it is
+        // without padding.
+        let n_real = 4;
+        let n_trials = SAMPLE_COUNT;
+        let mut counts = vec![0; n_real];
+        for _ in 0..n_trials {
+            let mut corpus = NoiseCorpus::new((0..n_real as u8).map(|i| vec![i]).collect());
+            pad_corpus(&mut corpus, 16, dummy_share, &mut rng);
+            let mut contexts = [SelectionContext::new(corpus)];
+            let selected = select_noise_shares_with_rng(&mut contexts, &mut rng)
+                .unwrap()
+                .remove(0);
+            assert_eq!(selected.len(), 1);
+            counts[selected[0] as usize] += 1;
+        }
+        let expected = 1.0_f64 / n_real as f64;
+        for count in counts {
+            assert!(
+                (count as f64 / n_trials as f64 - expected).abs()
+                    < frequency_tolerance(expected, n_trials)
+            );
+        }
+    }
+
+    #[test]
+    fn test_downsample_corpus() {
+        use rand::SeedableRng;
+
+        let mut rng = seeded_rng(11);
+        let dummy_share = |_: &mut rand::rngs::StdRng| vec![255];
+        let mut corpus = NoiseCorpus::new((0..10).map(|i| vec![i]).collect());
+        pad_corpus(&mut corpus, 16, dummy_share, &mut rng);
+        downsample_corpus(&mut corpus, 4, &mut rng);
+        assert_eq!(corpus.real_len(), 4);
+        assert_eq!(corpus.len(), 10);
+        assert!(corpus.noise_shares[..4].iter().all(|share| share[0] < 10));
+        assert!(corpus.noise_shares[4..].iter().all(|share| share[0] == 255));
+        // Downsampling to a larger size does nothing.
+        downsample_corpus(&mut corpus, 8, &mut rng);
+        assert_eq!(corpus.real_len(), 4);
+
+        // Parallel corpora downsampled with identically seeded RNGs remain parallel.
+        let mut corpus1 = NoiseCorpus::new((0..10).map(|i| vec![i]).collect());
+        let mut corpus2 = NoiseCorpus::new((0..10).map(|i| vec![i, i]).collect());
+        downsample_corpus(&mut corpus1, 5, &mut rand::rngs::StdRng::seed_from_u64(17));
+        downsample_corpus(&mut corpus2, 5, &mut rand::rngs::StdRng::seed_from_u64(17));
+        for (share1, share2) in corpus1.noise_shares.iter().zip(corpus2.noise_shares.iter()) {
+            assert_eq!(share1[0], share2[0]);
+        }
+
+        // Selecting uniformly from the retained shares selects uniformly from all of them.
+        let (n_real, target_len) = (10, 4);
+        let n_trials = SAMPLE_COUNT;
+        let mut counts = vec![0; n_real];
+        for _ in 0..n_trials {
+            let mut corpus = NoiseCorpus::new((0..n_real as u8).map(|i| vec![i]).collect());
+            downsample_corpus(&mut corpus, target_len, &mut rng);
+            let commitments = [
+                Commitment::new_with_rng(corpus.commit_size(), &mut rng).unwrap(),
+                Commitment::new_with_rng(corpus.commit_size(), &mut rng).unwrap(),
+            ];
+            let opened_commitments = commitments
+                .iter()
+                .map(|c| {
+                    let (p, nonce) = c.publish();
+                    c.commit().validate(p, &nonce).unwrap()
+                })
+                .collect::<Vec<OpenedCommitment>>();
+            let index = OpenedCommitment::gather(&opened_commitments).unwrap();
+            counts[corpus.remove_selected(index).unwrap()[0] as usize] += 1;
+        }
+        let expected = 1.0_f64 / n_real as f64;
+        for count in counts {
+            assert!(
+                (count as f64 / n_trials as f64 - expected).abs()
+                    < frequency_tolerance(expected, n_trials)
+            );
+        }
+    }
+
+    #[test]
+    fn test_selected_value_distribution() {
+        assert!(selected_value_distribution(&[]).is_empty());
+        assert_eq!(selected_value_distribution(&[7]), vec![(7, 1.0_f64)]);
+        assert_eq!(
+            selected_value_distribution(&[3, -1, 3, 0, 3, -1, 3, 3]),
+            vec![(-1, 0.25_f64), (0, 0.125_f64), (3, 0.625_f64)]
+        );
+        let distribution = selected_value_distribution(&[1, 2, 3, 1, 2, 3]);
+        assert!((distribution.iter().map(|(_, p)| p).sum::<f64>() - 1.0_f64).abs() < 1e-12_f64);
+        assert!(distribution
+            .iter()
+            .all(|(_, p)| (p - 1.0_f64 / 3.0_f64).abs() < 1e-12_f64));
+    }
+
+    #[test]
+    fn test_select_noise_index() {
+        let n = 4;
+        let n_samples = SAMPLE_COUNT;
+        let mut counts = vec![0; n as usize];
+        let mut rng = seeded_rng(22);
+        for _ in 0..n_samples {
+            counts[select_noise_index_with_rng(&[n, n], &mut rng).unwrap() as usize] += 1;
+        }
+        let expected = 1.0_f64 / n as f64;
+        for count in counts {
+            assert!(
+                (count as f64 / n_samples as f64 - expected).abs()
+                    < frequency_tolerance(expected, n_samples)
+            );
+        }
+        assert!(select_noise_index(&[n, n, n]).unwrap() < n);
+        assert!(matches!(
+            select_noise_index(&[n, n + 1]),
+            Err(CommitmentError::CorpusSizeMismatch)
+        ));
+        assert!(matches!(
+            select_noise_index(&[n, 0]),
+            Err(CommitmentError::InvalidCorpusSize)
+        ));
+        assert!(matches!(
+            select_noise_index(&[]),
+            Err(CommitmentError::InsufficientParties)
+        ));
+    }
+
+    #[test]
+    fn test_select_noise_shares() {
+        let mut contexts = [
+            SelectionContext::new(NoiseCorpus::new((0..10).map(|i| vec![i]).collect())),
+            SelectionContext::new(NoiseCorpus::new((0..10).map(|i| vec![i]).collect())),
+        ];
+        let mut selected = HashSet::new();
+        for _ in 0..10 {
+            let shares = select_noise_shares(&mut contexts).unwrap();
+            // The corpora stay parallel, so the servers select corresponding shares.
+            assert_eq!(shares.len(), 2);
+            assert_eq!(shares[0], shares[1]);
+            assert!(selected.insert(shares[0].clone()));
+        }
+        assert!(contexts.iter().all(|context| context.corpus().is_empty()));
+        assert!(matches!(
+            select_noise_shares(&mut contexts),
+            Err(CommitmentError::InvalidCorpusSize)
+        ));
+        // Corpora of different sizes can't correspond.
+        let mut contexts = [
+            SelectionContext::new(NoiseCorpus::new(vec![vec![0]; 10])),
+            SelectionContext::new(NoiseCorpus::new(vec![vec![0]; 9])),
+        ];
+        assert!(matches!(
+            select_noise_shares(&mut contexts),
+            Err(CommitmentError::CorpusSizeMismatch)
+        ));
+        // Without any servers, there's nobody to select an index.
+        assert!(matches!(
+            select_noise_shares(&mut []),
+            Err(CommitmentError::InsufficientParties)
+        ));
+    }
+
+    #[test]
+    fn test_noise_pool() {
+        let n = 10;
+        let mut pool = NoisePool::new(
+            (0..n).map(|i| vec![i, 1]).collect(),
+            (0..n).map(|i| vec![i, 2]).collect(),
+        )
+        .unwrap();
+        assert_eq!(pool.len(), n as usize);
+        let mut drawn = HashSet::new();
+        while let Some((share_for_server1, share_for_server2)) = pool.draw() {
+            // The shares come from the same participant.
+            assert_eq!(share_for_server1[0], share_for_server2[0]);
+            assert_eq!((share_for_server1[1], share_for_server2[1]), (1, 2));
+            assert!(drawn.insert(share_for_server1[0]));
+        }
+        assert_eq!(drawn.len(), n as usize);
+        assert!(pool.is_empty());
+        assert!(pool.draw().is_none());
+
+        assert!(matches!(
+            NoisePool::new(vec![vec![0]], vec![]),
+            Err(CommitmentError::CorpusSizeMismatch)
+        ));
+        assert!(NoisePool::new(vec![], vec![]).unwrap().draw().is_none());
+    }
+
+    #[test]
+    fn test_new_with_rng() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let commitment1 = Commitment::new_with_rng(1000, &mut StdRng::seed_from_u64(17)).unwrap();
+        let commitment2 = Commitment::new_with_rng(1000, &mut StdRng::seed_from_u64(17)).unwrap();
+        assert_eq!(commitment1.publish(), commitment2.publish());
+        assert_eq!(commitment1.commit().hash, commitment2.commit().hash);
+        let (p, nonce) = commitment1.publish();
+        assert!(commitment2.commit().validate(p, &nonce).is_ok());
+        assert!(matches!(
+            Commitment::new_with_rng(0, &mut StdRng::seed_from_u64(17)),
+            Err(CommitmentError::InvalidCorpusSize)
+        ));
+
+        // The whole selection is reproduced too.
+        let draws = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut pool = NoisePool::new(
+                (0..100).map(|i| vec![i]).collect(),
+                (0..100).map(|i| vec![i]).collect(),
+            )
+            .unwrap();
+            (0..10)
+                .map(|_| pool.draw_with_rng(&mut rng).unwrap().0)
+                .collect::<Vec<NoiseShare>>()
+        };
+        assert_eq!(draws(17), draws(17));
+        assert_ne!(draws(17), draws(18));
+    }
+
+    #[test]
+    fn test_selection_context() {
+        let mut corpus = NoiseCorpus::new((0..5).map(|i| vec![i]).collect());
+        // The padding is committed to as well.
+        pad_corpus(&mut corpus, 8, |_| vec![0], &mut rand::thread_rng());
+        let mut context = SelectionContext::new(corpus);
+        assert_eq!(context.n(), 8);
+        // Without a pending selection, no index is meaningful.
+        assert!(matches!(
+            context.remove_selected(1),
+            Err(CommitmentError::CorpusSizeMismatch)
+        ));
+        assert_eq!(context.begin_selection(), 8);
+        assert_eq!(context.remove_selected(1).unwrap(), vec![1]);
+        assert_eq!(context.n(), 7);
+        // An index gathered for 8 shares can't be applied again to the 7 that are left, even
+        // though it's still in range: it would remove another share.
+        assert!(matches!(
+            context.remove_selected(1),
+            Err(CommitmentError::CorpusSizeMismatch)
+        ));
+        assert_eq!(context.n(), 7);
+        assert_eq!(context.begin_selection(), 7);
+        assert!(matches!(
+            context.remove_selected(7),
+            Err(CommitmentError::IndexOutOfRange)
+        ));
+        assert_eq!(context.n(), 7);
+        assert_eq!(context.remove_selected(3).unwrap(), vec![3]);
+        assert_eq!(context.n(), 6);
+        // An index of the padding ends the selection without removing anything.
+        assert_eq!(context.begin_selection(), 6);
+        assert!(matches!(
+            context.remove_selected(4),
+            Err(CommitmentError::DummySelected)
+        ));
+        assert!(matches!(
+            context.remove_selected(0),
+            Err(CommitmentError::CorpusSizeMismatch)
+        ));
+        assert_eq!(context.n(), 6);
+        // The context owns the corpus, so it can't change behind the context's back. Once the
+        // selection is done, the rest of the corpus (including the padding) can be taken back.
+        let corpus = context.into_corpus();
+        assert_eq!(corpus.real_len(), 3);
+        assert_eq!(corpus.len(), 6);
+    }
+
+    #[test]
+    fn test_padded_commitments() {
+        // Corpora with different numbers of real shares, padded to the same size, are committed
+        // to with the same corpus size, so the commitments don't tell them apart.
+        let mut rng = seeded_rng(23);
+        for n_real in [1, 5, 12] {
+            let mut corpus = NoiseCorpus::new((0..n_real).map(|i| vec![i]).collect());
+            pad_corpus(&mut corpus, 16, |_| vec![255], &mut rng);
+            let mut context = SelectionContext::new(corpus);
+            let closed_commitment = Commitment::new_with_rng(context.begin_selection(), &mut rng)
+                .unwrap()
+                .commit();
+            assert_eq!(closed_commitment.corpus_size(), 16);
+        }
+
+        // Drawing from padded corpora only ever selects real shares, and once only the padding is
+        // left, there's nothing to draw.
+        let mut contexts = [0, 1].map(|server| {
+            let mut corpus = NoiseCorpus::new((0..5).map(|i| vec![i, server]).collect());
+            pad_corpus(&mut corpus, 16, |_| vec![255, server], &mut rng);
+            SelectionContext::new(corpus)
+        });
+        let mut selected = HashSet::new();
+        for _ in 0..5 {
+            let shares = select_noise_shares_with_rng(&mut contexts, &mut rng).unwrap();
+            assert_eq!(shares[0][0], shares[1][0]);
+            assert!(shares[0][0] < 5);
+            assert!(selected.insert(shares[0][0]));
+        }
+        assert!(matches!(
+            select_noise_shares_with_rng(&mut contexts, &mut rng),
+            Err(CommitmentError::EmptyCorpus)
+        ));
+    }
+
+    #[test]
+    fn test_verify_binding() {
+        assert!(verify_binding::<Sha256>());
+        // `commit` should use the hash that was verified.
+        let commitment = Commitment::new_with_p(1000, 17);
+        assert_eq!(
+            commitment.commit().hash,
+            commitment_hash::<Sha256>(
+                &DEFAULT_PERSONALIZATION,
+                &commitment.nonce,
+                &[],
+                1000,
+                commitment.p
+            )
+        );
+    }
+
+    #[test]
+    fn test_personalization() {
+        let study1 = [1; 16];
+        let study2 = [2; 16];
+        let commitment = Commitment::new(1000).unwrap().with_personalization(study1);
+        let closed_commitment = commitment.commit();
+        let (p, nonce) = commitment.publish();
+        assert!(closed_commitment.validate(p, &nonce).is_ok());
+
+        // A server of the same deployment receives the commitment and validates it.
+        let received_commitment = ClosedCommitment::new(1000, closed_commitment.hash.clone());
+        assert!(received_commitment
+            .with_personalization(study1)
+            .validate(p, &nonce)
+            .is_ok());
+        // A server of another deployment (or one without a personalization) rejects it.
+        for personalization in [study2, DEFAULT_PERSONALIZATION] {
+            let received_commitment = ClosedCommitment::new(1000, closed_commitment.hash.clone())
+                .with_personalization(personalization);
+            assert!(matches!(
+                received_commitment.validate(p, &nonce),
+                Err(CommitmentError::HashMismatch)
+            ));
+        }
+        let mut other_commitment = Commitment::new_with_p(1000, p);
+        other_commitment.nonce = nonce;
+        assert!(matches!(
+            other_commitment
+                .commit()
+                .with_personalization(study1)
+                .validate(p, &nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_commit_with_challenge() {
+        let n = 4;
+        let commitment = Commitment::new(n).unwrap();
+        let (p, nonce) = commitment.publish();
+        // A closed commitment made (or precomputed) before the challenge was known doesn't
+        // validate under the challenge.
+        let precomputed = commitment.commit();
+        let challenge: Vec<u8> = (0..32).map(|_| rand::thread_rng().gen::<u8>()).collect();
+        assert!(matches!(
+            precomputed.validate_with_challenge(p, &nonce, &challenge),
+            Err(CommitmentError::HashMismatch)
+        ));
+        let closed_commitment = commitment.commit_with_challenge(&challenge);
+        assert!(closed_commitment
+            .validate_with_challenge(p, &nonce, &challenge)
+            .is_ok());
+        assert!(matches!(
+            closed_commitment.validate(p, &nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+        let mut other_challenge = challenge.clone();
+        other_challenge[0] ^= 1;
+        assert!(matches!(
+            closed_commitment.validate_with_challenge(p, &nonce, &other_challenge),
+            Err(CommitmentError::HashMismatch)
+        ));
+        // Without a challenge, the commitment is the same as before.
+        assert_eq!(commitment.commit_with_challenge(&[]).hash, precomputed.hash);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_closed_commitment_serde() {
+        let n = 1000;
+        let commitment = Commitment::new(n).unwrap();
+        let (p, nonce) = commitment.publish();
+        let closed_commitment = commitment.commit();
+
+        let json = serde_json::to_string(&closed_commitment).unwrap();
+        let from_json: ClosedCommitment = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json.hash, closed_commitment.hash);
+        assert!(from_json.validate(p, &nonce).is_ok());
+
+        let bytes = bincode::serialize(&closed_commitment).unwrap();
+        let from_bincode: ClosedCommitment = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(from_bincode.hash, closed_commitment.hash);
+        assert!(from_bincode.validate(p, &nonce).is_ok());
+        assert!(matches!(
+            from_bincode.validate((p + 1) % n, &nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+
+        // The sender's personalization isn't sent, so a commitment from another deployment doesn't
+        // validate with the receiver's (here, the default) personalization.
+        let personalization = [7; 16];
+        let commitment = Commitment::new(n)
+            .unwrap()
+            .with_personalization(personalization);
+        let (p, nonce) = commitment.publish();
+        let json = serde_json::to_string(&commitment.commit()).unwrap();
+        let from_json: ClosedCommitment = serde_json::from_str(&json).unwrap();
+        assert!(matches!(
+            from_json.validate(p, &nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+        assert!(from_json
+            .with_personalization(personalization)
+            .validate(p, &nonce)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_commit_hides_p() {
+        for p in [0, 1, 12345, u64::MAX - 1].iter() {
+            let commitment = Commitment::new_with_p(u64::MAX, *p);
+            let closed_commitment = commitment.commit();
+            assert_eq!(closed_commitment.hash.len(), 32);
+            let mut encoded_p = Vec::new();
+            encoded_p.write_u64::<NetworkEndian>(*p).unwrap();
+            assert!(!closed_commitment
+                .hash
+                .windows(encoded_p.len())
+                .any(|window| window == encoded_p.as_slice()));
+            assert_eq!(
+                closed_commitment.hash,
+                Sha256::digest(
+                    [
+                        &b"dprio-commitment-v1"[..],
+                        &DEFAULT_PERSONALIZATION[..],
+                        &commitment.nonce[..],
+                        &u64::MAX.to_be_bytes()[..],
+                        &encoded_p[..]
+                    ]
+                    .concat()
+                )
+                .to_vec()
+            );
+        }
+    }
+
+    #[test]
+    fn test_nonce() {
+        // With a corpus this small, hashing every candidate value would recover p if the
+        // commitment only depended on p.
+        let n = 4;
+        let commitment = Commitment::new(n).unwrap();
+        let (p, nonce) = commitment.publish();
+        let closed_commitment = commitment.commit();
+        assert!((0..=max_published_value(n))
+            .take(1 << 12)
+            .all(|candidate| closed_commitment.hash
+                != commitment_hash::<Sha256>(
+                    &DEFAULT_PERSONALIZATION,
+                    &[0; 16],
+                    &[],
+                    n,
+                    candidate
+                )));
+        assert!(closed_commitment.validate(p, &nonce).is_ok());
+        let mut other_nonce = nonce;
+        other_nonce[0] ^= 1;
+        assert!(matches!(
+            closed_commitment.validate(p, &other_nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+        // Committing to the same value twice results in unrelated closed commitments.
+        let other_commitment = Commitment::new_with_p(n, p);
+        assert_ne!(other_commitment.nonce, nonce);
+        assert_ne!(other_commitment.commit().hash, closed_commitment.hash);
+    }
+
+    #[test]
+    fn test_commitment_binds_n() {
+        let n = 1000;
+        let commitment = Commitment::new_with_p(n, 12345);
+        let (p, nonce) = commitment.publish();
+        let closed_commitment = commitment.commit();
+        assert!(closed_commitment.validate(p, &nonce).is_ok());
+        // p is a valid published value for a corpus of either size, but the commitment was made
+        // for a corpus of size n.
+        let other_closed_commitment = ClosedCommitment::new(n + 1, closed_commitment.hash.clone());
+        assert!(matches!(
+            other_closed_commitment.validate(p, &nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+        // The domain tag is part of the preimage.
+        let mut undomained = Vec::new();
+        undomained.extend_from_slice(&DEFAULT_PERSONALIZATION);
+        undomained.extend_from_slice(&nonce);
+        undomained.write_u64::<NetworkEndian>(n).unwrap();
+        undomained.write_u64::<NetworkEndian>(p).unwrap();
+        assert_ne!(Sha256::digest(&undomained).to_vec(), closed_commitment.hash);
+    }
+
+    #[test]
+    fn test_reveal_round() {
+        let n = 1000;
+        let commitments = [Commitment::new(n).unwrap(), Commitment::new(n).unwrap()];
+        let mut round = RevealRound::new(n);
+        for (i, commitment) in commitments.iter().enumerate() {
+            assert_eq!(round.submit(commitment.commit()).unwrap(), i);
+        }
+        assert!(matches!(
+            round.submit(Commitment::new(n + 1).unwrap().commit()),
+            Err(CommitmentError::CorpusSizeMismatch)
+        ));
+        let published_values: Vec<(u64, Nonce)> = commitments.iter().map(|c| c.publish()).collect();
+        let index = round
+            .finalize(&published_values, &mut SeenCommitments::new())
+            .unwrap();
+        assert_eq!(
+            index,
+            ((published_values[0].0 as u128 + published_values[1].0 as u128) % n as u128) as u64
+        );
+
+        let mut round = RevealRound::new(n);
+        round.submit(commitments[0].commit()).unwrap();
+        round.submit(commitments[1].commit()).unwrap();
+        assert!(matches!(
+            round.finalize(&published_values[..1], &mut SeenCommitments::new()),
+            Err(CommitmentError::OpeningCountMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_replayed_commitment() {
+        let n = 1000;
+        let commitments = vec![Commitment::new(n).unwrap(), Commitment::new(n).unwrap()];
+        let published_values: Vec<(u64, Nonce)> = commitments.iter().map(|c| c.publish()).collect();
+        let mut seen_commitments = SeenCommitments::new();
+        let mut round = RevealRound::new(n);
+        for commitment in &commitments {
+            round.submit(commitment.commit()).unwrap();
+        }
+        assert!(round
+            .finalize(&published_values, &mut seen_commitments)
+            .is_ok());
+        assert_eq!(seen_commitments.len(), 2);
+
+        // A second round replaying one of the opened commitments is rejected.
+        let fresh_commitment = Commitment::new(n).unwrap();
+        let mut round = RevealRound::new(n);
+        round
+            .submit(ClosedCommitment::new(n, commitments[1].commit().hash))
+            .unwrap();
+        round.submit(fresh_commitment.commit()).unwrap();
+        assert!(matches!(
+            round.finalize(
+                &[published_values[1], fresh_commitment.publish()],
+                &mut seen_commitments
+            ),
+            Err(CommitmentError::ReplayedCommitment)
+        ));
+
+        // So is a commitment submitted twice within one round.
+        let mut round = RevealRound::new(n);
+        round.submit(fresh_commitment.commit()).unwrap();
+        round.submit(fresh_commitment.commit()).unwrap();
+        let p = fresh_commitment.publish();
+        assert!(matches!(
+            round.finalize(&[p, p], &mut seen_commitments),
+            Err(CommitmentError::ReplayedCommitment)
+        ));
+        assert_eq!(seen_commitments.len(), 2);
+    }
+
+    // A deliberately naive reference for `gather`: the published values are summed into an
+    // arbitrary-precision integer (little-endian base 2^32 digits, with explicit carries), which
+    // is then reduced modulo n digit by digit.
+    fn gather_reference(n: u64, published_values: &[u64]) -> u64 {
+        let mut digits: Vec<u32> = Vec::new();
+        for p in published_values {
+            let mut carry = *p;
+            let mut i = 0;
+            while carry != 0 {
+                if i == digits.len() {
+                    digits.push(0);
+                }
+                let digit_sum = digits[i] as u64 + (carry & 0xffff_ffff);
+                digits[i] = digit_sum as u32;
+                carry = (carry >> 32) + (digit_sum >> 32);
+                i += 1;
+            }
+        }
+        let mut remainder: u128 = 0;
+        for digit in digits.iter().rev() {
+            remainder = ((remainder << 32) + *digit as u128) % n as u128;
+        }
+        remainder as u64
+    }
+
+    #[test]
+    fn test_gather_matches_reference() {
+        let mut rng = seeded_rng(12);
+        for _ in 0..1000 {
+            let n = match rng.gen_range(0, 4) {
+                0 => rng.gen_range(1, 10),
+                1 => rng.gen::<u64>().max(1),
+                2 => u64::MAX - rng.gen_range(0, 10),
+                _ => 162_564_322,
+            };
+            let max = max_published_value(n);
+            let count = rng.gen_range(1, 40);
+            let published_values: Vec<u64> = (0..count)
+                .map(|_| match rng.gen_range(0, 3) {
+                    // Values near the top of the range, i.e. near u64::MAX.
+                    0 => max - rng.gen_range(0, 10).min(max),
+                    1 => rng.gen_range(0, 10).min(max),
+                    _ => rng.gen_range(0, max),
+                })
+                .collect();
+            let opened_commitments: Vec<OpenedCommitment> = published_values
+                .iter()
+                .map(|p| OpenedCommitment::new(n, *p))
+                .collect();
+            assert_eq!(
+                OpenedCommitment::gather(&opened_commitments).unwrap(),
+                gather_reference(n, &published_values)
+            );
+        }
+        // The reference itself is right on sums that can be checked by hand.
+        assert_eq!(gather_reference(7, &[5, 6]), 4);
+        assert_eq!(
+            gather_reference(u64::MAX, &[u64::MAX - 1, u64::MAX - 1]),
+            u64::MAX - 2
+        );
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize() {
+        let n = 1000;
+        let mut commitment = Commitment::new(n).unwrap();
+        let closed_commitment = commitment.commit();
+        // Publishing still works (any number of times) before the commitment is dropped.
+        let (p, nonce) = commitment.publish_checked(&closed_commitment).unwrap();
+        assert_eq!(commitment.publish(), (p, nonce));
+        assert!(closed_commitment.validate(p, &nonce).is_ok());
+        // What drop does.
+        commitment.zeroize_secrets();
+        assert_eq!(commitment.p, 0);
+        assert_eq!(commitment.nonce, [0; 16]);
+        // The published copies are unaffected.
+        assert!(closed_commitment.validate(p, &nonce).is_ok());
+        drop(commitment);
+    }
+
+    #[test]
+    fn test_commitment_error_display() {
+        let n = 1000;
+        let commitment = Commitment::new(n).unwrap();
+        let (p, nonce) = commitment.publish();
+        let mut other_nonce = nonce;
+        other_nonce[0] ^= 1;
+        // The error can be propagated as a Box<dyn Error>.
+        let validate = || -> Result<(), Box<dyn std::error::Error>> {
+            commitment.commit().validate(p, &other_nonce)?;
+            Ok(())
+        };
+        let error = validate().unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("the published value and nonce don't match"));
+        assert_eq!(
+            CommitmentError::CorpusSizeMismatch.to_string(),
+            "the commitments were made for different corpus sizes"
+        );
+        assert_eq!(
+            CommitmentError::EmptyCorpus.to_string(),
+            "there is nothing to select from (empty corpus or no opened commitments)"
+        );
+    }
+
+    #[test]
+    fn test_opened_commitment_range() {
+        let n = 1000;
+        let max = max_published_value(n);
+        assert!(OpenedCommitment::new(n, max).validate_range().is_ok());
+        assert!(matches!(
+            OpenedCommitment::new(n, max + 1).validate_range(),
+            Err(CommitmentError::PublishedValueOutOfRange)
+        ));
+        assert!(matches!(
+            OpenedCommitment::new(0, 0).validate_range(),
+            Err(CommitmentError::EmptyCorpus)
+        ));
+
+        // gather rejects an out-of-range value wherever it is, rather than reducing it modulo n.
+        let opened_commitments = vec![
+            OpenedCommitment::new(n, 1),
+            OpenedCommitment::new(n, u64::MAX),
+        ];
+        assert!(matches!(
+            OpenedCommitment::gather(&opened_commitments),
+            Err(CommitmentError::PublishedValueOutOfRange)
+        ));
+        let opened_commitments = vec![
+            OpenedCommitment::new(n, max + 1),
+            OpenedCommitment::new(n, 1),
+        ];
+        assert!(matches!(
+            OpenedCommitment::gather(&opened_commitments),
+            Err(CommitmentError::PublishedValueOutOfRange)
+        ));
+        let opened_commitments = vec![OpenedCommitment::new(n, max), OpenedCommitment::new(n, 1)];
+        assert_eq!(OpenedCommitment::gather(&opened_commitments).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_reveal_round_with_challenge() {
+        let n = 1000;
+        let challenge: Vec<u8> = (0..32).map(|_| rand::thread_rng().gen::<u8>()).collect();
+        let commitments = [Commitment::new(n).unwrap(), Commitment::new(n).unwrap()];
+        let published_values: Vec<(u64, Nonce)> = commitments.iter().map(|c| c.publish()).collect();
+        let expected_index =
+            ((published_values[0].0 as u128 + published_values[1].0 as u128) % n as u128) as u64;
+
+        let mut round = RevealRound::new(n).with_challenge(&challenge);
+        for commitment in commitments.iter() {
+            round
+                .submit(commitment.commit_with_challenge(&challenge))
+                .unwrap();
+        }
+        assert_eq!(
+            round
+                .finalize(&published_values, &mut SeenCommitments::new())
+                .unwrap(),
+            expected_index
+        );
+
+        // Commitments made without the challenge don't validate.
+        let mut round = RevealRound::new(n).with_challenge(&challenge);
+        for commitment in commitments.iter() {
+            round.submit(commitment.commit()).unwrap();
+        }
+        assert!(matches!(
+            round.finalize(&published_values, &mut SeenCommitments::new()),
+            Err(CommitmentError::HashMismatch)
+        ));
+
+        // Force a collision: a third participant submits a copy of the first participant's
+        // commitment and published value. The copy counts once, wherever it's submitted.
+        let copy = Commitment {
+            n,
+            p: commitments[0].p,
+            nonce: commitments[0].nonce,
+            personalization: commitments[0].personalization,
+            digest: PhantomData,
+        };
+        for copy_position in 0..3 {
+            let mut participants: Vec<&Commitment> = commitments.iter().collect();
+            participants.insert(copy_position, &copy);
+            let mut round = RevealRound::new(n).with_challenge(&challenge);
+            for participant in participants.iter() {
+                round
+                    .submit(participant.commit_with_challenge(&challenge))
+                    .unwrap();
+            }
+            let published_values: Vec<(u64, Nonce)> =
+                participants.iter().map(|c| c.publish()).collect();
+            let mut seen_commitments = SeenCommitments::new();
+            assert_eq!(
+                round
+                    .finalize(&published_values, &mut seen_commitments)
+                    .unwrap(),
+                expected_index
+            );
+            assert_eq!(seen_commitments.len(), 2);
+        }
+
+        // Colliding participants count as one towards the minimum number of honest participants.
+        let mut round = RevealRound::new(n)
+            .with_challenge(&challenge)
+            .with_min_honest(2);
+        round
+            .submit(commitments[0].commit_with_challenge(&challenge))
+            .unwrap();
+        round
+            .submit(copy.commit_with_challenge(&challenge))
+            .unwrap();
+        assert!(matches!(
+            round.finalize(
+                &[published_values[0], copy.publish()],
+                &mut SeenCommitments::new()
+            ),
+            Err(CommitmentError::InsufficientParties)
+        ));
+    }
+
+    #[test]
+    fn test_published_value_uniform() {
+        // The accepted range holds the same number of values of each residue.
+        for n in [1, 2, 3, 5, 1000, 162_564_322, u64::MAX].iter() {
+            assert_eq!((max_published_value(*n) as u128 + 1) % *n as u128, 0);
+        }
+
+        // A chi-square goodness of fit test of the residues modulo a small n. With n - 1 = 4
+        // degrees of freedom, the statistic exceeds 33.4 with probability below 10^-6.
+        let n = 5;
+        let n_samples = SAMPLE_COUNT;
+        let mut rng = seeded_rng(13);
+        let mut counts = vec![0; n as usize];
+        for _ in 0..n_samples {
+            counts[(sample_published_value(n, &mut rng) % n) as usize] += 1;
+        }
+        let expected = n_samples as f64 / n as f64;
+        let chi_square: f64 = counts
+            .iter()
+            .map(|count| (*count as f64 - expected).powi(2) / expected)
+            .sum();
+        assert!(chi_square < 33.4_f64);
+    }
+
+    #[test]
+    fn test_large_corpus_size() {
+        for n in [1 << 63, (1 << 63) + 1, u64::MAX - 1, u64::MAX].iter() {
+            let max_p = max_published_value(*n);
+            assert_eq!(max_p, n - 1);
+            let commitment = Commitment::new(*n).unwrap();
+            let (p, nonce) = commitment.publish();
+            assert!(p <= max_p);
+            let opened_commitment = commitment.commit().validate(p, &nonce).unwrap();
+            let index = OpenedCommitment::gather(&[opened_commitment]).unwrap();
+            assert!(index < *n);
+        }
+    }
+
+    #[test]
+    fn test_serialize_round() {
+        let n = 1000;
+        let commitments: Vec<Commitment> = (0..100).map(|_| Commitment::new(n).unwrap()).collect();
+        let closed_commitments: Vec<ClosedCommitment> =
+            commitments.iter().map(|c| c.commit()).collect();
+        let blob = serialize_round(&closed_commitments).unwrap();
+        let received: Vec<ClosedCommitment> = deserialize_round(&blob).unwrap();
+        assert_eq!(received.len(), commitments.len());
+        for (commitment, closed_commitment) in commitments.iter().zip(received.iter()) {
+            let (p, nonce) = commitment.publish();
+            assert!(closed_commitment.validate(p, &nonce).is_ok());
+        }
+        let no_commitments: [ClosedCommitment; 0] = [];
+        let empty_blob = serialize_round(&no_commitments).unwrap();
+        assert!(deserialize_round::<Sha256>(&empty_blob).unwrap().is_empty());
+
+        for len in [0, 3, blob.len() / 2, blob.len() - 1].iter() {
+            assert!(matches!(
+                deserialize_round::<Sha256>(&blob[..*len]),
+                Err(CommitmentError::MalformedBytes)
+            ));
+        }
+        let mut padded_blob = blob.clone();
+        padded_blob.push(0);
+        assert!(matches!(
+            deserialize_round::<Sha256>(&padded_blob),
+            Err(CommitmentError::MalformedBytes)
+        ));
+    }
+
+    #[test]
+    fn test_digest() {
+        use sha2::Sha512_256;
+
+        let n = 1000;
+        let commitment = Commitment::<Sha512_256>::new_with_digest(n).unwrap();
+        let closed_commitment = commitment.commit();
+        let (p, nonce) = commitment.publish();
+        let opened_commitment = closed_commitment.validate(p, &nonce).unwrap();
+        assert_eq!(
+            OpenedCommitment::gather(&[opened_commitment]).unwrap(),
+            p % n
+        );
+        assert!(commitment.publish_checked(&closed_commitment).is_ok());
+        assert!(verify_binding::<Sha512_256>());
+        // A round of such commitments is decoded with the same digest.
+        let blob = serialize_round(&[commitment.commit()]).unwrap();
+        let received = deserialize_round::<Sha512_256>(&blob).unwrap();
+        assert!(received[0].validate(p, &nonce).is_ok());
+
+        // Sha512_256 digests are as long as Sha256 ones, but a commitment made with one never
+        // validates with the other.
+        let sha256_closed_commitment = ClosedCommitment::new(n, closed_commitment.hash.clone());
+        assert!(matches!(
+            sha256_closed_commitment.validate(p, &nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+        let commitment = Commitment::new(n).unwrap();
+        let (p, nonce) = commitment.publish();
+        let sha512_256_closed_commitment =
+            ClosedCommitment::<Sha512_256>::new_with_digest(n, commitment.commit().hash);
+        assert!(matches!(
+            sha512_256_closed_commitment.validate(p, &nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_closed_commitment_bytes() {
+        let closed_commitment = ClosedCommitment::new(0x0102_0304_0506_0708, vec![0xab; 32]);
+        let bytes = closed_commitment.to_bytes();
+        let mut expected = vec![1, 2, 3, 4, 5, 6, 7, 8, 0, 0, 0, 32];
+        expected.extend_from_slice(&[0xab; 32]);
+        assert_eq!(bytes, expected);
+        assert_eq!(
+            ClosedCommitment::<Sha256>::from_bytes(&bytes)
+                .unwrap()
+                .to_bytes(),
+            bytes
+        );
+
+        let commitment = Commitment::new(1000).unwrap();
+        let (p, nonce) = commitment.publish();
+        let received: ClosedCommitment =
+            ClosedCommitment::from_bytes(&commitment.commit().to_bytes()).unwrap();
+        assert_eq!(received.corpus_size(), 1000);
+        assert_eq!(received.validate(p, &nonce).unwrap().corpus_size(), 1000);
+
+        for len in [0, 7, 11, bytes.len() - 1].iter() {
+            assert!(matches!(
+                ClosedCommitment::<Sha256>::from_bytes(&bytes[..*len]),
+                Err(CommitmentError::MalformedBytes)
+            ));
+        }
+        let mut padded_bytes = bytes.clone();
+        padded_bytes.push(0);
+        assert!(matches!(
+            ClosedCommitment::<Sha256>::from_bytes(&padded_bytes),
+            Err(CommitmentError::MalformedBytes)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_corpus_size() {
+        assert!(matches!(
+            Commitment::new(0),
+            Err(CommitmentError::InvalidCorpusSize)
+        ));
+        assert!(Commitment::new(1).is_ok());
+    }
+
+    #[test]
+    fn test_published_value_out_of_range() {
+        let n = 1000;
+        let max_p = max_published_value(n);
+        assert!(max_p < u64::MAX);
+        assert_eq!(max_p % n, n - 1);
+        let commitment = Commitment::new_with_p(n, max_p);
+        let nonce = commitment.nonce;
+        assert!(commitment.commit().validate(max_p, &nonce).is_ok());
+
+        // A malformed message decoding to a value no participant could have published.
+        let received_bytes = [0xff_u8; 8];
+        let received_p = u64::from_be_bytes(received_bytes);
+        assert!(matches!(
+            commitment.commit().validate(received_p, &nonce),
+            Err(CommitmentError::PublishedValueOutOfRange)
+        ));
+        assert!(matches!(
+            ClosedCommitment::new(0, commitment.commit().hash).validate(0, &nonce),
+            Err(CommitmentError::EmptyCorpus)
+        ));
+    }
+
+    #[test]
+    fn test_gather_with_transcript() {
+        let n = 1000;
+        let commitments: Vec<Commitment> = (0..3).map(|_| Commitment::new(n).unwrap()).collect();
+        let closed_commitments: Vec<ClosedCommitment> = commitments
+            .iter()
+            .map(|commitment| commitment.commit())
+            .collect();
+        let published_values: Vec<(u64, Nonce)> = commitments
+            .iter()
+            .map(|commitment| commitment.publish())
+            .collect();
+        let nonces: Vec<Nonce> = published_values.iter().map(|(_, nonce)| *nonce).collect();
+        let opened_commitments: Vec<OpenedCommitment> = closed_commitments
+            .iter()
+            .zip(&published_values)
+            .map(|(closed_commitment, (p, nonce))| closed_commitment.validate(*p, nonce).unwrap())
+            .collect();
+
+        let (index, transcript) =
+            OpenedCommitment::gather_with_transcript(&opened_commitments).unwrap();
+        assert_eq!(
+            index,
+            OpenedCommitment::gather(&opened_commitments).unwrap()
+        );
+        assert_eq!(transcript.index(), index);
+        assert_eq!(
+            transcript.contributions(),
+            &published_values
+                .iter()
+                .map(|(p, _)| (n, *p))
+                .collect::<Vec<_>>()[..]
+        );
+        assert_eq!(
+            transcript.verify(&closed_commitments, &nonces).unwrap(),
+            index
+        );
+
+        assert!(matches!(
+            transcript.verify(&closed_commitments[..2], &nonces[..2]),
+            Err(CommitmentError::OpeningCountMismatch)
+        ));
+        // The nonces must be given in the order of the contributions.
+        let mut swapped_nonces = nonces.clone();
+        swapped_nonces.swap(0, 1);
+        assert!(matches!(
+            transcript.verify(&closed_commitments, &swapped_nonces),
+            Err(CommitmentError::HashMismatch)
+        ));
+        let mut forged = transcript.clone();
+        forged.index = (index + 1) % n;
+        assert!(matches!(
+            forged.verify(&closed_commitments, &nonces),
+            Err(CommitmentError::IndexDisagreement)
+        ));
+        let mut forged = transcript.clone();
+        forged.contributions[0].1 = (forged.contributions[0].1 + 1) % n;
+        assert!(matches!(
+            forged.verify(&closed_commitments, &nonces),
+            Err(CommitmentError::HashMismatch)
+        ));
+        let mut forged = transcript;
+        forged.contributions[0].0 = n + 1;
+        assert!(matches!(
+            forged.verify(&closed_commitments, &nonces),
+            Err(CommitmentError::CorpusSizeMismatch)
+        ));
+
+        assert!(matches!(
+            OpenedCommitment::gather_with_transcript(&[]),
+            Err(CommitmentError::EmptyCorpus)
+        ));
+    }
+
+    #[test]
+    fn test_gather_threshold() {
+        let n = 1000;
+        let opened_commitments: Vec<OpenedCommitment> = (0..5)
+            .map(|_| {
+                let commitment = Commitment::new(n).unwrap();
+                let (p, nonce) = commitment.publish();
+                commitment.commit().validate(p, &nonce).unwrap()
+            })
+            .collect();
+        // With all of the servers present, this is the same as gather.
+        assert_eq!(
+            OpenedCommitment::gather_threshold(&opened_commitments, 3).unwrap(),
+            OpenedCommitment::gather(&opened_commitments).unwrap()
+        );
+        // With two servers dropped out, the rest still meet the threshold.
+        assert_eq!(
+            OpenedCommitment::gather_threshold(&opened_commitments[..3], 3).unwrap(),
+            OpenedCommitment::gather(&opened_commitments[..3]).unwrap()
+        );
+        assert!(matches!(
+            OpenedCommitment::gather_threshold(&opened_commitments[..2], 3),
+            Err(CommitmentError::InsufficientParties)
+        ));
+        // The threshold can't be less than min_honest_servers().
+        assert!(matches!(
+            OpenedCommitment::gather_threshold(&[], 0),
+            Err(CommitmentError::InsufficientParties)
+        ));
+    }
+
+    #[test]
+    fn test_min_honest() {
+        let n = 1000;
+        assert_eq!(min_honest_servers(), 1);
+        assert!(!security_assumptions().is_empty());
+
+        let commitment = Commitment::new(n).unwrap();
+        let mut round = RevealRound::new(n).with_min_honest(2);
+        round.submit(commitment.commit()).unwrap();
+        assert!(matches!(
+            round.finalize(&[commitment.publish()], &mut SeenCommitments::new()),
+            Err(CommitmentError::InsufficientParties)
+        ));
+
+        // A round can't be configured to require fewer than min_honest_servers().
+        let round = RevealRound::new(n).with_min_honest(0);
+        assert!(matches!(
+            round.finalize(&[], &mut SeenCommitments::new()),
+            Err(CommitmentError::InsufficientParties)
+        ));
+
+        let commitments = vec![Commitment::new(n).unwrap(), Commitment::new(n).unwrap()];
+        let mut round = RevealRound::new(n).with_min_honest(2);
+        for commitment in &commitments {
+            round.submit(commitment.commit()).unwrap();
+        }
+        let published_values: Vec<(u64, Nonce)> = commitments.iter().map(|c| c.publish()).collect();
+        assert!(round
+            .finalize(&published_values, &mut SeenCommitments::new())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_reveal_round_events() {
+        let n = 1000;
+        let counting_sink = events::CountingSink::new();
+        let commitments = vec![
+            Commitment::new(n).unwrap(),
+            Commitment::new(n).unwrap(),
+            Commitment::new(n).unwrap(),
+        ];
+        let mut round = RevealRound::new(n)
+            .with_max_participants(commitments.len())
+            .with_event_sink(Box::new(counting_sink.clone()));
+        for commitment in &commitments {
+            round.submit(commitment.commit()).unwrap();
+        }
+        assert!(round
+            .submit(Commitment::new(n + 1).unwrap().commit())
+            .is_err());
+        assert!(round.submit(Commitment::new(n).unwrap().commit()).is_err());
+        let published_values: Vec<(u64, Nonce)> = commitments.iter().map(|c| c.publish()).collect();
+        round
+            .finalize(&published_values, &mut SeenCommitments::new())
+            .unwrap();
+        assert_eq!(counting_sink.count(Event::CommitmentReceived), 3);
+        assert_eq!(counting_sink.count(Event::CommitmentRejected), 2);
+        assert_eq!(counting_sink.count(Event::OpeningValidated), 3);
+        assert_eq!(counting_sink.count(Event::OpeningRejected), 0);
+        assert_eq!(counting_sink.count(Event::IndexSelected), 1);
+
+        // Validation stops at the first value that doesn't open its commitment.
+        let counting_sink = events::CountingSink::new();
+        let mut round = RevealRound::new(n).with_event_sink(Box::new(counting_sink.clone()));
+        for commitment in &commitments {
+            round.submit(commitment.commit()).unwrap();
+        }
+        let mut tampered_values = published_values.clone();
+        tampered_values[1].0 = tampered_values[1].0.wrapping_add(1);
+        assert!(round
+            .finalize(&tampered_values, &mut SeenCommitments::new())
+            .is_err());
+        assert_eq!(counting_sink.count(Event::OpeningValidated), 1);
+        assert_eq!(counting_sink.count(Event::OpeningRejected), 1);
+        assert_eq!(counting_sink.count(Event::IndexSelected), 0);
+
+        // A round, along with its sink, can be moved to another thread, and the counts read from
+        // this one.
+        let counting_sink = events::CountingSink::new();
+        let mut round = RevealRound::new(n).with_event_sink(Box::new(counting_sink.clone()));
+        std::thread::spawn(move || {
+            for commitment in &commitments {
+                round.submit(commitment.commit()).unwrap();
+            }
+            round
+                .finalize(&published_values, &mut SeenCommitments::new())
+                .unwrap();
+        })
+        .join()
+        .unwrap();
+        assert_eq!(counting_sink.count(Event::OpeningValidated), 3);
+        assert_eq!(counting_sink.count(Event::IndexSelected), 1);
+    }
+
+    #[test]
+    fn test_reveal_round_max_participants() {
+        let n = 1000;
+        let max_participants = 3;
+        let mut round = RevealRound::new(n).with_max_participants(max_participants);
+        for _ in 0..max_participants {
+            assert!(round.submit(Commitment::new(n).unwrap().commit()).is_ok());
+        }
+        assert!(matches!(
+            round.submit(Commitment::new(n).unwrap().commit()),
+            Err(CommitmentError::TooManyParticipants)
+        ));
+    }
+
+    #[test]
+    fn test_gather_from_iterator() {
+        let n = 10;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for p in [3, 4, 8].iter() {
+            sender.send(OpenedCommitment::new(n, *p)).unwrap();
+        }
+        drop(sender);
+        assert_eq!(OpenedCommitment::gather(receiver).unwrap(), 5);
+        let opened_commitments = vec![OpenedCommitment::new(n, 3), OpenedCommitment::new(n, 4)];
+        assert_eq!(
+            OpenedCommitment::gather(opened_commitments.iter()).unwrap(),
+            7
+        );
+        assert_eq!(OpenedCommitment::gather(opened_commitments).unwrap(), 7);
+        assert!(matches!(
+            OpenedCommitment::gather(std::iter::empty::<OpenedCommitment>()),
+            Err(CommitmentError::EmptyCorpus)
+        ));
+        assert!(matches!(
+            OpenedCommitment::gather(vec![
+                OpenedCommitment::new(n, 3),
+                OpenedCommitment::new(n + 1, 4)
+            ]),
+            Err(CommitmentError::CorpusSizeMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_gather_signed() {
+        assert_eq!(OpenedCommitment::gather_signed(10, &[3, 4]).unwrap(), 7);
+        assert_eq!(OpenedCommitment::gather_signed(10, &[3, -4]).unwrap(), 9);
+        assert_eq!(
+            OpenedCommitment::gather_signed(10, &[-25, 2, -1]).unwrap(),
+            6
+        );
+        assert_eq!(OpenedCommitment::gather_signed(10, &[-30]).unwrap(), 0);
+        assert_eq!(
+            OpenedCommitment::gather_signed(u64::MAX, &[i64::MIN, i64::MIN, -1]).unwrap(),
+            u64::MAX - 2
+        );
+        assert!(matches!(
+            OpenedCommitment::gather_signed(10, &[]),
+            Err(CommitmentError::EmptyCorpus)
+        ));
+        assert!(matches!(
+            OpenedCommitment::gather_signed(0, &[1]),
+            Err(CommitmentError::EmptyCorpus)
+        ));
+    }
+
+    #[test]
+    fn test_publish_checked() {
+        let n = 1000;
+        let commitment = Commitment::new_with_p(n, 17);
+        let other_commitment = Commitment::new_with_p(n, 42);
+        let closed_commitment = commitment.commit();
+        let other_closed_commitment = other_commitment.commit();
+        let result = commitment.publish_checked(&closed_commitment);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), commitment.publish());
+        assert!(matches!(
+            commitment.publish_checked(&other_closed_commitment),
+            Err(CommitmentError::HashMismatch)
+        ));
+    }
 }
@@ -0,0 +1,349 @@
+// A commitment to a random permutation of the corpus rather than to a single index, for the
+// oblivious-selection variant of the protocol, in which the servers shuffle the whole corpus.
+// Each server commits to a random seed, from which the permutation is derived deterministically
+// with a Fisher-Yates shuffle. Once every commitment is closed, the seeds are revealed, validated,
+// and the permutations composed. As with the single-index commitment, the composition is a
+// uniformly random permutation as long as one of the servers chose its seed at random, and no
+// server can choose its seed depending on the others'.
+
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use crate::{CommitmentError, Nonce, Personalization, DEFAULT_PERSONALIZATION};
+
+// Distinguishes permutation commitments from index commitments, so that neither can be opened as
+// the other.
+const DOMAIN_TAG: &[u8] = b"dprio-permutation-v1";
+
+pub type PermutationSeed = [u8; 32];
+
+// The stream of pseudorandom u64s a permutation is derived from: the i-th block is
+// SHA-256(seed || i). Every party must derive the same permutation from a seed, so this doesn't
+// use a rand RNG, whose output may change between versions of rand.
+struct SeedStream {
+    seed: PermutationSeed,
+    counter: u64,
+    block: Vec<u8>,
+    offset: usize,
+}
+
+impl SeedStream {
+    fn new(seed: &PermutationSeed) -> SeedStream {
+        SeedStream {
+            seed: *seed,
+            counter: 0,
+            block: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.offset == self.block.len() {
+            let mut hasher = Sha256::new();
+            hasher.update(self.seed);
+            hasher.update(self.counter.to_be_bytes());
+            self.block = hasher.finalize().to_vec();
+            self.counter += 1;
+            self.offset = 0;
+        }
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.block[self.offset..self.offset + 8]);
+        self.offset += 8;
+        u64::from_be_bytes(bytes)
+    }
+
+    // Returns a uniform value in [0, bound), by rejection (see `sample_published_value`).
+    fn next_below(&mut self, bound: u64) -> u64 {
+        let limit = bound * (u64::MAX / bound);
+        loop {
+            let x = self.next_u64();
+            if x < limit {
+                return x % bound;
+            }
+        }
+    }
+}
+
+// Returns the permutation of [0, n) derived from seed: position i of the result holds the index
+// that i is mapped to.
+pub fn permutation_from_seed(seed: &PermutationSeed, n: usize) -> Vec<usize> {
+    let mut stream = SeedStream::new(seed);
+    let mut permutation: Vec<usize> = (0..n).collect();
+    for i in (1..n).rev() {
+        let j = stream.next_below(i as u64 + 1) as usize;
+        permutation.swap(i, j);
+    }
+    permutation
+}
+
+// Returns true if values is a permutation of [0, values.len()).
+pub fn is_permutation(values: &[usize]) -> bool {
+    let mut seen = vec![false; values.len()];
+    for value in values {
+        match seen.get_mut(*value) {
+            Some(seen) if !*seen => *seen = true,
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn permutation_commitment_hash(
+    personalization: &Personalization,
+    nonce: &Nonce,
+    n: usize,
+    seed: &PermutationSeed,
+) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(DOMAIN_TAG);
+    hasher.update(personalization);
+    hasher.update(nonce);
+    hasher.update((n as u64).to_be_bytes());
+    hasher.update(seed);
+    hasher.finalize().to_vec()
+}
+
+pub struct PermutationCommitment {
+    n: usize,
+    seed: PermutationSeed,
+    nonce: Nonce,
+    personalization: Personalization,
+}
+
+impl PermutationCommitment {
+    // A commitment to a permutation of a corpus of size n, which must be positive.
+    pub fn new(n: usize) -> Result<PermutationCommitment, CommitmentError> {
+        if n == 0 {
+            return Err(CommitmentError::InvalidCorpusSize);
+        }
+        let mut rng = rand::thread_rng();
+        Ok(PermutationCommitment {
+            n,
+            seed: rng.gen(),
+            nonce: rng.gen(),
+            personalization: DEFAULT_PERSONALIZATION,
+        })
+    }
+
+    pub fn with_personalization(
+        mut self,
+        personalization: Personalization,
+    ) -> PermutationCommitment {
+        self.personalization = personalization;
+        self
+    }
+
+    pub fn commit(&self) -> ClosedPermutationCommitment {
+        ClosedPermutationCommitment {
+            n: self.n,
+            hash: permutation_commitment_hash(
+                &self.personalization,
+                &self.nonce,
+                self.n,
+                &self.seed,
+            ),
+            personalization: self.personalization,
+        }
+    }
+
+    // Returns the seed to reveal, along with the nonce needed to validate it.
+    pub fn publish(&self) -> (PermutationSeed, Nonce) {
+        (self.seed, self.nonce)
+    }
+}
+
+pub struct ClosedPermutationCommitment {
+    n: usize,
+    hash: Vec<u8>,
+    personalization: Personalization,
+}
+
+impl ClosedPermutationCommitment {
+    // See `ClosedCommitment::with_personalization`.
+    pub fn with_personalization(
+        mut self,
+        personalization: Personalization,
+    ) -> ClosedPermutationCommitment {
+        self.personalization = personalization;
+        self
+    }
+
+    // Validates a revealed seed and its nonce against the commitment, and derives the permutation.
+    pub fn validate(
+        &self,
+        seed: &PermutationSeed,
+        nonce: &Nonce,
+    ) -> Result<OpenedPermutation, CommitmentError> {
+        if self.n == 0 {
+            return Err(CommitmentError::EmptyCorpus);
+        }
+        if permutation_commitment_hash(&self.personalization, nonce, self.n, seed) != self.hash {
+            return Err(CommitmentError::HashMismatch);
+        }
+        Ok(OpenedPermutation {
+            permutation: permutation_from_seed(seed, self.n),
+        })
+    }
+}
+
+pub struct OpenedPermutation {
+    permutation: Vec<usize>,
+}
+
+impl OpenedPermutation {
+    pub fn permutation(&self) -> &[usize] {
+        &self.permutation
+    }
+
+    // The analog of `OpenedCommitment::gather`: composes the permutations in the order given,
+    // i.e. index i is mapped by the first permutation, the result by the second, and so on. All of
+    // the permutations must be of the same corpus.
+    pub fn compose(
+        opened_permutations: &[OpenedPermutation],
+    ) -> Result<Vec<usize>, CommitmentError> {
+        let n = match opened_permutations.first() {
+            Some(opened_permutation) => opened_permutation.permutation.len(),
+            None => return Err(CommitmentError::EmptyCorpus),
+        };
+        if opened_permutations
+            .iter()
+            .any(|opened_permutation| opened_permutation.permutation.len() != n)
+        {
+            return Err(CommitmentError::CorpusSizeMismatch);
+        }
+        let mut composed: Vec<usize> = (0..n).collect();
+        for opened_permutation in opened_permutations {
+            for index in composed.iter_mut() {
+                *index = opened_permutation.permutation[*index];
+            }
+        }
+        Ok(composed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn test_permutation_from_seed() {
+        let seed = [7; 32];
+        let permutation = permutation_from_seed(&seed, 100);
+        assert!(is_permutation(&permutation));
+        // The permutation is a function of the seed alone.
+        assert_eq!(permutation, permutation_from_seed(&seed, 100));
+        assert_ne!(permutation, permutation_from_seed(&[8; 32], 100));
+        assert_eq!(permutation_from_seed(&seed, 1), vec![0]);
+        assert!(permutation_from_seed(&seed, 0).is_empty());
+
+        assert!(!is_permutation(&[0, 0]));
+        assert!(!is_permutation(&[1, 2]));
+
+        // Where index 0 is mapped to is uniform.
+        let n = 4;
+        let n_samples = SAMPLE_COUNT;
+        let mut rng = seeded_rng(14);
+        let mut counts = vec![0; n];
+        for _ in 0..n_samples {
+            counts[permutation_from_seed(&rng.gen(), n)[0]] += 1;
+        }
+        let expected = 1.0_f64 / n as f64;
+        for count in counts {
+            assert!(
+                (count as f64 / n_samples as f64 - expected).abs()
+                    < frequency_tolerance(expected, n_samples)
+            );
+        }
+    }
+
+    #[test]
+    fn test_compose() {
+        let n = 50;
+        let commitments: Vec<PermutationCommitment> = (0..3)
+            .map(|_| PermutationCommitment::new(n).unwrap())
+            .collect();
+        let closed_commitments: Vec<ClosedPermutationCommitment> =
+            commitments.iter().map(|c| c.commit()).collect();
+        let opened_permutations: Vec<OpenedPermutation> = commitments
+            .iter()
+            .zip(closed_commitments.iter())
+            .map(|(commitment, closed_commitment)| {
+                let (seed, nonce) = commitment.publish();
+                closed_commitment.validate(&seed, &nonce).unwrap()
+            })
+            .collect();
+        for opened_permutation in opened_permutations.iter() {
+            assert!(is_permutation(opened_permutation.permutation()));
+        }
+        let composed = OpenedPermutation::compose(&opened_permutations).unwrap();
+        assert!(is_permutation(&composed));
+        for (i, index) in composed.iter().enumerate() {
+            let expected = opened_permutations
+                .iter()
+                .fold(i, |index, opened| opened.permutation()[index]);
+            assert_eq!(*index, expected);
+        }
+
+        assert!(matches!(
+            OpenedPermutation::compose(&[]),
+            Err(CommitmentError::EmptyCorpus)
+        ));
+        let other = PermutationCommitment::new(n + 1).unwrap();
+        let (seed, nonce) = other.publish();
+        let mismatched = vec![
+            closed_commitments[0]
+                .validate(&commitments[0].publish().0, &commitments[0].publish().1)
+                .unwrap(),
+            other.commit().validate(&seed, &nonce).unwrap(),
+        ];
+        assert!(matches!(
+            OpenedPermutation::compose(&mismatched),
+            Err(CommitmentError::CorpusSizeMismatch)
+        ));
+        assert!(matches!(
+            PermutationCommitment::new(0),
+            Err(CommitmentError::InvalidCorpusSize)
+        ));
+    }
+
+    #[test]
+    fn test_tampering() {
+        let n = 50;
+        let commitment = PermutationCommitment::new(n).unwrap();
+        let closed_commitment = commitment.commit();
+        let (seed, nonce) = commitment.publish();
+        assert!(closed_commitment.validate(&seed, &nonce).is_ok());
+
+        // Revealing any other seed (e.g. one chosen after seeing the other servers' seeds) or
+        // nonce is detected.
+        let mut other_seed = seed;
+        other_seed[31] ^= 1;
+        assert!(matches!(
+            closed_commitment.validate(&other_seed, &nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+        let mut other_nonce = nonce;
+        other_nonce[0] ^= 1;
+        assert!(matches!(
+            closed_commitment.validate(&seed, &other_nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+        // So is a commitment from another deployment.
+        assert!(matches!(
+            closed_commitment
+                .with_personalization([1; 16])
+                .validate(&seed, &nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+        // The commitment also binds the corpus size.
+        let closed_commitment = ClosedPermutationCommitment {
+            n: n + 1,
+            ..commitment.commit()
+        };
+        assert!(matches!(
+            closed_commitment.validate(&seed, &nonce),
+            Err(CommitmentError::HashMismatch)
+        ));
+    }
+}
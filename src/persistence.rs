@@ -0,0 +1,300 @@
+extern crate chacha20poly1305;
+
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::Rng;
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use crate::{NoiseCorpus, NoisePool, NoiseShare, SelectionContext};
+
+// The key a server's noise candidates are encrypted with at rest (see `SelectionContext::save`).
+pub type PoolKey = [u8; 32];
+
+// Authenticated along with a saved corpus, so that a file encrypted with the same key for another
+// purpose (or another version of this format) doesn't load as a corpus.
+const POOL_DOMAIN_TAG: &[u8] = b"dprio-noise-pool-v1";
+
+const NONCE_LEN: usize = 12;
+
+impl SelectionContext {
+    // Saves the context's corpus to the file at path, encrypted and authenticated with key
+    // (ChaCha20-Poly1305 with a fresh random nonce), so that a server doesn't have to regenerate
+    // its candidates after a restart. The file is written next to path and flushed to disk, then
+    // renamed over it, and the rename is flushed as well, so once this returns the new file
+    // survives a crash or power loss, and a crash while saving leaves the previous file intact. On
+    // unix, the file is only readable and writable by its owner.
+    //
+    // The file holds a single server's shares, and must only ever be readable by that server: the
+    // noise is split between the servers so that neither learns it, and anyone who holds both
+    // servers' files and keys can reconstruct every candidate (see `NoisePool::save`). Beyond
+    // that, key management is up to the caller, and the encryption is only as good as it:
+    // - The key must be kept out of reach of anyone who can read the file (e.g. in the server's
+    //   KMS or HSM, never next to the file or in the same backup), and should be generated with a
+    //   cryptographically secure RNG.
+    // - A saved corpus is a snapshot. Restoring an older snapshot brings back candidates that were
+    //   drawn since, and noise that's injected twice no longer protects the clients' data, so the
+    //   corpus must be saved after every draw (and before the result of the draw is used), and
+    //   older snapshots must be deleted rather than kept as backups.
+    pub fn save<P: AsRef<Path>>(&self, path: P, key: &PoolKey) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill(&mut nonce);
+        let mut plaintext = Vec::new();
+        write_corpus(&mut plaintext, self.corpus())?;
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: POOL_DOMAIN_TAG,
+                },
+            )
+            .map_err(|_| io::Error::other("encrypting the corpus failed"))?;
+        let mut contents = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        contents.extend_from_slice(&nonce);
+        contents.extend_from_slice(&ciphertext);
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(".tmp");
+        write_private_file(Path::new(&temporary_path), &contents)?;
+        std::fs::rename(&temporary_path, path)?;
+        sync_parent_dir(path)
+    }
+
+    // Loads a context saved with `save` and the same key. A wrong key, or a file that was modified
+    // or truncated, is an error of kind InvalidData.
+    pub fn load<P: AsRef<Path>>(path: P, key: &PoolKey) -> io::Result<SelectionContext> {
+        let contents = std::fs::read(path)?;
+        if contents.len() < NONCE_LEN {
+            return Err(invalid_data());
+        }
+        let (nonce, ciphertext) = contents.split_at(NONCE_LEN);
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(key))
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: POOL_DOMAIN_TAG,
+                },
+            )
+            .map_err(|_| invalid_data())?;
+        let mut reader = &plaintext[..];
+        let corpus = read_corpus(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(invalid_data());
+        }
+        Ok(SelectionContext::new(corpus))
+    }
+}
+
+impl NoisePool {
+    // Saves each server's corpus with `SelectionContext::save`, to its own file under its own key.
+    // A pool holds both servers' shares of every candidate, so saving it to a single file (or
+    // under a single key) would let whoever holds that file and key reconstruct all of the noise;
+    // the keys must therefore differ, and should be held by different parties, as the servers'
+    // private keys are. In a deployment, each server saves only its own context.
+    pub fn save<P: AsRef<Path>>(&self, paths: [P; 2], keys: [&PoolKey; 2]) -> io::Result<()> {
+        if keys[0] == keys[1] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "each server's shares must be encrypted with its own key",
+            ));
+        }
+        for ((context, path), key) in self.contexts.iter().zip(paths.iter()).zip(keys.iter()) {
+            context.save(path, key)?;
+        }
+        Ok(())
+    }
+
+    // Loads a pool saved with `save` and the same keys. The two corpora must still be parallel.
+    pub fn load<P: AsRef<Path>>(paths: [P; 2], keys: [&PoolKey; 2]) -> io::Result<NoisePool> {
+        let [path1, path2] = paths;
+        let context1 = SelectionContext::load(path1, keys[0])?;
+        let context2 = SelectionContext::load(path2, keys[1])?;
+        if context1.corpus().len() != context2.corpus().len()
+            || context1.corpus().real_len() != context2.corpus().real_len()
+        {
+            return Err(invalid_data());
+        }
+        Ok(NoisePool {
+            contexts: [context1, context2],
+        })
+    }
+}
+
+// Writes contents to a new file at path, readable only by its owner on unix, and flushes it to
+// disk. Any file left at path (e.g. by a crash while saving) is replaced rather than reused, as it
+// might have been created with other permissions.
+fn write_private_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+// Flushes the directory entry of path to disk, so that a rename to path survives a power loss.
+// Directories can only be opened (and so flushed) like this on unix.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn invalid_data() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "the saved corpus is corrupted or was encrypted with another key",
+    )
+}
+
+// A corpus is encoded as its number of shares and its number of real shares (the rest are
+// padding), followed by each share's length and bytes, all in network byte order.
+fn write_corpus(buf: &mut Vec<u8>, corpus: &NoiseCorpus) -> io::Result<()> {
+    buf.write_u64::<NetworkEndian>(corpus.noise_shares.len() as u64)?;
+    buf.write_u64::<NetworkEndian>(corpus.n_real as u64)?;
+    for noise_share in corpus.noise_shares.iter() {
+        let len = u32::try_from(noise_share.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "noise share too long"))?;
+        buf.write_u32::<NetworkEndian>(len)?;
+        buf.extend_from_slice(noise_share);
+    }
+    Ok(())
+}
+
+fn read_corpus(reader: &mut &[u8]) -> io::Result<NoiseCorpus> {
+    let len = reader.read_u64::<NetworkEndian>()? as usize;
+    let n_real = reader.read_u64::<NetworkEndian>()? as usize;
+    if n_real > len {
+        return Err(invalid_data());
+    }
+    // Every share takes at least 4 bytes, which bounds the allocation by the size of the file.
+    let mut noise_shares = Vec::with_capacity(std::cmp::min(len, reader.len() / 4));
+    for _ in 0..len {
+        let share_len = reader.read_u32::<NetworkEndian>()? as usize;
+        if share_len > reader.len() {
+            return Err(invalid_data());
+        }
+        let (noise_share, rest) = reader.split_at(share_len);
+        noise_shares.push(NoiseShare::from(noise_share));
+        *reader = rest;
+    }
+    Ok(NoiseCorpus {
+        noise_shares,
+        n_real,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_save_and_load() {
+        let path = |server: usize| {
+            std::env::temp_dir().join(format!("dprio-pool-{}-{}", std::process::id(), server))
+        };
+        let paths = [path(1), path(2)];
+        let key1: PoolKey = rand::thread_rng().gen();
+        let key2: PoolKey = rand::thread_rng().gen();
+        let mut pool = NoisePool::new(
+            (0..20).map(|i| vec![i, 1]).collect(),
+            (0..20).map(|i| vec![i, 2]).collect(),
+        )
+        .unwrap();
+        pool.draw().unwrap();
+        pool.save([&paths[0], &paths[1]], [&key1, &key2]).unwrap();
+        let mut loaded_pool = NoisePool::load([&paths[0], &paths[1]], [&key1, &key2]).unwrap();
+        assert_eq!(loaded_pool.len(), 19);
+        // The loaded pool holds the same candidates, so the same draws select the same shares.
+        let mut rng1 = StdRng::seed_from_u64(17);
+        let mut rng2 = StdRng::seed_from_u64(17);
+        while let Some(noise_shares) = pool.draw_with_rng(&mut rng1) {
+            assert_eq!(loaded_pool.draw_with_rng(&mut rng2), Some(noise_shares));
+        }
+        assert!(loaded_pool.is_empty());
+
+        // The files are only readable by their owner.
+        #[cfg(unix)]
+        for path in &paths {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // Each file holds one server's shares, under that server's key only.
+        let context1 = SelectionContext::load(&paths[0], &key1).unwrap();
+        assert!(context1
+            .corpus()
+            .noise_shares
+            .iter()
+            .all(|noise_share| noise_share[1] == 1));
+        assert_eq!(
+            SelectionContext::load(&paths[0], &key2)
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            pool.save([&paths[0], &paths[1]], [&key1, &key1])
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        let mut wrong_key = key1;
+        wrong_key[0] ^= 1;
+        assert_eq!(
+            SelectionContext::load(&paths[0], &wrong_key)
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        let mut contents = std::fs::read(&paths[0]).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 1;
+        std::fs::write(&paths[0], &contents).unwrap();
+        assert_eq!(
+            SelectionContext::load(&paths[0], &key1)
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        std::fs::write(&paths[0], &contents[..NONCE_LEN - 1]).unwrap();
+        assert_eq!(
+            NoisePool::load([&paths[0], &paths[1]], [&key1, &key2])
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        for path in paths.iter() {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,308 @@
+extern crate ed25519_dalek;
+
+use byteorder::{NetworkEndian, WriteBytesExt};
+use ed25519_dalek::{Signature, Signer, Verifier};
+use sha2::{Digest, Sha256};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use crate::laplace::DpResult;
+use crate::{ClosedCommitment, CommitmentError};
+
+// Prefixes the signed bytes of a commitment, so that its signature can't be passed off as one over
+// anything else signed with the same key (such as a published result), or the other way around.
+const SIGNED_COMMITMENT_DOMAIN_TAG: &[u8] = b"dprio-signed-commitment-v1";
+
+// A ClosedCommitment signed by the randomness server that produced it. In an authenticated
+// deployment, only commitments signed by a registered server's key should be accepted, so that
+// unauthorized parties can't inject commitments into the noise selection.
+pub struct SignedCommitment {
+    closed_commitment: ClosedCommitment,
+    signature: Signature,
+}
+
+impl ClosedCommitment {
+    pub fn sign(self, signing_key: &SigningKey) -> SignedCommitment {
+        let signature = signing_key.sign(&self.signed_bytes());
+        SignedCommitment {
+            closed_commitment: self,
+            signature,
+        }
+    }
+
+    // The signature covers the domain tag, the corpus size, and the hash.
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(
+            SIGNED_COMMITMENT_DOMAIN_TAG.len() + std::mem::size_of::<u64>() + self.hash.len(),
+        );
+        buf.extend_from_slice(SIGNED_COMMITMENT_DOMAIN_TAG);
+        buf.write_u64::<NetworkEndian>(self.n).unwrap();
+        buf.extend_from_slice(&self.hash);
+        buf
+    }
+}
+
+impl SignedCommitment {
+    pub fn closed_commitment(&self) -> &ClosedCommitment {
+        &self.closed_commitment
+    }
+
+    // A hash of the wrong length can't be a commitment, whatever signed it, so it's rejected
+    // before the signature is checked.
+    pub fn verify_signed(&self, verifying_key: &VerifyingKey) -> Result<(), CommitmentError> {
+        if self.closed_commitment.hash.len() != Sha256::output_size() {
+            return Err(CommitmentError::InvalidSignature);
+        }
+        verifying_key
+            .verify(&self.closed_commitment.signed_bytes(), &self.signature)
+            .map_err(|_| CommitmentError::InvalidSignature)
+    }
+
+    // Succeeds if the commitment was signed by any of the keys in the allowlist.
+    pub fn verify_allowlisted(&self, allowlist: &[VerifyingKey]) -> Result<(), CommitmentError> {
+        if allowlist
+            .iter()
+            .any(|verifying_key| self.verify_signed(verifying_key).is_ok())
+        {
+            Ok(())
+        } else {
+            Err(CommitmentError::InvalidSignature)
+        }
+    }
+}
+
+// Prefixes the signed bytes of a published result, so that its signature can't be passed off as
+// one over anything else signed with the same key (such as a commitment).
+const PUBLISHED_RESULT_DOMAIN_TAG: &[u8] = b"dprio-published-result-v1";
+
+// The result of a study as published: the noised value and its DP parameters, a digest of the
+// transcript of the noise selection (which the servers can produce to show how the noise was
+// selected), and when it was published, in seconds since the Unix epoch, all signed by the
+// publishing server so that anyone with its verifying key can check the result is the one it
+// published.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PublishedResult {
+    result: DpResult,
+    transcript_digest: [u8; 32],
+    timestamp: u64,
+    // The bytes of the signature (see `signed_bytes`), which are kept as is so that a malformed
+    // signature is rejected by `verify` rather than by deserialization.
+    signature: Vec<u8>,
+}
+
+impl PublishedResult {
+    pub fn sign(
+        result: DpResult,
+        transcript_digest: [u8; 32],
+        timestamp: u64,
+        signing_key: &SigningKey,
+    ) -> PublishedResult {
+        let mut published_result = PublishedResult {
+            result,
+            transcript_digest,
+            timestamp,
+            signature: Vec::new(),
+        };
+        published_result.signature = signing_key
+            .sign(&published_result.signed_bytes())
+            .to_bytes()
+            .to_vec();
+        published_result
+    }
+
+    pub fn result(&self) -> &DpResult {
+        &self.result
+    }
+
+    pub fn transcript_digest(&self) -> &[u8; 32] {
+        &self.transcript_digest
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn verify(&self, verifying_key: &VerifyingKey) -> Result<(), CommitmentError> {
+        let mut signature = [0; 64];
+        if self.signature.len() != signature.len() {
+            return Err(CommitmentError::InvalidSignature);
+        }
+        signature.copy_from_slice(&self.signature);
+        verifying_key
+            .verify(&self.signed_bytes(), &Signature::from_bytes(&signature))
+            .map_err(|_| CommitmentError::InvalidSignature)
+    }
+
+    // The canonical encoding of everything but the signature: the domain tag, the fields of the
+    // result (with floats as their IEEE 754 bits), the transcript digest, and the timestamp, all
+    // fixed-length and in network byte order.
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(PUBLISHED_RESULT_DOMAIN_TAG);
+        buf.write_i64::<NetworkEndian>(self.result.value).unwrap();
+        for x in [
+            self.result.epsilon,
+            self.result.delta,
+            self.result.confidence,
+            self.result.confidence_interval.0,
+            self.result.confidence_interval.1,
+        ]
+        .iter()
+        {
+            buf.write_u64::<NetworkEndian>(x.to_bits()).unwrap();
+        }
+        buf.extend_from_slice(&self.transcript_digest);
+        buf.write_u64::<NetworkEndian>(self.timestamp).unwrap();
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Commitment;
+
+    fn published_result(signing_key: &SigningKey) -> PublishedResult {
+        let result = DpResult::new(1234, 1.0_f64, 0.1_f64, 10, 0.95_f64).unwrap();
+        PublishedResult::sign(result, [7; 32], 1_700_000_000, signing_key)
+    }
+
+    #[test]
+    fn test_published_result() {
+        let signing_key = SigningKey::from_bytes(&[1; 32]);
+        let verifying_key = signing_key.verifying_key();
+        let published_result = published_result(&signing_key);
+        assert!(published_result.verify(&verifying_key).is_ok());
+        assert_eq!(published_result.result().value, 1234);
+        assert_eq!(published_result.transcript_digest(), &[7; 32]);
+        assert_eq!(published_result.timestamp(), 1_700_000_000);
+        assert!(matches!(
+            published_result.verify(&SigningKey::from_bytes(&[2; 32]).verifying_key()),
+            Err(CommitmentError::InvalidSignature)
+        ));
+
+        // Tampering with any part of the result invalidates the signature.
+        let mut tampered = published_result.clone();
+        tampered.result.value += 1;
+        assert!(matches!(
+            tampered.verify(&verifying_key),
+            Err(CommitmentError::InvalidSignature)
+        ));
+        let mut tampered = published_result.clone();
+        tampered.result.epsilon *= 2.0_f64;
+        assert!(tampered.verify(&verifying_key).is_err());
+        let mut tampered = published_result.clone();
+        tampered.transcript_digest[0] ^= 1;
+        assert!(tampered.verify(&verifying_key).is_err());
+        let mut tampered = published_result.clone();
+        tampered.timestamp += 1;
+        assert!(tampered.verify(&verifying_key).is_err());
+        let mut tampered = published_result;
+        tampered.signature.pop();
+        assert!(tampered.verify(&verifying_key).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_published_result_serde() {
+        let signing_key = SigningKey::from_bytes(&[1; 32]);
+        let published_result = published_result(&signing_key);
+        let encoded = serde_json::to_string(&published_result).unwrap();
+        let decoded: PublishedResult = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded, published_result);
+        assert!(decoded.verify(&signing_key.verifying_key()).is_ok());
+    }
+
+    #[test]
+    fn test_signed_commitment() {
+        let registered_key = SigningKey::from_bytes(&[1; 32]);
+        let other_registered_key = SigningKey::from_bytes(&[2; 32]);
+        let unregistered_key = SigningKey::from_bytes(&[3; 32]);
+        let allowlist = vec![
+            registered_key.verifying_key(),
+            other_registered_key.verifying_key(),
+        ];
+
+        let commitment = Commitment::new(1000).unwrap();
+        let signed_commitment = commitment.commit().sign(&registered_key);
+        assert!(signed_commitment
+            .verify_signed(&registered_key.verifying_key())
+            .is_ok());
+        assert!(signed_commitment.verify_allowlisted(&allowlist).is_ok());
+        let (p, nonce) = commitment.publish();
+        assert!(signed_commitment
+            .closed_commitment()
+            .validate(p, &nonce)
+            .is_ok());
+
+        let unlisted_commitment = Commitment::new(1000)
+            .unwrap()
+            .commit()
+            .sign(&unregistered_key);
+        assert!(matches!(
+            unlisted_commitment.verify_allowlisted(&allowlist),
+            Err(CommitmentError::InvalidSignature)
+        ));
+
+        // Changing the signed commitment invalidates the signature.
+        let mut tampered_commitment = Commitment::new(1000)
+            .unwrap()
+            .commit()
+            .sign(&registered_key);
+        tampered_commitment.closed_commitment.n = 999;
+        assert!(matches!(
+            tampered_commitment.verify_allowlisted(&allowlist),
+            Err(CommitmentError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_signed_commitment_domain_separation() {
+        // A published result's signed bytes, split into a corpus size and a hash the way a
+        // commitment's were before they were tagged, don't pass as a signed commitment.
+        let registered_key = SigningKey::from_bytes(&[1; 32]);
+        let published_result = published_result(&registered_key);
+        let signed_bytes = published_result.signed_bytes();
+        let mut n = [0; 8];
+        n.copy_from_slice(&signed_bytes[..8]);
+        let mut signature = [0; 64];
+        signature.copy_from_slice(&published_result.signature);
+        let forged_commitment = SignedCommitment {
+            closed_commitment: ClosedCommitment::new(
+                u64::from_be_bytes(n),
+                signed_bytes[8..].to_vec(),
+            ),
+            signature: Signature::from_bytes(&signature),
+        };
+        assert!(matches!(
+            forged_commitment.verify_signed(&registered_key.verifying_key()),
+            Err(CommitmentError::InvalidSignature)
+        ));
+        assert!(matches!(
+            forged_commitment.verify_allowlisted(&[registered_key.verifying_key()]),
+            Err(CommitmentError::InvalidSignature)
+        ));
+
+        // Nor does a signature over a commitment's corpus size and hash without the tag, as
+        // another message of the same shape would be signed.
+        let closed_commitment = Commitment::new(1000).unwrap().commit();
+        let mut untagged_bytes = closed_commitment.n.to_be_bytes().to_vec();
+        untagged_bytes.extend_from_slice(&closed_commitment.hash);
+        let forged_commitment = SignedCommitment {
+            signature: registered_key.sign(&untagged_bytes),
+            closed_commitment,
+        };
+        assert!(matches!(
+            forged_commitment.verify_signed(&registered_key.verifying_key()),
+            Err(CommitmentError::InvalidSignature)
+        ));
+
+        // A hash of the wrong length is rejected even with a valid signature over it.
+        let short_hash = ClosedCommitment::new(1000, vec![0; 16]).sign(&registered_key);
+        assert!(matches!(
+            short_hash.verify_signed(&registered_key.verifying_key()),
+            Err(CommitmentError::InvalidSignature)
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+// Sample counts and tolerances shared by the statistical tests of the samplers.
+//
+// An estimate from n independent samples (a mean, a variance, or the frequency of an outcome) has
+// a standard error proportional to 1 / sqrt(n). Each tolerance below is TOLERANCE_SIGMAS standard
+// errors, so a correct sampler fails a given check with probability below 10^-6 (by the normal
+// approximation), regardless of the sample count. Quadrupling the sample count halves the
+// tolerances, so to tighten a test (e.g. to detect a smaller bias), raise the sample count rather
+// than lowering a tolerance.
+//
+// The tests draw their samples from a fixed seed (see `seeded_rng`), so a test that passes keeps
+// passing, and one that fails reproduces.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+// The number of samples the statistical tests draw unless they have reason to draw fewer.
+pub const SAMPLE_COUNT: usize = 20_000;
+
+// The number of standard errors an estimate may deviate from its expected value.
+pub const TOLERANCE_SIGMAS: f64 = 5.0_f64;
+
+// The tolerance for the mean of n_samples samples of a distribution with the given standard
+// deviation.
+pub fn mean_tolerance(std_dev: f64, n_samples: usize) -> f64 {
+    TOLERANCE_SIGMAS * std_dev / (n_samples as f64).sqrt()
+}
+
+// The tolerance for the (population) variance of n_samples samples of a distribution with the
+// given variance and kurtosis (the fourth standard moment, e.g. 3 for a normal distribution and 6
+// for a Laplace distribution). The variance of the estimate is (kurtosis - 1) * variance^2 / n.
+pub fn variance_tolerance(variance: f64, kurtosis: f64, n_samples: usize) -> f64 {
+    TOLERANCE_SIGMAS * variance * ((kurtosis - 1.0_f64) / n_samples as f64).sqrt()
+}
+
+// The tolerance for the frequency of an outcome with the given probability over n_samples
+// samples.
+pub fn frequency_tolerance(probability: f64, n_samples: usize) -> f64 {
+    TOLERANCE_SIGMAS * (probability * (1.0_f64 - probability) / n_samples as f64).sqrt()
+}
+
+// Returns the RNG a statistical test draws its samples from.
+pub fn seeded_rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+// Returns the mean and the (population) variance of samples.
+pub fn mean_and_variance(samples: &[i64]) -> (f64, f64) {
+    let mean = samples.iter().sum::<i64>() as f64 / samples.len() as f64;
+    let variance = samples
+        .iter()
+        .map(|sample| (*sample as f64 - mean).powi(2))
+        .sum::<f64>()
+        / samples.len() as f64;
+    (mean, variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_tolerances() {
+        // Tolerances scale with 1 / sqrt(n).
+        assert!(
+            (mean_tolerance(1.0_f64, 4 * SAMPLE_COUNT) * 2.0_f64
+                - mean_tolerance(1.0_f64, SAMPLE_COUNT))
+            .abs()
+                < 1e-12_f64
+        );
+        assert!(frequency_tolerance(0.5_f64, 100) > frequency_tolerance(0.5_f64, 10_000));
+
+        // For a known distribution (uniform on [0, 1), with mean 1/2, variance 1/12, and kurtosis
+        // 9/5), estimates from SAMPLE_COUNT samples are within the tolerances, which are tight
+        // enough to be useful.
+        let mut rng = seeded_rng(15);
+        let samples: Vec<f64> = (0..SAMPLE_COUNT).map(|_| rng.gen::<f64>()).collect();
+        let mean = samples.iter().sum::<f64>() / SAMPLE_COUNT as f64;
+        let variance =
+            samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / SAMPLE_COUNT as f64;
+        let frequency =
+            samples.iter().filter(|x| **x < 0.25_f64).count() as f64 / SAMPLE_COUNT as f64;
+        let expected_variance = 1.0_f64 / 12.0_f64;
+        let mean_tolerance = mean_tolerance(expected_variance.sqrt(), SAMPLE_COUNT);
+        let variance_tolerance = variance_tolerance(expected_variance, 1.8_f64, SAMPLE_COUNT);
+        let frequency_tolerance = frequency_tolerance(0.25_f64, SAMPLE_COUNT);
+        assert!((mean - 0.5_f64).abs() < mean_tolerance);
+        assert!((variance - expected_variance).abs() < variance_tolerance);
+        assert!((frequency - 0.25_f64).abs() < frequency_tolerance);
+        assert!(mean_tolerance < 0.011_f64);
+        assert!(variance_tolerance < 0.0027_f64);
+        assert!(frequency_tolerance < 0.016_f64);
+    }
+}